@@ -0,0 +1,198 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::IndexEntry;
+
+/// A social networking service that can be archived
+///
+/// Every service supported by sns-archive implements this so it can be run generically, either
+/// on its own or together with all other configured services.
+#[async_trait(?Send)]
+pub trait Archiver {
+    /// Human readable name of the service
+    fn name(&self) -> &'static str;
+
+    /// Name of the config file section the service is configured with
+    fn config_section(&self) -> &'static str;
+
+    /// Find posts that would be downloaded, without writing anything to disk
+    async fn discover(&self) -> Result<Vec<DiscoveredPost>>;
+
+    /// Download all new posts
+    async fn download(&self) -> Result<()>;
+
+    /// Summary of what happened during [`Archiver::download`] and [`Archiver::download_target`]
+    /// since the report was last taken
+    fn take_report(&self) -> Report;
+
+    /// Parts of the service that are polled on their own schedule in daemon mode, like single
+    /// accounts
+    ///
+    /// The default polls the whole service with the default interval.
+    fn poll_targets(&self) -> Vec<PollTarget> {
+        vec![PollTarget {
+            name: String::new(),
+            interval: None,
+        }]
+    }
+
+    /// Download all new posts of a target returned by [`Archiver::poll_targets`]
+    async fn download_target(&self, target: &str) -> Result<()> {
+        let _ = target;
+        self.download().await
+    }
+
+    /// Directories posts are downloaded to
+    ///
+    /// Temporary directories of interrupted downloads are removed from these when the daemon
+    /// stops.
+    fn download_dirs(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// Add posts downloaded before the archive index existed to the index, returns the number of
+    /// added posts
+    ///
+    /// Services that don't use the archive index keep the default, which does nothing.
+    async fn migrate_index(&self) -> Result<usize> {
+        Ok(0)
+    }
+}
+
+/// Part of a service that is polled on its own schedule, see [`Archiver::poll_targets`]
+#[derive(Clone, Debug)]
+pub struct PollTarget {
+    /// Passed to [`Archiver::download_target`], empty for the whole service
+    pub name: String,
+    /// Configured poll interval, `None` to use the default
+    pub interval: Option<Duration>,
+}
+
+/// A post found by [`Archiver::discover`]
+#[derive(Clone, Debug)]
+pub struct DiscoveredPost {
+    /// Account the post belongs to
+    pub account: String,
+    /// Service specific post ID
+    pub id: String,
+    pub slug: String,
+    /// Location the post would be downloaded to
+    pub path: PathBuf,
+}
+
+/// Posts handled by an archiver
+#[derive(Clone, Default, Debug)]
+pub struct Report {
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Every post that was handled, in the order they were handled
+    pub posts: Vec<PostRecord>,
+}
+
+impl Report {
+    /// Add a post and count it towards its status
+    pub fn add(&mut self, post: PostRecord) {
+        match post.status {
+            PostStatus::Downloaded => self.downloaded += 1,
+            PostStatus::Skipped => self.skipped += 1,
+            PostStatus::Failed => self.failed += 1,
+        }
+        self.posts.push(post);
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} downloaded, {} skipped, {} failed",
+            self.downloaded, self.skipped, self.failed
+        )
+    }
+}
+
+/// Outcome of a single post
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostStatus {
+    Downloaded,
+    Skipped,
+    Failed,
+}
+
+/// What happened to a single post during [`Archiver::download`]
+///
+/// Created with [`PostRecord::new`] and finished with [`PostRecord::downloaded`],
+/// [`PostRecord::skipped`] or [`PostRecord::failed`].
+#[derive(Clone, Debug, Serialize)]
+pub struct PostRecord {
+    pub service: String,
+    pub account: String,
+    /// Missing if the post failed before its ID was known, e.g. when fetching a page of posts
+    pub post_id: Option<String>,
+    pub slug: Option<String>,
+    pub status: PostStatus,
+    /// Total size of all files
+    pub bytes: u64,
+    pub files: Vec<PathBuf>,
+    /// Error and its causes, outermost first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub error: Vec<String>,
+}
+
+impl PostRecord {
+    pub fn new(service: &str, account: &str) -> Self {
+        Self {
+            service: service.to_owned(),
+            account: account.to_owned(),
+            post_id: None,
+            slug: None,
+            status: PostStatus::Skipped,
+            bytes: 0,
+            files: Vec::new(),
+            error: Vec::new(),
+        }
+    }
+
+    pub fn post(mut self, post_id: &str, slug: &str) -> Self {
+        self.post_id = Some(post_id.to_owned());
+        self.slug = Some(slug.to_owned());
+        self
+    }
+
+    /// The post was downloaded to the files of `entry`
+    pub fn downloaded(mut self, entry: &IndexEntry) -> Self {
+        self.status = PostStatus::Downloaded;
+        self.post_id = Some(entry.post_id.clone());
+        self.slug = Some(entry.slug.clone());
+        self.bytes = entry.files.iter().map(|f| f.size).sum();
+        self.files = entry.files.iter().map(|f| f.path.clone()).collect();
+        self
+    }
+
+    pub fn skipped(mut self) -> Self {
+        self.status = PostStatus::Skipped;
+        self
+    }
+
+    pub fn failed(mut self, error: &anyhow::Error) -> Self {
+        self.status = PostStatus::Failed;
+        self.error = error.chain().map(|e| e.to_string()).collect();
+        self
+    }
+
+    /// Finish the record with the result of downloading the post, where `None` means the post
+    /// was skipped
+    pub fn finish(self, result: Result<Option<IndexEntry>>) -> Self {
+        match result {
+            Ok(Some(entry)) => self.downloaded(&entry),
+            Ok(None) => self.skipped(),
+            Err(e) => self.failed(&e),
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use futures::stream::StreamExt;
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{IntoUrl, StatusCode, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// Number of times a download that breaks off part way is attempted before giving up
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Wait before the first retry, doubled after every failed attempt
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Download `url` to `path`
+///
+/// The file is downloaded to `path` with a `.part` suffix and only renamed to `path` once the
+/// whole file has been received, so `path` never holds a truncated file. Failed requests are
+/// already retried by the client, so only downloads that break off part way through the body are
+/// retried here, with exponential backoff, resuming from the end of the `.part` file with a
+/// `Range` request if the server supports it. A `.part` file left behind by an earlier run is
+/// resumed as well.
+pub async fn streamed_download(
+    client: &ClientWithMiddleware,
+    url: impl IntoUrl,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let url = url.into_url()?;
+    let path = path.as_ref();
+    let part_path = part_path(path);
+
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1.. {
+        match download_part(client, &url, &part_path).await {
+            Ok(()) => break,
+            Err(e) if e.is_transient() && attempt < MAX_ATTEMPTS => {
+                eprintln!(
+                    "Download of {} failed, retrying in {}s: {}",
+                    url,
+                    backoff.as_secs(),
+                    e
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    fs::rename(&part_path, path).await?;
+
+    Ok(())
+}
+
+/// Location a file is downloaded to before it is complete
+fn part_path(path: &Path) -> PathBuf {
+    let mut part: OsString = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Download to `part_path`, continuing from its current length if it already exists
+async fn download_part(
+    client: &ClientWithMiddleware,
+    url: &Url,
+    part_path: &Path,
+) -> Result<(), DownloadError> {
+    let existing = match fs::metadata(part_path).await {
+        Ok(m) => m.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut request = client.get(url.clone());
+    if existing > 0 {
+        request = request.header(RANGE, format!("bytes={}-", existing));
+    }
+    let resp = request.send().await?;
+
+    let status = resp.status();
+    let resumed =
+        status == StatusCode::PARTIAL_CONTENT && content_range_start(&resp) == Some(existing);
+    let (mut file, offset) = if resumed {
+        let file = fs::OpenOptions::new().append(true).open(part_path).await?;
+        (file, existing)
+    } else if status == StatusCode::OK {
+        // Server doesn't support ranges, download the whole file again
+        (fs::File::create(part_path).await?, 0)
+    } else {
+        // The part file is no use if the server can't continue it, start over on the next attempt
+        if status == StatusCode::PARTIAL_CONTENT || status == StatusCode::RANGE_NOT_SATISFIABLE {
+            fs::remove_file(part_path).await?;
+        }
+        return Err(DownloadError::Status(status));
+    };
+
+    let expected = resp.content_length().map(|len| len + offset);
+    let mut received = offset;
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(DownloadError::Body)?;
+        file.write_all(&chunk).await?;
+        received += chunk.len() as u64;
+    }
+    file.flush().await?;
+
+    if let Some(expected) = expected {
+        if received != expected {
+            if received > expected {
+                fs::remove_file(part_path).await?;
+            }
+            return Err(DownloadError::Length { expected, received });
+        }
+    }
+
+    Ok(())
+}
+
+/// First byte of a `206 Partial Content` response, from `Content-Range: bytes <start>-<end>/<len>`
+fn content_range_start(resp: &reqwest::Response) -> Option<u64> {
+    let range = resp.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = range.strip_prefix("bytes ")?;
+    range.split('-').next()?.parse().ok()
+}
+
+#[derive(Debug)]
+enum DownloadError {
+    Request(reqwest_middleware::Error),
+    Body(reqwest::Error),
+    Status(StatusCode),
+    Length { expected: u64, received: u64 },
+    Io(std::io::Error),
+}
+
+impl DownloadError {
+    /// Whether trying again might succeed
+    ///
+    /// Failed requests and error statuses were already retried by the client's middleware, so
+    /// only a broken off body or a part file the server couldn't continue are worth another try.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Request(_) | Self::Io(_) => false,
+            Self::Status(s) => {
+                *s == StatusCode::PARTIAL_CONTENT || *s == StatusCode::RANGE_NOT_SATISFIABLE
+            }
+            Self::Body(_) | Self::Length { .. } => true,
+        }
+    }
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Request(e) => write!(f, "{}", e),
+            Self::Body(e) => write!(f, "{}", e),
+            Self::Status(s) => write!(f, "server responded with {}", s),
+            Self::Length { expected, received } => write!(
+                f,
+                "expected {} bytes but received {} bytes",
+                expected, received
+            ),
+            Self::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Request(e) => Some(e),
+            Self::Body(e) => Some(e),
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest_middleware::Error> for DownloadError {
+    fn from(e: reqwest_middleware::Error) -> Self {
+        Self::Request(e)
+    }
+}
+
+impl From<std::io::Error> for DownloadError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+
+    const BODY: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    #[tokio::test]
+    async fn resume_part_file() {
+        let server = TestServer::start(|request| Response::ranged(request, BODY)).await;
+        let url = server.url("/file");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+
+        // Leftover from an interrupted download
+        fs::write(part_path(&path), &BODY[..10]).await.unwrap();
+
+        let client = crate::http_client(Default::default());
+        streamed_download(&client, url, &path).await.unwrap();
+        assert_eq!(fs::read(&path).await.unwrap(), BODY);
+        assert!(!part_path(&path).exists());
+    }
+}
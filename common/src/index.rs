@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use regex::Regex;
+use rusqlite::{params, Connection, OptionalExtension};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+/// On-disk index of every downloaded post, keyed by service and post ID
+///
+/// Posts are looked up by ID rather than by their slug, so posts are still recognized after the
+/// slug changes (e.g. an edited post body) or after the files are moved somewhere else.
+pub struct ArchiveIndex {
+    conn: Mutex<Connection>,
+}
+
+/// A downloaded post
+#[derive(Clone, Debug)]
+pub struct IndexEntry {
+    pub service: String,
+    pub post_id: String,
+    pub slug: String,
+    pub downloaded_at: OffsetDateTime,
+    pub files: Vec<IndexedFile>,
+}
+
+/// A file belonging to a downloaded post
+#[derive(Clone, Debug)]
+pub struct IndexedFile {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Hex encoded SHA-256 of the file contents
+    pub sha256: String,
+    pub modified_at: Option<OffsetDateTime>,
+}
+
+/// A post found on disk by [`scan_dir`]
+#[derive(Clone, Debug)]
+pub struct ScannedPost {
+    pub post_id: String,
+    pub slug: String,
+    pub paths: Vec<PathBuf>,
+}
+
+impl ArchiveIndex {
+    /// Open the index at `path`, creating it if it doesn't exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Self::init(Connection::open(path)?)
+    }
+
+    /// Open an index that only lives in memory
+    pub fn open_in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+            CREATE TABLE IF NOT EXISTS posts (
+                service TEXT NOT NULL,
+                post_id TEXT NOT NULL,
+                slug TEXT NOT NULL,
+                downloaded_at INTEGER NOT NULL,
+                PRIMARY KEY (service, post_id)
+            );
+            CREATE TABLE IF NOT EXISTS files (
+                service TEXT NOT NULL,
+                post_id TEXT NOT NULL,
+                path TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                sha256 TEXT NOT NULL,
+                modified_at INTEGER,
+                PRIMARY KEY (service, post_id, path),
+                FOREIGN KEY (service, post_id) REFERENCES posts (service, post_id)
+                    ON DELETE CASCADE
+            );",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Check whether a post has been downloaded
+    pub fn contains(&self, service: &str, post_id: &str) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let found = conn
+            .query_row(
+                "SELECT 1 FROM posts WHERE service = ?1 AND post_id = ?2",
+                params![service, post_id],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    /// Get a downloaded post
+    pub fn get(&self, service: &str, post_id: &str) -> Result<Option<IndexEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let post = conn
+            .query_row(
+                "SELECT slug, downloaded_at FROM posts WHERE service = ?1 AND post_id = ?2",
+                params![service, post_id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+            )
+            .optional()?;
+        let (slug, downloaded_at) = match post {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+
+        let mut stmt = conn.prepare(
+            "SELECT path, size, sha256, modified_at FROM files
+            WHERE service = ?1 AND post_id = ?2 ORDER BY path",
+        )?;
+        let files = stmt
+            .query_map(params![service, post_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                ))
+            })?
+            .map(|r| {
+                let (path, size, sha256, modified_at) = r?;
+                Ok(IndexedFile {
+                    path: path.into(),
+                    size: size as u64,
+                    sha256,
+                    modified_at: modified_at
+                        .map(OffsetDateTime::from_unix_timestamp)
+                        .transpose()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Some(IndexEntry {
+            service: service.to_owned(),
+            post_id: post_id.to_owned(),
+            slug,
+            downloaded_at: OffsetDateTime::from_unix_timestamp(downloaded_at)?,
+            files,
+        }))
+    }
+
+    /// Add a post to the index, replacing any previous entry of the same post
+    pub fn insert(&self, entry: &IndexEntry) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT OR REPLACE INTO posts (service, post_id, slug, downloaded_at)
+            VALUES (?1, ?2, ?3, ?4)",
+            params![
+                entry.service,
+                entry.post_id,
+                entry.slug,
+                entry.downloaded_at.unix_timestamp()
+            ],
+        )?;
+        tx.execute(
+            "DELETE FROM files WHERE service = ?1 AND post_id = ?2",
+            params![entry.service, entry.post_id],
+        )?;
+        for file in &entry.files {
+            tx.execute(
+                "INSERT INTO files (service, post_id, path, size, sha256, modified_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    entry.service,
+                    entry.post_id,
+                    file.path.to_string_lossy(),
+                    file.size as i64,
+                    file.sha256,
+                    file.modified_at.map(|t| t.unix_timestamp()),
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Add a post to the index with checksums of all files in `paths`, returns the added entry
+    ///
+    /// Directories in `paths` are walked recursively.
+    pub async fn insert_files(
+        &self,
+        service: &str,
+        post_id: &str,
+        slug: &str,
+        paths: Vec<PathBuf>,
+    ) -> Result<IndexEntry> {
+        let files = tokio::task::spawn_blocking(move || index_files(&paths)).await??;
+        let entry = IndexEntry {
+            service: service.to_owned(),
+            post_id: post_id.to_owned(),
+            slug: slug.to_owned(),
+            downloaded_at: OffsetDateTime::now_utc(),
+            files,
+        };
+        self.insert(&entry)?;
+        Ok(entry)
+    }
+
+    /// Check whether a post has been downloaded
+    ///
+    /// `existing` are files on disk that belong to the post. If the post is not in the index but
+    /// files exist, it was downloaded before the index existed and is added to the index.
+    pub async fn check(
+        &self,
+        service: &str,
+        post_id: &str,
+        slug: &str,
+        existing: Vec<PathBuf>,
+    ) -> Result<bool> {
+        if self.contains(service, post_id)? {
+            return Ok(true);
+        }
+        if existing.is_empty() {
+            return Ok(false);
+        }
+        self.insert_files(service, post_id, slug, existing).await?;
+        Ok(true)
+    }
+
+    /// Add posts found on disk that are not in the index yet, returns the number of added posts
+    pub async fn migrate(&self, service: &str, posts: Vec<ScannedPost>) -> Result<usize> {
+        let mut added = 0;
+        for post in posts {
+            if self.contains(service, &post.post_id)? {
+                continue;
+            }
+            self.insert_files(service, &post.post_id, &post.slug, post.paths)
+                .await?;
+            added += 1;
+        }
+        Ok(added)
+    }
+}
+
+/// Find posts in an archive directory
+///
+/// Entries whose name matches `re` are grouped into posts by the `id` capture group. Entries
+/// starting with a `.` (e.g. temporary directories) are ignored.
+pub fn scan_dir(dir: impl AsRef<Path>, re: &Regex) -> Result<Vec<ScannedPost>> {
+    let mut posts: Vec<ScannedPost> = Vec::new();
+    // Position of every post in `posts` by ID
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut entries: Vec<_> = match std::fs::read_dir(dir.as_ref()) {
+        Ok(r) => r.collect::<Result<_, _>>()?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(posts),
+        Err(e) => return Err(e.into()),
+    };
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let id = match re.captures(&name).and_then(|c| c.name("id")) {
+            Some(id) => id.as_str().to_owned(),
+            None => continue,
+        };
+        let path = entry.path();
+        match positions.get(&id) {
+            Some(&i) => posts[i].paths.push(path),
+            None => {
+                let slug = if path.is_dir() {
+                    name
+                } else {
+                    path.file_stem()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or(name)
+                };
+                positions.insert(id.clone(), posts.len());
+                posts.push(ScannedPost {
+                    post_id: id,
+                    slug,
+                    paths: vec![path],
+                });
+            }
+        }
+    }
+
+    Ok(posts)
+}
+
+fn index_files(paths: &[PathBuf]) -> Result<Vec<IndexedFile>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut children: Vec<_> = std::fs::read_dir(path)?
+                .map(|e| e.map(|e| e.path()))
+                .collect::<Result<_, _>>()?;
+            children.sort();
+            files.extend(index_files(&children)?);
+        } else {
+            files.push(index_file(path)?);
+        }
+    }
+    Ok(files)
+}
+
+fn index_file(path: &Path) -> Result<IndexedFile> {
+    let metadata = std::fs::metadata(path)?;
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    let sha256 = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let modified_at = metadata.modified().ok().map(OffsetDateTime::from);
+
+    Ok(IndexedFile {
+        path: path.to_owned(),
+        size: metadata.len(),
+        sha256,
+        modified_at,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn insert_and_get() {
+        let index = ArchiveIndex::open_in_memory().unwrap();
+        assert!(!index.contains("weverse", "1-106028137").unwrap());
+
+        let entry = IndexEntry {
+            service: "weverse".to_owned(),
+            post_id: "1-106028137".to_owned(),
+            slug: "20220927-1-106028137-DAMI".to_owned(),
+            downloaded_at: OffsetDateTime::from_unix_timestamp(1_664_000_000).unwrap(),
+            files: vec![IndexedFile {
+                path: "a/b.jpg".into(),
+                size: 3,
+                sha256: "abc".to_owned(),
+                modified_at: None,
+            }],
+        };
+        index.insert(&entry).unwrap();
+        assert!(index.contains("weverse", "1-106028137").unwrap());
+        assert!(!index.contains("weibo", "1-106028137").unwrap());
+
+        let got = index.get("weverse", "1-106028137").unwrap().unwrap();
+        assert_eq!(got.slug, entry.slug);
+        assert_eq!(got.downloaded_at, entry.downloaded_at);
+        assert_eq!(got.files.len(), 1);
+        assert_eq!(got.files[0].path, PathBuf::from("a/b.jpg"));
+
+        // Replacing an entry replaces its files
+        index
+            .insert(&IndexEntry {
+                files: vec![],
+                ..entry
+            })
+            .unwrap();
+        let got = index.get("weverse", "1-106028137").unwrap().unwrap();
+        assert!(got.files.is_empty());
+    }
+
+    #[test]
+    fn scan() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir.join("20230510-0-119057265-YOOHYEON-안농")).unwrap();
+        std::fs::create_dir_all(dir.join(".20230511-0-119057266-JI U.temp")).unwrap();
+        std::fs::write(dir.join("20230101_7183_user.mp4"), b"video").unwrap();
+        std::fs::write(dir.join("unrelated.txt"), b"").unwrap();
+
+        let re = Regex::new(r"^\d{8}[-_](?P<id>\d+(?:-\d+)?)[-_]").unwrap();
+        let posts = scan_dir(dir, &re).unwrap();
+
+        assert_eq!(posts.len(), 2);
+        assert_eq!(posts[0].post_id, "7183");
+        assert_eq!(posts[0].slug, "20230101_7183_user");
+        assert_eq!(posts[1].post_id, "0-119057265");
+        assert_eq!(posts[1].slug, "20230510-0-119057265-YOOHYEON-안농");
+    }
+}
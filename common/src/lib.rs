@@ -3,35 +3,31 @@ use std::path::Path;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use futures::stream::StreamExt;
-use reqwest::{Client, IntoUrl};
+use reqwest_middleware::ClientWithMiddleware;
 use time::OffsetDateTime;
-use tokio::fs;
-use tokio::io::AsyncWriteExt;
+
+mod archiver;
+mod download;
+mod index;
+mod rate_limit;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_server;
+
+pub use archiver::{Archiver, DiscoveredPost, PollTarget, PostRecord, PostStatus, Report};
+pub use download::streamed_download;
+pub use index::{scan_dir, ArchiveIndex, IndexEntry, IndexedFile, ScannedPost};
+pub use rate_limit::{http_client, RequestPolicy};
 
 #[async_trait]
 pub trait SavablePost {
-    async fn download(&self, client: &Client, directory: impl AsRef<Path> + Send) -> Result<()>;
+    async fn download(
+        &self,
+        client: &ClientWithMiddleware,
+        directory: impl AsRef<Path> + Send,
+    ) -> Result<()>;
     fn slug(&self) -> Result<String>;
 }
 
-pub async fn streamed_download(
-    client: &Client,
-    url: impl IntoUrl,
-    path: impl AsRef<Path>,
-) -> Result<()> {
-    // Download file
-    let resp = client.get(url.as_str()).send().await?.error_for_status()?;
-    let mut file = fs::File::create(&path).await?;
-    let mut stream = resp.bytes_stream();
-    while let Some(b) = stream.next().await {
-        let chunk = b?;
-        file.write_all(&chunk).await?;
-    }
-
-    Ok(())
-}
-
 pub fn set_mtime(path: impl AsRef<Path>, mtime: &OffsetDateTime) -> Result<()> {
     let mtime_unix = mtime.unix_timestamp_nanos();
     const ONE_BILLION: i128 = 1_000_000_000;
@@ -42,6 +38,30 @@ pub fn set_mtime(path: impl AsRef<Path>, mtime: &OffsetDateTime) -> Result<()> {
     Ok(())
 }
 
+/// Remove temporary directories of interrupted downloads from `dir`, returns the number of removed
+/// directories
+///
+/// Posts are downloaded to `.<slug>.temp`, or `.tmp.<slug>` for Naver blog posts, and only moved
+/// to their final location once complete.
+pub async fn remove_temp_dirs(dir: impl AsRef<Path>) -> Result<usize> {
+    let mut removed = 0;
+    let mut read_dir = match tokio::fs::read_dir(dir.as_ref()).await {
+        Ok(r) => r,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(entry) = read_dir.next_entry().await? {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let temp = (name.starts_with('.') && name.ends_with(".temp")) || name.starts_with(".tmp.");
+        if temp && entry.file_type().await?.is_dir() {
+            tokio::fs::remove_dir_all(entry.path()).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
 pub fn osstr_starts_with(osstr: &OsStr, start: &str) -> bool {
     #[cfg(unix)]
     {
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use time::format_description::well_known::Rfc2822;
+use time::OffsetDateTime;
+
+/// Rate limit and retry policy applied to every request made through [`http_client`]
+#[derive(Clone, Debug)]
+pub struct RequestPolicy {
+    /// Sustained requests per second allowed to each host, 0 disables rate limiting
+    pub requests_per_second: f64,
+    /// Requests that can be made to a host at once before the rate limit applies
+    pub burst: u32,
+    /// Retries after the first attempt, 0 disables retrying
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled after every retry
+    pub initial_backoff: Duration,
+    /// Longest wait between retries, including waits asked for with `Retry-After`
+    pub max_backoff: Duration,
+    /// Response statuses that are retried
+    pub retry_statuses: Vec<StatusCode>,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 10.0,
+            burst: 10,
+            max_retries: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            retry_statuses: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+        }
+    }
+}
+
+/// Create an HTTP client that applies `policy` to every request
+pub fn http_client(policy: RequestPolicy) -> ClientWithMiddleware {
+    ClientBuilder::new(reqwest::Client::new())
+        .with(RateLimit::new(policy))
+        .build()
+}
+
+/// Middleware limiting requests with a token bucket per host, retrying failed requests with
+/// exponential backoff and jitter
+///
+/// A `Retry-After` header on a retried response is used as the wait instead of the backoff, up to
+/// the policy's `max_backoff`.
+struct RateLimit {
+    policy: RequestPolicy,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimit {
+    fn new(policy: RequestPolicy) -> Self {
+        Self {
+            policy,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait until a request to `host` is allowed
+    async fn acquire(&self, host: &str) {
+        if self.policy.requests_per_second <= 0.0 {
+            return;
+        }
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let now = Instant::now();
+                let burst = self.policy.burst.max(1) as f64;
+                let bucket = buckets.entry(host.to_owned()).or_insert(Bucket {
+                    tokens: burst,
+                    updated: now,
+                });
+                let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens =
+                    (bucket.tokens + elapsed * self.policy.requests_per_second).min(burst);
+                bucket.updated = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.policy.requests_per_second)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Wait before retry number `retry`, between half and the full exponential backoff
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .policy
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.policy.max_backoff);
+        backoff / 2 + backoff.mul_f64(fastrand::f64() / 2.0)
+    }
+
+    /// Wait before retry number `retry`, the server's `Retry-After` if it sent one
+    ///
+    /// A single bad header must not stall a run, so the wait never exceeds `max_backoff`.
+    fn retry_wait(&self, retry: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(wait) => wait.min(self.policy.max_backoff),
+            None => self.backoff(retry),
+        }
+    }
+
+    /// Whether the result of a request should be retried, and how long the server asked to wait
+    fn should_retry(
+        &self,
+        result: &reqwest_middleware::Result<Response>,
+    ) -> Option<Option<Duration>> {
+        match result {
+            Ok(resp) if self.policy.retry_statuses.contains(&resp.status()) => {
+                Some(retry_after(resp))
+            }
+            Err(reqwest_middleware::Error::Reqwest(e))
+                if e.is_timeout() || e.is_connect() || e.is_request() =>
+            {
+                Some(None)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimit {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let host = req.url().host_str().unwrap_or_default().to_owned();
+        let mut retry = 0;
+        loop {
+            self.acquire(&host).await;
+
+            // Requests with a streaming body can't be sent again
+            let Some(attempt) = req.try_clone() else {
+                return next.run(req, extensions).await;
+            };
+            let result = next.clone().run(attempt, extensions).await;
+
+            let retry_after = match self.should_retry(&result) {
+                Some(retry_after) if retry < self.policy.max_retries => retry_after,
+                _ => return result,
+            };
+            let wait = self.retry_wait(retry, retry_after);
+            eprintln!(
+                "Request to {} failed, retrying in {:.1}s",
+                req.url(),
+                wait.as_secs_f64()
+            );
+            tokio::time::sleep(wait).await;
+            retry += 1;
+        }
+    }
+}
+
+/// Parse a `Retry-After` header, either a number of seconds or an HTTP date
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = OffsetDateTime::parse(value, &Rfc2822).ok()?;
+    let wait = date - OffsetDateTime::now_utc();
+    Some(wait.try_into().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn token_bucket() {
+        let rate_limit = RateLimit::new(RequestPolicy {
+            requests_per_second: 20.0,
+            burst: 2,
+            ..Default::default()
+        });
+
+        // Burst is allowed immediately, the next request waits for a new token
+        let start = Instant::now();
+        rate_limit.acquire("example.com").await;
+        rate_limit.acquire("example.com").await;
+        assert!(start.elapsed() < Duration::from_millis(40));
+        rate_limit.acquire("example.com").await;
+        assert!(start.elapsed() >= Duration::from_millis(45));
+
+        // Other hosts have their own bucket
+        let start = Instant::now();
+        rate_limit.acquire("example.org").await;
+        assert!(start.elapsed() < Duration::from_millis(40));
+    }
+
+    #[test]
+    fn parse_retry_after() {
+        let resp = |value: &str| {
+            Response::from(
+                http::Response::builder()
+                    .status(StatusCode::TOO_MANY_REQUESTS)
+                    .header(RETRY_AFTER, value)
+                    .body("")
+                    .unwrap(),
+            )
+        };
+        assert_eq!(retry_after(&resp("120")), Some(Duration::from_secs(120)));
+        assert_eq!(
+            retry_after(&resp("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&resp("soon")), None);
+    }
+
+    #[test]
+    fn backoff() {
+        let rate_limit = RateLimit::new(RequestPolicy::default());
+        for retry in 0..10 {
+            let full = Duration::from_secs(1 << retry).min(Duration::from_secs(60));
+            let backoff = rate_limit.backoff(retry);
+            assert!(backoff >= full / 2 && backoff <= full);
+        }
+    }
+
+    #[test]
+    fn retry_after_capped() {
+        let rate_limit = RateLimit::new(RequestPolicy::default());
+        let wait = rate_limit.retry_wait(0, Some(Duration::from_secs(30)));
+        assert_eq!(wait, Duration::from_secs(30));
+        let wait = rate_limit.retry_wait(0, Some(Duration::from_secs(6 * 60 * 60)));
+        assert_eq!(wait, Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,198 @@
+//! Local HTTP server for tests, enabled with the `test-util` feature
+//!
+//! Every request is answered by a handler function, on a free port until the test's runtime
+//! shuts down. Connections are closed after every response.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use reqwest::{StatusCode, Url};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+pub struct TestServer {
+    base: Url,
+    /// Paths of every request received
+    paths: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    /// Answer every request with `handler`
+    pub async fn start(handler: impl Fn(&Request) -> Response + Send + Sync + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        let paths = Arc::new(Mutex::new(Vec::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let server_base = base.clone();
+        let server_paths = paths.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(respond(
+                    stream,
+                    server_base.clone(),
+                    server_paths.clone(),
+                    handler.clone(),
+                ));
+            }
+        });
+        Self { base, paths }
+    }
+
+    /// Address of the server, e.g. `http://127.0.0.1:41234/`
+    pub fn base(&self) -> &Url {
+        &self.base
+    }
+
+    /// URL of `path` on the server
+    pub fn url(&self, path: &str) -> Url {
+        self.base.join(path).unwrap()
+    }
+
+    /// Number of requests received for `path`
+    pub fn requests(&self, path: &str) -> usize {
+        self.paths
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|p| *p == path)
+            .count()
+    }
+}
+
+pub struct Request {
+    /// Path and query, e.g. `/2/users?id=1`
+    pub target: String,
+    /// Header names are lowercase
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+    /// Address of the server, to answer with URLs pointing back to it
+    pub base: Url,
+}
+
+impl Request {
+    /// Path without the query
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// Target parsed as a URL on the server
+    pub fn url(&self) -> Url {
+        self.base.join(&self.target).unwrap()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: StatusCode, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::new(StatusCode::OK, body)
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "")
+    }
+
+    /// `body`, or the part of it asked for with a `Range: bytes=<start>-[<end>]` header
+    pub fn ranged(request: &Request, body: impl Into<Vec<u8>>) -> Self {
+        let body = body.into();
+        let Some((start, end)) = request.header("range").and_then(|r| range(r, body.len())) else {
+            return Self::ok(body);
+        };
+        let len = body.len();
+        Self::new(StatusCode::PARTIAL_CONTENT, &body[start..=end])
+            .header("Content-Range", &format!("bytes {}-{}/{}", start, end, len))
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_owned(), value.to_owned()));
+        self
+    }
+}
+
+/// First and last byte of a `bytes=<start>-[<end>]` range within a body of `len` bytes
+fn range(header: &str, len: usize) -> Option<(usize, usize)> {
+    let (start, end) = header.strip_prefix("bytes=")?.split_once('-')?;
+    let start: usize = start.parse().ok()?;
+    let last = len.checked_sub(1)?;
+    let end = match end {
+        "" => last,
+        end => end.parse::<usize>().ok()?.min(last),
+    };
+    (start <= end).then_some((start, end))
+}
+
+async fn respond(
+    stream: TcpStream,
+    base: Url,
+    paths: Arc<Mutex<Vec<String>>>,
+    handler: Arc<Handler>,
+) {
+    let mut stream = BufReader::new(stream);
+    let Some(request) = read_request(&mut stream, base).await else {
+        return;
+    };
+    paths.lock().unwrap().push(request.path().to_owned());
+    let response = handler(&request);
+
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    let stream = stream.get_mut();
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&response.body).await;
+    let _ = stream.shutdown().await;
+}
+
+async fn read_request(stream: &mut BufReader<TcpStream>, base: Url) -> Option<Request> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await.ok()?;
+    let target = line.split_whitespace().nth(1)?.to_owned();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        stream.read_line(&mut line).await.ok()?;
+        let Some((name, value)) = line.trim_end().split_once(':') else {
+            break;
+        };
+        headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|l| l.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await.ok()?;
+
+    Some(Request {
+        target,
+        headers,
+        body,
+        base,
+    })
+}
@@ -4,6 +4,7 @@ use anyhow::Result;
 use clap::{Parser, Subcommand, ValueEnum, ValueHint};
 use naver_blog::{ImageType, NaverBlogClient, ProgressBar};
 use regex::RegexBuilder;
+use sns_archive_common::{http_client, RequestPolicy};
 
 #[derive(Parser, Debug)]
 pub struct Args {
@@ -73,7 +74,7 @@ pub enum Commands {
 
 impl Args {
     pub async fn download<PB: ProgressBar>(&self) -> Result<()> {
-        let reqwest_client = reqwest::Client::new();
+        let reqwest_client = http_client(RequestPolicy::default());
         let client = NaverBlogClient::new(&reqwest_client);
 
         match &self.command {
@@ -4,13 +4,14 @@ use std::time::Duration;
 
 use futures::StreamExt;
 use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
 use tokio::io::AsyncWriteExt;
 
 use crate::util::{parse_date, slug, NaverBlogMetadata};
 use crate::{ImageType, NaverBlogClient, NaverBlogError, ProgressBar};
 
 pub(crate) enum NaverBlogDownloadStatus {
-    Downloaded,
+    Downloaded { slug: String },
     Exists,
 }
 
@@ -22,38 +23,8 @@ impl NaverBlogClient<'_> {
         id: u64,
         image_type: ImageType,
     ) -> Result<NaverBlogDownloadStatus, NaverBlogError> {
-        // Get the page HTML
-        let mut url = Url::parse("https://blog.naver.com/PostView.naver").unwrap();
-        url.query_pairs_mut()
-            .append_pair("blogId", member)
-            .append_pair("logNo", &id.to_string());
-
-        let err_func = |e| -> _ {
-            NaverBlogError::FetchBlogPost {
-                url: url.to_string(),
-                error: e,
-            }
-        };
-        let html = self
-            .client
-            .get(url.clone())
-            .send()
-            .await
-            .map_err(err_func)?
-            .error_for_status()
-            .map_err(err_func)?
-            .text()
-            .await
-            .map_err(err_func)?;
-
-        // Extract images from page source
-        let document = scraper::Html::parse_document(&html);
-        let metadata = extract_post_metadata(&document, member.to_owned(), id).map_err(|msg| {
-            NaverBlogError::ParseBlogPost {
-                url: url.to_string(),
-                msg,
-            }
-        })?;
+        let url = post_view_url(member, id);
+        let (document, metadata) = self.fetch_post(member, id).await?;
         let slug = slug(&metadata);
         let blog_post_url = format!("https://blog.naver.com/{member}/{id}");
 
@@ -94,7 +65,7 @@ impl NaverBlogClient<'_> {
         // On windows, sometimes moving the directory fails with OS err 5 for some reason,
         // retry a few times before giving up
         const ATTEMPTS: usize = 10;
-        let final_dir_path = download_path.as_ref().join(slug);
+        let final_dir_path = download_path.as_ref().join(&slug);
         for i in 0.. {
             let rename_res = tokio::fs::rename(&tmp_dir_path, &final_dir_path)
                 .await
@@ -121,10 +92,61 @@ impl NaverBlogClient<'_> {
 
         pb.destroy();
 
-        Ok(NaverBlogDownloadStatus::Downloaded)
+        Ok(NaverBlogDownloadStatus::Downloaded { slug })
+    }
+
+    /// Get the slug a blog post would be downloaded as
+    pub(crate) async fn post_slug(&self, member: &str, id: u64) -> Result<String, NaverBlogError> {
+        let (_, metadata) = self.fetch_post(member, id).await?;
+        Ok(slug(&metadata))
+    }
+
+    /// Fetch and parse the page HTML of a blog post
+    async fn fetch_post(
+        &self,
+        member: &str,
+        id: u64,
+    ) -> Result<(scraper::Html, NaverBlogMetadata), NaverBlogError> {
+        let url = post_view_url(member, id);
+        let err_func = |e: reqwest_middleware::Error| -> _ {
+            NaverBlogError::FetchBlogPost {
+                url: url.to_string(),
+                error: e,
+            }
+        };
+        let html = self
+            .client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(err_func)?
+            .error_for_status()
+            .map_err(|e| err_func(e.into()))?
+            .text()
+            .await
+            .map_err(|e| err_func(e.into()))?;
+
+        // Extract metadata from page source
+        let document = scraper::Html::parse_document(&html);
+        let metadata = extract_post_metadata(&document, member.to_owned(), id).map_err(|msg| {
+            NaverBlogError::ParseBlogPost {
+                url: url.to_string(),
+                msg,
+            }
+        })?;
+
+        Ok((document, metadata))
     }
 }
 
+fn post_view_url(member: &str, id: u64) -> Url {
+    let mut url = Url::parse("https://blog.naver.com/PostView.naver").unwrap();
+    url.query_pairs_mut()
+        .append_pair("blogId", member)
+        .append_pair("logNo", &id.to_string());
+    url
+}
+
 fn extract_post_metadata(
     document: &scraper::Html,
     member: String,
@@ -192,7 +214,7 @@ struct DownloadImageError {
 }
 
 async fn download_images<PB: ProgressBar>(
-    client: &reqwest::Client,
+    client: &ClientWithMiddleware,
     download_dir: impl AsRef<Path>,
     urls: &[String],
     slug: &str,
@@ -200,7 +222,7 @@ async fn download_images<PB: ProgressBar>(
 ) -> Result<(), DownloadImageError> {
     // Helper function to download a single image
     async fn download_one_image<PB: ProgressBar>(
-        client: &reqwest::Client,
+        client: &ClientWithMiddleware,
         base_dir: impl AsRef<Path>,
         index: usize,
         slug: &str,
@@ -208,7 +230,7 @@ async fn download_images<PB: ProgressBar>(
         pb: &PB,
     ) -> Result<(), DownloadImageError> {
         // Download to a temp file without extension first
-        let err_func = |e: reqwest::Error| -> _ {
+        let err_func = |e: reqwest_middleware::Error| -> _ {
             DownloadImageError {
                 image_url: url.clone(),
                 msg: e.to_string(),
@@ -221,10 +243,10 @@ async fn download_images<PB: ProgressBar>(
             .await
             .map_err(err_func)?
             .error_for_status()
-            .map_err(err_func)?
+            .map_err(|e| err_func(e.into()))?
             .bytes()
             .await
-            .map_err(err_func)?;
+            .map_err(|e| err_func(e.into()))?;
 
         // Guess the extension from the file contents
         let ext = infer::get(&bytes)
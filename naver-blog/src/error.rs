@@ -8,7 +8,7 @@ pub enum NaverBlogError {
     #[error("unable to fetch blog post: {url}, {error:#?}")]
     FetchBlogPost {
         url: String,
-        error: reqwest::Error,
+        error: reqwest_middleware::Error,
     },
 
     #[error("unable to parse blog post: {url}, {msg}")]
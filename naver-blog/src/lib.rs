@@ -9,3 +9,4 @@ pub use error::NaverBlogError;
 pub use naver_blog_client::NaverBlogClient;
 pub use progress_bar::ProgressBar;
 pub use naver_blog_client::ImageType;
+pub use naver_blog_client::NaverBlogPost;
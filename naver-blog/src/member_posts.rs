@@ -53,7 +53,7 @@ impl NaverBlogClient<'_> {
     async fn get_posts(&self, request: GetPostsRequest) -> Result<GetPostResponse, NaverBlogError> {
         const COUNT_PER_PAGE: usize = 30;
 
-        let err_func = |e: reqwest::Error| NaverBlogError::FetchBlogPostList {
+        let err_func = |e: reqwest_middleware::Error| NaverBlogError::FetchBlogPostList {
             member: request.member.clone(),
             page: request.page,
             msg: e.to_string(),
@@ -71,10 +71,10 @@ impl NaverBlogClient<'_> {
             .await
             .map_err(err_func)?
             .error_for_status()
-            .map_err(err_func)?
+            .map_err(|e| err_func(e.into()))?
             .text()
             .await
-            .map_err(err_func)?;
+            .map_err(|e| err_func(e.into()))?;
 
         // Remove bad single quote escapes
         static QUOTE_RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\'").unwrap());
@@ -1,24 +1,50 @@
 use std::path::Path;
 
-use futures::TryStreamExt;
+use futures::{future, Stream, StreamExt, TryStreamExt};
 use page_turner::{PageTurner, PagesStream};
 use regex::Regex;
-use reqwest::{Client, Url};
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
 
 use crate::download_post::NaverBlogDownloadStatus;
-use crate::member_posts::GetPostsRequest;
+use crate::member_posts::{GetPostsRequest, NaverBlogPostStub};
 use crate::progress_bar::ProgressBar;
 use crate::NaverBlogError;
 
+type SkipPost<'client> = Box<dyn Fn(&str, u64) -> bool + 'client>;
+
 pub struct NaverBlogClient<'client> {
-    pub(crate) client: &'client Client,
+    pub(crate) client: &'client ClientWithMiddleware,
+    skip_post: Option<SkipPost<'client>>,
 }
 
 impl<'client> NaverBlogClient<'client> {
-    pub fn new(client: &'client Client) -> Self {
-        Self { client }
+    pub fn new(client: &'client ClientWithMiddleware) -> Self {
+        Self {
+            client,
+            skip_post: None,
+        }
+    }
+
+    /// Treat posts as already downloaded when `skip` returns true for the member and post ID
+    ///
+    /// This is checked before the post is fetched, in addition to looking for the post in the
+    /// download directory.
+    pub fn skip_post(mut self, skip: impl Fn(&str, u64) -> bool + 'client) -> Self {
+        self.skip_post = Some(Box::new(skip));
+        self
+    }
+
+    fn is_skipped(&self, member: &str, id: u64) -> bool {
+        self.skip_post
+            .as_ref()
+            .map(|skip| skip(member, id))
+            .unwrap_or(false)
     }
 
+    /// Download posts of a member, stopping at the first post that has already been downloaded
+    ///
+    /// Returns the downloaded posts
     #[allow(clippy::too_many_arguments)]
     pub async fn download_member<PB: ProgressBar>(
         &self,
@@ -29,31 +55,13 @@ impl<'client> NaverBlogClient<'client> {
         image_type: ImageType,
         until_post: Option<u64>,
         since_post: Option<u64>,
-    ) -> Result<(), NaverBlogError> {
-        let stream = self.pages(GetPostsRequest::new(member.to_owned())).items();
+    ) -> Result<Vec<NaverBlogPost>, NaverBlogError> {
+        let stream = self.member_posts(member, filter, limit, until_post, since_post);
         futures::pin_mut!(stream);
-        let mut idx = 0;
+        let mut downloaded = Vec::new();
         while let Some(stub) = stream.try_next().await? {
-            // Only download posts in specified range
-            if until_post.map(|id| stub.post_id > id).unwrap_or(false)
-                || since_post.map(|id| stub.post_id < id).unwrap_or(false)
-            {
-                continue;
-            }
-
-            // Break if limit is enabled and reached
-            if let Some(limit) = limit {
-                if idx >= limit {
-                    break;
-                }
-            }
-            idx += 1;
-
-            // Check title filter
-            if let Some(re) = filter {
-                if !re.is_match(&stub.title) {
-                    continue;
-                }
+            if self.is_skipped(member, stub.post_id) {
+                break;
             }
 
             // Download the post
@@ -61,11 +69,75 @@ impl<'client> NaverBlogClient<'client> {
                 .download_post::<PB>(download_path.as_ref(), member, stub.post_id, image_type)
                 .await?;
             match download_result {
-                NaverBlogDownloadStatus::Downloaded => {}
+                NaverBlogDownloadStatus::Downloaded { slug } => downloaded.push(NaverBlogPost {
+                    post_id: stub.post_id,
+                    title: stub.title,
+                    slug,
+                }),
                 NaverBlogDownloadStatus::Exists => break,
             }
         }
-        Ok(())
+        Ok(downloaded)
+    }
+
+    /// Find posts of a member that [`NaverBlogClient::download_member`] would download, without
+    /// downloading anything
+    pub async fn discover_member(
+        &self,
+        member: &str,
+        download_path: impl AsRef<Path>,
+        filter: Option<&Regex>,
+        limit: Option<usize>,
+        until_post: Option<u64>,
+        since_post: Option<u64>,
+    ) -> Result<Vec<NaverBlogPost>, NaverBlogError> {
+        let stream = self.member_posts(member, filter, limit, until_post, since_post);
+        futures::pin_mut!(stream);
+        let mut posts = Vec::new();
+        while let Some(stub) = stream.try_next().await? {
+            if self.is_skipped(member, stub.post_id) {
+                break;
+            }
+            let slug = self.post_slug(member, stub.post_id).await?;
+            if tokio::fs::metadata(download_path.as_ref().join(&slug))
+                .await
+                .is_ok()
+            {
+                break;
+            }
+            posts.push(NaverBlogPost {
+                post_id: stub.post_id,
+                title: stub.title,
+                slug,
+            });
+        }
+        Ok(posts)
+    }
+
+    /// Stream of a member's posts in the specified range, filtered by title
+    fn member_posts<'a>(
+        &'a self,
+        member: &str,
+        filter: Option<&'a Regex>,
+        limit: Option<usize>,
+        until_post: Option<u64>,
+        since_post: Option<u64>,
+    ) -> impl Stream<Item = Result<NaverBlogPostStub, NaverBlogError>> + 'a {
+        self.pages(GetPostsRequest::new(member.to_owned()))
+            .items()
+            // Only download posts in specified range
+            .try_filter(move |stub| {
+                future::ready(
+                    !(until_post.map(|id| stub.post_id > id).unwrap_or(false)
+                        || since_post.map(|id| stub.post_id < id).unwrap_or(false)),
+                )
+            })
+            // Stop if limit is enabled and reached, filtered posts count towards the limit
+            .take(limit.unwrap_or(usize::MAX))
+            // Check title filter
+            .try_filter(move |stub| {
+                future::ready(filter.map(|re| re.is_match(&stub.title)).unwrap_or(true))
+            })
     }
 
     pub async fn download_url<PB: ProgressBar>(
@@ -83,6 +155,15 @@ impl<'client> NaverBlogClient<'client> {
     }
 }
 
+/// A blog post found by [`NaverBlogClient::discover_member`]
+#[derive(Clone, Debug)]
+pub struct NaverBlogPost {
+    pub post_id: u64,
+    pub title: String,
+    /// Name of the directory the post would be downloaded to
+    pub slug: String,
+}
+
 #[derive(Clone, Copy, Default, Debug)]
 pub enum ImageType {
     #[default]
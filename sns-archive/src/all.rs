@@ -0,0 +1,93 @@
+use std::fmt::Display;
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future;
+use sns_archive_common::ArchiveIndex;
+
+use crate::config::Config;
+use crate::report::{ReportFile, ServiceResult};
+
+/// Run every service that has a section in the config file
+///
+/// A failing service does not stop the others, all results are printed as a summary table after
+/// every service has finished.
+pub async fn download(
+    conf: Config,
+    index: Arc<ArchiveIndex>,
+    report: Option<&ReportFile>,
+) -> Result<()> {
+    let concurrent = conf.all.as_ref().map(|a| a.concurrent).unwrap_or_default();
+    let archivers = crate::archivers(conf, index);
+
+    let results: Vec<_> = if concurrent {
+        future::join_all(archivers.iter().map(|a| ServiceResult::run(a.as_ref()))).await
+    } else {
+        let mut results = Vec::with_capacity(archivers.len());
+        for archiver in &archivers {
+            println!("Running {}", archiver.name());
+            results.push(ServiceResult::run(archiver.as_ref()).await);
+        }
+        results
+    };
+
+    print_summary(&results);
+    if let Some(report) = report {
+        report.write(&results)?;
+    }
+
+    let failed = results.iter().filter(|r| r.result.is_err()).count();
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(Error {
+            failed,
+            total: results.len(),
+        }
+        .into())
+    }
+}
+
+fn print_summary(results: &[ServiceResult]) {
+    let name_width = results
+        .iter()
+        .map(|r| r.name.len())
+        .chain(std::iter::once("Service".len()))
+        .max()
+        .unwrap_or_default();
+
+    println!();
+    println!(
+        "{:name_width$}  {:>8}  {:>10}  {:>7}  {:>6}  Status",
+        "Service", "Time", "Downloaded", "Skipped", "Failed"
+    );
+    for r in results {
+        let status = match &r.result {
+            Ok(_) => "ok".to_owned(),
+            // Only show the first line, some errors span multiple lines
+            Err(e) => format!(
+                "failed: {}",
+                format!("{:#}", e).lines().next().unwrap_or_default()
+            ),
+        };
+        let elapsed = format!("{}s", r.elapsed.as_secs());
+        println!(
+            "{:name_width$}  {:>8}  {:>10}  {:>7}  {:>6}  {}",
+            r.name, elapsed, r.report.downloaded, r.report.skipped, r.report.failed, status
+        );
+    }
+}
+
+#[derive(Debug)]
+struct Error {
+    failed: usize,
+    total: usize,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} of {} services failed", self.failed, self.total)
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,10 @@
+use serde::Deserialize;
+
+/// Run every configured service
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AllConfig {
+    /// Run all services at the same time instead of one after another
+    #[serde(default)]
+    pub concurrent: bool,
+}
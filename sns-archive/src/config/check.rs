@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use regex::RegexBuilder;
+use serde::Deserialize;
+use toml::de::{DeTable, DeValue, Deserializer};
+use toml::Spanned;
+
+use super::Config;
+
+/// Problem found in the config file, with its location if it belongs to a key
+#[derive(Debug)]
+pub struct Problem {
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+/// 1-based line and column in the config file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.location {
+            Some(Location { line, column }) => write!(f, "{}:{}: {}", line, column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Check a config file for problems that would only show up while downloading, or not at all
+///
+/// Unknown keys, invalid values and regexes, download paths that can't be written to or are shared
+/// between accounts and missing external programs are reported. An error is only returned if the
+/// file can't be read.
+pub fn check(path: impl AsRef<Path>) -> Result<Vec<Problem>> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(check_str(&text))
+}
+
+fn check_str(text: &str) -> Vec<Problem> {
+    let mut checker = Checker {
+        text,
+        problems: Vec::new(),
+    };
+    let mut table = match DeTable::parse(text) {
+        Ok(table) => table,
+        Err(e) => {
+            checker.add(e.span(), e.message());
+            return checker.problems;
+        }
+    };
+
+    // Deserialization stops at the first error, drop the offending key and retry to find the rest
+    let conf = loop {
+        match Config::deserialize(Deserializer::from(table.clone())) {
+            Ok(conf) => break Some(conf),
+            Err(e) => {
+                checker.add(e.span(), e.message());
+                match e.span() {
+                    Some(span) if remove_key(table.get_mut(), &span) => {}
+                    _ => break None,
+                }
+            }
+        }
+    };
+
+    if let Some(conf) = conf {
+        checker.check_regexes(&conf, table.get_ref());
+        checker.check_date_ranges(&conf, table.get_ref());
+        checker.check_download_paths(&conf, table.get_ref());
+        checker.check_programs(&conf, table.get_ref());
+    }
+
+    // Keys are deserialized in alphabetical order, problems without location go last
+    let mut problems = checker.problems;
+    problems.sort_by_key(|p| (p.location.is_none(), p.location));
+    problems
+}
+
+struct Checker<'a> {
+    text: &'a str,
+    problems: Vec<Problem>,
+}
+
+impl Checker<'_> {
+    fn add(&mut self, span: Option<Range<usize>>, message: impl Into<String>) {
+        self.problems.push(Problem {
+            location: span.map(|s| self.location(s.start)),
+            message: message.into(),
+        });
+    }
+
+    fn location(&self, offset: usize) -> Location {
+        let before = &self.text[..offset.min(self.text.len())];
+        Location {
+            line: before.matches('\n').count() + 1,
+            column: before
+                .rsplit('\n')
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .count()
+                + 1,
+        }
+    }
+
+    /// Filters are passed to yt-dlp, so they only show up as failed downloads
+    fn check_regexes(&mut self, conf: &Config, table: &DeTable) {
+        let Some(youtube) = &conf.youtube else {
+            return;
+        };
+        let mut filters = vec![(
+            youtube.filter.as_str(),
+            lookup(table, &["youtube", "filter"]),
+        )];
+        for (i, channel) in youtube.channels.iter().enumerate() {
+            if let Some(filter) = &channel.custom_filter {
+                let path = ["youtube", "channels", &i.to_string(), "custom_filter"];
+                filters.push((filter, lookup(table, &path)));
+            }
+        }
+
+        for (filter, span) in filters {
+            if let Err(e) = RegexBuilder::new(filter).case_insensitive(true).build() {
+                self.add(span, format!("invalid regex: {}", e));
+            }
+        }
+    }
+
+    /// An empty range downloads nothing without any error
+    fn check_date_ranges(&mut self, conf: &Config, table: &DeTable) {
+        let Some(weverse) = &conf.weverse else {
+            return;
+        };
+        for (artist, artist_conf) in &weverse.artists {
+            if let (Some(since), Some(until)) = (artist_conf.since, artist_conf.until) {
+                if since >= until {
+                    let span = lookup(table, &["weverse", "artists", artist, "until"]);
+                    self.add(span, "until is not after since");
+                }
+            }
+        }
+    }
+
+    fn check_download_paths(&mut self, conf: &Config, table: &DeTable) {
+        let mut paths: Vec<(&Path, Vec<String>)> = Vec::new();
+        if let Some(weverse) = &conf.weverse {
+            for (artist, artist_conf) in &weverse.artists {
+                for (key, path) in [
+                    ("artist_download_path", &artist_conf.artist_download_path),
+                    ("moments_download_path", &artist_conf.moments_download_path),
+                    ("lives_download_path", &artist_conf.lives_download_path),
+                    ("notices_download_path", &artist_conf.notices_download_path),
+                    ("letters_download_path", &artist_conf.letters_download_path),
+                    (
+                        "artist_comments_download_path",
+                        &artist_conf.artist_comments_download_path,
+                    ),
+                    ("media_download_path", &artist_conf.media_download_path),
+                ] {
+                    if let Some(path) = path {
+                        paths.push((path, keys(["weverse", "artists", artist, key])));
+                    }
+                }
+            }
+        }
+        if let Some(twitter) = &conf.twitter {
+            paths.push((&twitter.download_path, keys(["twitter", "download_path"])));
+        }
+        if let Some(youtube) = &conf.youtube {
+            paths.push((&youtube.download_path, keys(["youtube", "download_path"])));
+        }
+        if let Some(naver_post) = &conf.naver_post {
+            for (i, member) in naver_post.members.iter().enumerate() {
+                let path = keys(["naver_post", "members", &i.to_string(), "download_path"]);
+                paths.push((&member.download_path, path));
+            }
+        }
+        if let Some(xiaohongshu) = &conf.xiaohongshu {
+            paths.push((
+                &xiaohongshu.download_path,
+                keys(["xiaohongshu", "download_path"]),
+            ));
+        }
+        if let Some(weibo) = &conf.weibo {
+            for (i, user) in weibo.users.iter().enumerate() {
+                let path = keys(["weibo", "users", &i.to_string(), "download_path"]);
+                paths.push((&user.download_path, path));
+            }
+        }
+        if let Some(tiktok) = &conf.tiktok {
+            for (user, user_conf) in &tiktok.users {
+                paths.push((
+                    &user_conf.download_path,
+                    keys(["tiktok", "users", user, "download_path"]),
+                ));
+            }
+        }
+
+        // Report in file order, the maps of artists and users are unordered
+        let mut paths: Vec<_> = paths
+            .into_iter()
+            .map(|(path, keys)| {
+                let keys: Vec<_> = keys.iter().map(String::as_str).collect();
+                (path, keys.join("."), lookup(table, &keys))
+            })
+            .collect();
+        paths.sort_by_key(|(_, _, span)| span.as_ref().map(|s| s.start));
+
+        let mut seen: HashMap<PathBuf, String> = HashMap::new();
+        for (path, key, span) in paths {
+            // Trailing slashes and `.` components don't make a different directory
+            let normalized: PathBuf = path.components().collect();
+            if let Some(first) = seen.get(&normalized) {
+                let message = format!("download path {} is also used by {}", path.display(), first);
+                self.add(span.clone(), message);
+            } else {
+                seen.insert(normalized, key);
+            }
+
+            if let Err(message) = check_writable(path) {
+                self.add(span, message);
+            }
+        }
+    }
+
+    fn check_programs(&mut self, conf: &Config, table: &DeTable) {
+        let needed = [
+            ("yt-dlp", "youtube", conf.youtube.is_some()),
+            ("ffmpeg", "tiktok", conf.tiktok.is_some()),
+            ("geckodriver", "xiaohongshu", conf.xiaohongshu.is_some()),
+        ];
+        for (program, section, configured) in needed {
+            if configured && !in_path(program) {
+                let message = format!(
+                    "{} is needed by [{}] but was not found in PATH",
+                    program, section
+                );
+                self.add(lookup(table, &[section]), message);
+            }
+        }
+    }
+}
+
+fn keys<'a>(keys: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    keys.into_iter().map(str::to_owned).collect()
+}
+
+/// Span of the value at `path`, numeric keys index into arrays
+fn lookup(table: &DeTable, path: &[&str]) -> Option<Range<usize>> {
+    let (first, rest) = path.split_first()?;
+    let mut value = table.get(*first)?;
+    for key in rest {
+        value = match key.parse::<usize>() {
+            Ok(i) if value.get_ref().as_array().is_some() => value.get_ref().get(i)?,
+            _ => value.get_ref().get(*key)?,
+        };
+    }
+    Some(value.span())
+}
+
+/// Remove the key whose key or value is at `span`, returns whether a key was found
+fn remove_key(table: &mut DeTable, span: &Range<usize>) -> bool {
+    let key = table
+        .iter()
+        .find(|(key, value)| key.span() == *span || value.span() == *span)
+        .map(|(key, _)| key.clone());
+    if let Some(key) = key {
+        table.remove(&key);
+        return true;
+    }
+    table
+        .iter_mut()
+        .any(|(_, value)| remove_from_value(value, span))
+}
+
+fn remove_from_value(value: &mut Spanned<DeValue>, span: &Range<usize>) -> bool {
+    match value.get_mut() {
+        DeValue::Table(table) => remove_key(table, span),
+        DeValue::Array(array) => array.iter_mut().any(|v| remove_from_value(v, span)),
+        _ => false,
+    }
+}
+
+/// Check that a download directory exists or can be created, and that files can be written to it
+fn check_writable(path: &Path) -> Result<(), String> {
+    if path.exists() && !path.is_dir() {
+        return Err(format!(
+            "download path {} is not a directory",
+            path.display()
+        ));
+    }
+    // Relative paths end in an empty ancestor, which is the working directory
+    let existing = path
+        .ancestors()
+        .map(|p| {
+            if p.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                p
+            }
+        })
+        .find(|p| p.exists())
+        .unwrap_or(Path::new("."));
+    if !existing.is_dir() {
+        return Err(format!(
+            "download path {} can't be created, {} is not a directory",
+            path.display(),
+            existing.display()
+        ));
+    }
+    tempfile::tempfile_in(existing).map_err(|e| {
+        format!(
+            "download path {} is not writable, {}: {}",
+            path.display(),
+            existing.display(),
+            e
+        )
+    })?;
+    Ok(())
+}
+
+fn in_path(program: &str) -> bool {
+    let filename = format!("{}{}", program, std::env::consts::EXE_SUFFIX);
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(&filename).is_file()))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn problems(text: &str) -> Vec<(Option<Location>, String)> {
+        check_str(text)
+            .into_iter()
+            .map(|p| (p.location, p.message))
+            .collect()
+    }
+
+    fn at(line: usize, column: usize) -> Option<Location> {
+        Some(Location { line, column })
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = format!(
+            r#"
+[weverse]
+email = "a@example.com"
+max_conections = 10
+
+[weverse.artists.dreamcatcher]
+artist_downlod_path = "{0}/artist"
+moments_download_path = "{0}/moments"
+"#,
+            dir.path().display()
+        );
+        let problems = problems(&text);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert_eq!(problems[0].0, at(4, 1));
+        assert!(problems[0].1.contains("max_conections"));
+        assert_eq!(problems[1].0, at(7, 1));
+        assert!(problems[1].1.contains("artist_downlod_path"));
+    }
+
+    #[test]
+    fn shared_download_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = format!(
+            r#"
+[weibo]
+users = [
+  {{ user = 1, download_path = "{0}/weibo" }},
+  {{ user = 2, download_path = "{0}/weibo/" }},
+]
+"#,
+            dir.path().display()
+        );
+        let problems = problems(&text);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert_eq!(problems[0].0, at(5, 31));
+        assert!(problems[0]
+            .1
+            .ends_with("is also used by weibo.users.0.download_path"));
+    }
+
+    #[test]
+    fn download_path_is_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let text = format!(
+            "[xiaohongshu]\ndownload_path = \"{}\"\n",
+            file.path().display()
+        );
+        let problems = problems(&text);
+        assert!(problems
+            .iter()
+            .any(|(location, message)| *location == at(2, 17)
+                && message.ends_with("is not a directory")));
+    }
+
+    #[test]
+    fn invalid_regex() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = format!(
+            r#"
+[youtube]
+download_path = "{}"
+filter = "(dreamcatcher"
+channels = []
+"#,
+            dir.path().display()
+        );
+        // yt-dlp may be missing too
+        let problems = problems(&text);
+        let (location, _) = problems
+            .iter()
+            .find(|(_, message)| message.starts_with("invalid regex"))
+            .unwrap();
+        assert_eq!(*location, at(4, 10));
+    }
+
+    #[test]
+    fn empty_date_range() {
+        let text = r#"
+[weverse]
+email = "a@example.com"
+
+[weverse.artists.dreamcatcher]
+since = 2023-05-17
+until = "2023-05-16"
+"#;
+        assert_eq!(
+            problems(text),
+            vec![(at(7, 9), "until is not after since".to_owned())]
+        );
+    }
+
+    #[test]
+    fn invalid_toml() {
+        let problems = problems("[weibo\nusers = []\n");
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].0, at(1, 7));
+    }
+}
@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Poll every configured service with `sns-archive daemon`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Minutes between polls of services and accounts without their own interval
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_mins: u64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_mins: default_poll_interval(),
+        }
+    }
+}
+
+fn default_poll_interval() -> u64 {
+    10
+}
+
+/// First configured poll interval, from the most to the least specific setting
+pub fn poll_interval(minutes: impl IntoIterator<Item = Option<u64>>) -> Option<Duration> {
+    minutes
+        .into_iter()
+        .flatten()
+        .next()
+        .map(|m| Duration::from_secs(m * 60))
+}
@@ -0,0 +1,13 @@
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+/// Archive index of downloaded posts
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct IndexConfig {
+    /// Location of the index database
+    #[serde(deserialize_with = "super::deserialize_option_path")]
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
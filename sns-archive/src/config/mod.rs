@@ -1,12 +1,15 @@
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 #[cfg(target_family = "unix")]
 use home_dir::HomeDirExt;
 use regex::{Regex, RegexBuilder};
 use serde::de::Error;
 use serde::{Deserialize, Deserializer};
 
+use self::all::AllConfig;
+use self::daemon::DaemonConfig;
+use self::index::IndexConfig;
 use self::naver_post::NaverPostConfig;
 use self::tiktok::TikTokConfig;
 use self::twitter::TwitterConfig;
@@ -15,7 +18,12 @@ use self::weverse::WeverseConfig;
 use self::xiaohongshu::XiaoHongShuConfig;
 use self::youtube::YoutubeConfig;
 
+pub mod all;
+pub mod check;
+pub mod daemon;
+pub mod index;
 pub mod naver_post;
+pub mod rate_limit;
 pub mod tiktok;
 pub mod twitter;
 pub mod weibo;
@@ -24,7 +32,11 @@ pub mod xiaohongshu;
 pub mod youtube;
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
+    pub all: Option<AllConfig>,
+    pub daemon: Option<DaemonConfig>,
+    pub index: Option<IndexConfig>,
     pub weverse: Option<WeverseConfig>,
     pub twitter: Option<TwitterConfig>,
     pub youtube: Option<YoutubeConfig>,
@@ -39,6 +51,16 @@ impl Config {
         let conf_contents = std::fs::read_to_string(path.as_ref())?;
         Ok(toml::from_str(&conf_contents)?)
     }
+
+    /// Location of the archive index, in the user data directory unless configured otherwise
+    pub fn index_path(&self) -> Result<PathBuf> {
+        if let Some(path) = self.index.as_ref().and_then(|i| i.path.clone()) {
+            return Ok(path);
+        }
+        directories::BaseDirs::new()
+            .map(|d| d.data_dir().join("sns-archive/index.sqlite3"))
+            .ok_or_else(|| anyhow!("unable to find user data directory"))
+    }
 }
 
 fn deserialize_path<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
@@ -73,7 +95,6 @@ where
     Ok(path.as_ref().into())
 }
 
-
 fn deserialize_regex_option<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
 where
     D: Deserializer<'de>,
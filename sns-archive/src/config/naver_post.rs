@@ -3,12 +3,20 @@ use std::path::PathBuf;
 use regex::Regex;
 use serde::Deserialize;
 
+use super::rate_limit::RateLimitConfig;
+
 #[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct NaverPostConfig {
     pub members: Vec<NPMember>,
+    /// Minutes between polls in daemon mode
+    pub poll_interval_mins: Option<u64>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct NPMember {
     pub id: String,
     #[serde(default, deserialize_with = "super::deserialize_regex_option")]
@@ -18,4 +26,5 @@ pub struct NPMember {
     pub download_path: PathBuf,
     pub until_post: Option<u64>,
     pub since_post: Option<u64>,
+    pub poll_interval_mins: Option<u64>,
 }
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use sns_archive_common::RequestPolicy;
+
+/// Rate limit and retry settings of a service, unset values keep the service defaults
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub requests_per_second: Option<f64>,
+    pub burst: Option<u32>,
+    pub max_retries: Option<u32>,
+    pub initial_backoff_ms: Option<u64>,
+    pub max_backoff_secs: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_statuses")]
+    pub retry_statuses: Option<Vec<StatusCode>>,
+}
+
+impl RateLimitConfig {
+    /// Apply the configured settings to the default `policy` of a service
+    pub fn policy(&self, mut policy: RequestPolicy) -> RequestPolicy {
+        if let Some(requests_per_second) = self.requests_per_second {
+            policy.requests_per_second = requests_per_second;
+        }
+        if let Some(burst) = self.burst {
+            policy.burst = burst;
+        }
+        if let Some(max_retries) = self.max_retries {
+            policy.max_retries = max_retries;
+        }
+        if let Some(ms) = self.initial_backoff_ms {
+            policy.initial_backoff = Duration::from_millis(ms);
+        }
+        if let Some(secs) = self.max_backoff_secs {
+            policy.max_backoff = Duration::from_secs(secs);
+        }
+        if let Some(statuses) = &self.retry_statuses {
+            policy.retry_statuses = statuses.clone();
+        }
+        policy
+    }
+}
+
+fn deserialize_statuses<'de, D>(deserializer: D) -> Result<Option<Vec<StatusCode>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let codes: Vec<u16> = Deserialize::deserialize(deserializer)?;
+    codes
+        .into_iter()
+        .map(|c| StatusCode::from_u16(c).map_err(D::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
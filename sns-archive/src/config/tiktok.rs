@@ -3,12 +3,21 @@ use std::path::PathBuf;
 
 use serde::Deserialize;
 
+use super::rate_limit::RateLimitConfig;
+
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct TikTokConfig {
     pub users: HashMap<String, UserConfig>,
+    /// Minutes between polls in daemon mode
+    pub poll_interval_mins: Option<u64>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct UserConfig {
     pub download_path: PathBuf,
+    pub poll_interval_mins: Option<u64>,
 }
@@ -2,12 +2,28 @@ use std::path::PathBuf;
 
 use serde::Deserialize;
 
-#[derive(Deserialize, Debug)]
+use super::rate_limit::RateLimitConfig;
+
+/// Download tweets of Twitter users
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct TwitterConfig {
     pub bearer: String,
+    /// Tweets are downloaded to a directory per user in this path
     #[serde(deserialize_with = "super::deserialize_path")]
     pub download_path: PathBuf,
     pub users: Vec<String>,
+    /// Hours from UTC of the dates in slugs and saved text
     #[serde(default)]
     pub timezone_offset: i32,
+    #[serde(default = "default_num_processes")]
+    pub max_connections: usize,
+    /// Minutes between polls in daemon mode
+    pub poll_interval_mins: Option<u64>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+}
+
+fn default_num_processes() -> usize {
+    20
 }
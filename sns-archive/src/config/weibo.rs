@@ -2,19 +2,28 @@ use std::path::PathBuf;
 
 use serde::Deserialize;
 
+use super::rate_limit::RateLimitConfig;
+
 /// Download Weverse posts and moments
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct WeiboConfig {
     #[serde(default = "default_num_processes")]
     pub max_connections: usize,
     pub users: Vec<WeiboUserConfig>,
+    /// Minutes between polls in daemon mode
+    pub poll_interval_mins: Option<u64>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct WeiboUserConfig {
     pub user: u64,
     #[serde(deserialize_with = "super::deserialize_path")]
     pub download_path: PathBuf,
+    pub poll_interval_mins: Option<u64>,
 }
 
 fn default_num_processes() -> usize {
@@ -1,20 +1,44 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::path::PathBuf;
 
-use serde::Deserialize;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+use weverse::endpoint::artist_tab_posts::DateRange;
+use weverse::endpoint::member::MemberFilter;
+use weverse::endpoint::vod::{VideoPolicy, VideoQuality};
+
+use super::rate_limit::RateLimitConfig;
 
 /// Download Weverse posts and moments
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct WeverseConfig {
     pub email: String,
     // pub password: String,
     #[serde(default = "default_num_processes")]
     pub max_connections: usize,
     pub artists: HashMap<String, ArtistConfig>,
+    /// Minutes between polls in daemon mode
+    pub poll_interval_mins: Option<u64>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
 }
 
 #[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct ArtistConfig {
+    /// Account to download the artist with instead of the default email
+    pub email: Option<String>,
+    /// Only download posts, lives and moments of some members
+    #[serde(default)]
+    pub members: MembersConfig,
+    /// Only download posts and lives published at or after this time
+    #[serde(default, deserialize_with = "deserialize_since")]
+    pub since: Option<OffsetDateTime>,
+    /// Only download posts and lives published before this time
+    #[serde(default, deserialize_with = "deserialize_until")]
+    pub until: Option<OffsetDateTime>,
     #[serde(deserialize_with = "super::deserialize_option_path")]
     #[serde(default)]
     pub artist_download_path: Option<PathBuf>,
@@ -23,13 +47,242 @@ pub struct ArtistConfig {
     #[serde(deserialize_with = "super::deserialize_option_path")]
     #[serde(default)]
     pub moments_download_path: Option<PathBuf>,
+    /// Download every moment that can still be reached instead of stopping at the first moment
+    /// that was already downloaded
+    #[serde(default)]
+    pub moments_backfill: bool,
     #[serde(deserialize_with = "super::deserialize_option_path")]
     #[serde(default)]
     pub lives_download_path: Option<PathBuf>,
     pub lives_download_limit: Option<usize>,
     pub lives_stop_id: Option<String>,
+    /// Save community notices including their images
+    #[serde(deserialize_with = "super::deserialize_option_path")]
+    #[serde(default)]
+    pub notices_download_path: Option<PathBuf>,
+    /// Save letters artists wrote to their fans
+    #[serde(deserialize_with = "super::deserialize_option_path")]
+    #[serde(default)]
+    pub letters_download_path: Option<PathBuf>,
+    /// Save every comment artists write in the community, one JSON file per comment
+    #[serde(deserialize_with = "super::deserialize_option_path")]
+    #[serde(default)]
+    pub artist_comments_download_path: Option<PathBuf>,
+    pub artist_comments_poll_interval_mins: Option<u64>,
+    /// Save comments written by artists as JSON next to posts and moments
+    #[serde(default)]
+    pub artist_comments: bool,
+    /// Also save the comments that artists replied to
+    #[serde(default)]
+    pub artist_comment_parents: bool,
+    /// Save official photos and videos of the media tab
+    #[serde(deserialize_with = "super::deserialize_option_path")]
+    #[serde(default)]
+    pub media_download_path: Option<PathBuf>,
+    /// Only download media of these categories, all media if unset
+    pub media_categories: Option<Vec<String>>,
+    pub media_poll_interval_mins: Option<u64>,
+    /// Which stream of videos and lives to download
+    #[serde(default, deserialize_with = "deserialize_video_quality")]
+    pub video_quality: VideoQuality,
+    /// Also keep the lowest resolution stream of videos and lives
+    #[serde(default)]
+    pub video_proxy: bool,
+    /// Preferred codecs of videos and lives, most preferred first
+    #[serde(default)]
+    pub video_codecs: Vec<String>,
+    pub artist_poll_interval_mins: Option<u64>,
+    pub moments_poll_interval_mins: Option<u64>,
+    pub lives_poll_interval_mins: Option<u64>,
+    pub notices_poll_interval_mins: Option<u64>,
+    pub letters_poll_interval_mins: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct MembersConfig {
+    /// Member IDs or official names to download, all members if empty
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Member IDs or official names to skip
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+impl WeverseConfig {
+    /// Account an artist is downloaded with
+    pub fn email<'a>(&'a self, artist_config: &'a ArtistConfig) -> &'a str {
+        artist_config.email.as_deref().unwrap_or(&self.email)
+    }
+
+    /// Every account used by the config, the default one first and the others sorted
+    pub fn emails(&self) -> Vec<&str> {
+        let others: BTreeSet<_> = self
+            .artists
+            .values()
+            .map(|a| self.email(a))
+            .filter(|e| *e != self.email)
+            .collect();
+        std::iter::once(self.email.as_str()).chain(others).collect()
+    }
+}
+
+impl ArtistConfig {
+    pub fn member_filter(&self) -> MemberFilter {
+        MemberFilter {
+            include: self.members.include.clone(),
+            exclude: self.members.exclude.clone(),
+        }
+    }
+
+    pub fn date_range(&self) -> DateRange {
+        DateRange {
+            since: self.since,
+            until: self.until,
+        }
+    }
+
+    pub fn video_policy(&self) -> VideoPolicy {
+        VideoPolicy {
+            quality: self.video_quality,
+            proxy: self.video_proxy,
+            codecs: self.video_codecs.clone(),
+        }
+    }
+}
+
+/// Video quality is either a height like `1080` or a string like `"max"` or `"720+"`
+fn deserialize_video_quality<'de, D>(deserializer: D) -> Result<VideoQuality, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Quality {
+        Height(u64),
+        Str(String),
+    }
+
+    match Quality::deserialize(deserializer)? {
+        Quality::Height(h) => Ok(VideoQuality::Height(h)),
+        Quality::Str(s) => s.parse().map_err(D::Error::custom),
+    }
+}
+
+fn deserialize_since<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let datetime = deserialize_datetime(deserializer)?;
+    date_bound(&datetime, false)
+        .map(Some)
+        .map_err(D::Error::custom)
+}
+
+fn deserialize_until<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let datetime = deserialize_datetime(deserializer)?;
+    date_bound(&datetime, true)
+        .map(Some)
+        .map_err(D::Error::custom)
+}
+
+/// TOML dates and times, or strings in the same format
+fn deserialize_datetime<'de, D>(deserializer: D) -> Result<toml::value::Datetime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match toml::Value::deserialize(deserializer)? {
+        toml::Value::Datetime(d) => Ok(d),
+        toml::Value::String(s) => s.parse().map_err(D::Error::custom),
+        v => Err(D::Error::custom(format!(
+            "expected a date or date and time, found {}",
+            v.type_str()
+        ))),
+    }
+}
+
+/// Dates and times without an offset are in KST like the times shown on Weverse, and a date
+/// without a time is the start of the day, or its end if `end_of_day`
+fn date_bound(
+    datetime: &toml::value::Datetime,
+    end_of_day: bool,
+) -> Result<OffsetDateTime, String> {
+    let date = datetime
+        .date
+        .ok_or_else(|| format!("missing date in {}", datetime))?;
+    let month = Month::try_from(date.month).map_err(|e| e.to_string())?;
+    let date =
+        Date::from_calendar_date(date.year.into(), month, date.day).map_err(|e| e.to_string())?;
+    let time = match datetime.time {
+        Some(t) => Time::from_hms_nano(t.hour, t.minute, t.second, t.nanosecond)
+            .map_err(|e| e.to_string())?,
+        None => Time::MIDNIGHT,
+    };
+    let offset = match datetime.offset {
+        Some(toml::value::Offset::Z) => UtcOffset::UTC,
+        Some(toml::value::Offset::Custom { minutes }) => {
+            UtcOffset::from_whole_seconds(i32::from(minutes) * 60).map_err(|e| e.to_string())?
+        }
+        None => UtcOffset::from_hms(9, 0, 0).map_err(|e| e.to_string())?,
+    };
+    let bound = PrimitiveDateTime::new(date, time).assume_offset(offset);
+    if end_of_day && datetime.time.is_none() {
+        Ok(bound + Duration::DAY)
+    } else {
+        Ok(bound)
+    }
 }
 
 fn default_num_processes() -> usize {
     20
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn date_bounds() {
+        let bound = |s: &str, end_of_day| date_bound(&s.parse().unwrap(), end_of_day).unwrap();
+        let kst = UtcOffset::from_hms(9, 0, 0).unwrap();
+        let day = Date::from_calendar_date(2023, Month::May, 17).unwrap();
+        let midnight = PrimitiveDateTime::new(day, Time::MIDNIGHT).assume_offset(kst);
+        assert_eq!(bound("2023-05-17", false), midnight);
+        assert_eq!(bound("2023-05-17", true), midnight + Duration::DAY);
+        assert_eq!(
+            bound("2023-05-17T18:00:00", true),
+            midnight + Duration::hours(18)
+        );
+        assert_eq!(
+            bound("2023-05-17T00:00:00Z", false),
+            PrimitiveDateTime::new(day, Time::MIDNIGHT).assume_utc()
+        );
+        assert!(date_bound(&"18:00:00".parse().unwrap(), false).is_err());
+    }
+
+    #[test]
+    fn artist_accounts() {
+        let conf: WeverseConfig = toml::from_str(
+            r#"
+            email = "a@example.com"
+            [artists.dreamcatcher]
+            [artists.purplekiss]
+            email = "c@example.com"
+            [artists.billlie]
+            email = "b@example.com"
+            [artists.weeekly]
+            email = "a@example.com"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(conf.email(&conf.artists["dreamcatcher"]), "a@example.com");
+        assert_eq!(conf.email(&conf.artists["purplekiss"]), "c@example.com");
+        assert_eq!(
+            conf.emails(),
+            ["a@example.com", "b@example.com", "c@example.com"]
+        );
+    }
+}
@@ -2,8 +2,13 @@ use std::path::PathBuf;
 
 use serde::Deserialize;
 
-#[derive(Deserialize, Debug)]
+use super::rate_limit::RateLimitConfig;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct XiaoHongShuConfig {
     #[serde(deserialize_with = "super::deserialize_path")]
     pub download_path: PathBuf,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
 }
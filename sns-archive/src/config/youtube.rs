@@ -2,7 +2,8 @@ use std::path::PathBuf;
 
 use serde::Deserialize;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct YoutubeConfig {
     #[serde(deserialize_with = "super::deserialize_path")]
     pub download_path: PathBuf,
@@ -12,9 +13,12 @@ pub struct YoutubeConfig {
     pub channels: Vec<YTChannel>,
     pub filter: String,
     pub proxy: Option<String>,
+    /// Minutes between polls in daemon mode
+    pub poll_interval_mins: Option<u64>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct YTChannel {
     pub channel_id: String,
     pub display_name: String,
@@ -0,0 +1,104 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures::future;
+use sns_archive_common::{remove_temp_dirs, ArchiveIndex, Archiver};
+use tokio::time::{self, Instant};
+
+use crate::config::Config;
+
+/// Poll every configured service until SIGTERM or Ctrl-C is received
+///
+/// Each service polls its targets one after another, services run at the same time. Clients are
+/// created once and kept for the whole run. Downloads that are still running when the daemon is
+/// stopped are cancelled and their temporary directories removed.
+pub async fn run(conf: Config, index: Arc<ArchiveIndex>) -> Result<()> {
+    let default_interval =
+        Duration::from_secs(conf.daemon.clone().unwrap_or_default().poll_interval_mins * 60);
+    let archivers = crate::archivers(conf, index);
+    if archivers.is_empty() {
+        return Err(anyhow!("No services configured"));
+    }
+
+    let polls = future::join_all(
+        archivers
+            .iter()
+            .map(|a| poll_service(a.as_ref(), default_interval)),
+    );
+    tokio::select! {
+        _ = polls => {}
+        result = shutdown_signal() => result?,
+    }
+
+    println!("Shutting down");
+    for archiver in &archivers {
+        for dir in archiver.download_dirs() {
+            let removed = remove_temp_dirs(&dir).await?;
+            if removed > 0 {
+                println!(
+                    "Removed {} unfinished downloads from {}",
+                    removed,
+                    dir.display()
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Poll the targets of a service forever, each on its own interval
+async fn poll_service(archiver: &dyn Archiver, default_interval: Duration) {
+    struct Target {
+        name: String,
+        interval: Duration,
+        next: Instant,
+    }
+
+    let now = Instant::now();
+    let mut targets: Vec<_> = archiver
+        .poll_targets()
+        .into_iter()
+        .map(|t| Target {
+            name: t.name,
+            interval: t.interval.unwrap_or(default_interval),
+            next: now,
+        })
+        .collect();
+
+    while let Some(target) = targets.iter_mut().min_by_key(|t| t.next) {
+        time::sleep_until(target.next).await;
+        target.next = Instant::now() + target.interval;
+
+        let label = if target.name.is_empty() {
+            archiver.name().to_owned()
+        } else {
+            format!("{} {}", archiver.name(), target.name)
+        };
+        let result = archiver.download_target(&target.name).await;
+        let report = archiver.take_report();
+        match result {
+            Ok(()) => println!("{}: {}", label, report),
+            Err(e) => println!("{}: {}, failed: {:#}", label, report, e),
+        }
+    }
+}
+
+/// Wait for SIGTERM or Ctrl-C
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut sigterm = signal(SignalKind::terminate())?;
+        tokio::select! {
+            _ = sigterm.recv() => {}
+            result = tokio::signal::ctrl_c() => result?,
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+
+    Ok(())
+}
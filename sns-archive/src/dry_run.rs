@@ -0,0 +1,56 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use sns_archive_common::{Archiver, DiscoveredPost};
+
+/// Print the posts each archiver would download, without downloading anything
+///
+/// A failing service does not stop the others, like with `sns-archive all`.
+pub async fn print_plan(archivers: &[Box<dyn Archiver>]) -> Result<()> {
+    let mut failed = 0;
+    for archiver in archivers {
+        match archiver.discover().await {
+            Ok(posts) => print_posts(archiver.name(), &posts),
+            Err(e) => {
+                println!("{}: discovery failed: {:#}", archiver.name(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(Error {
+            failed,
+            total: archivers.len(),
+        }
+        .into())
+    }
+}
+
+fn print_posts(name: &str, posts: &[DiscoveredPost]) {
+    println!("{}: {} posts would be downloaded", name, posts.len());
+    for post in posts {
+        println!("  [{}] {} {}", post.account, post.id, post.slug);
+        println!("    -> {}", post.path.display());
+    }
+}
+
+#[derive(Debug)]
+struct Error {
+    failed: usize,
+    total: usize,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "discovery failed for {} of {} services",
+            self.failed, self.total
+        )
+    }
+}
+
+impl std::error::Error for Error {}
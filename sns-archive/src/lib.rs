@@ -1,7 +1,51 @@
+use std::sync::Arc;
+
+use sns_archive_common::{ArchiveIndex, Archiver};
+
+use crate::config::Config;
+
+pub mod all;
 pub mod config;
+pub mod daemon;
+pub mod dry_run;
 pub mod naver_post;
+pub mod report;
 pub mod tiktok;
+pub mod twitter;
 pub mod weibo;
 pub mod weverse;
 pub mod xiaohongshu;
 pub mod youtube;
+
+/// Create an archiver for every service that has a section in the config file
+///
+/// Xiaohongshu is not included because it needs an API response as input.
+pub fn archivers(conf: Config, index: Arc<ArchiveIndex>) -> Vec<Box<dyn Archiver>> {
+    let mut archivers: Vec<Box<dyn Archiver>> = Vec::new();
+    if let Some(conf) = conf.weverse {
+        archivers.push(Box::new(weverse::WeverseArchiver::new(conf, index.clone())));
+    }
+    if let Some(conf) = conf.youtube {
+        archivers.push(Box::new(youtube::YoutubeArchiver::new(conf)));
+    }
+    if let Some(conf) = conf.naver_post {
+        archivers.push(Box::new(naver_post::NaverPostArchiver::new(
+            conf,
+            index.clone(),
+        )));
+    }
+    if let Some(conf) = conf.weibo {
+        archivers.push(Box::new(weibo::WeiboArchiver::new(conf, index.clone())));
+    }
+    if let Some(conf) = conf.tiktok {
+        archivers.push(Box::new(tiktok::TikTokArchiver::new(
+            conf,
+            index.clone(),
+            None,
+        )));
+    }
+    if let Some(conf) = conf.twitter {
+        archivers.push(Box::new(twitter::TwitterArchiver::new(conf, index)));
+    }
+    archivers
+}
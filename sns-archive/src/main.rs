@@ -1,27 +1,57 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
-use clap::{Parser, Subcommand};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
 use sns_archive::config::Config;
+use sns_archive::report::{ReportFile, ReportFormat, ServiceResult};
+use sns_archive::tiktok::TikTokArchiver;
+use sns_archive::weverse::account;
+use sns_archive::xiaohongshu::XiaoHongShuArchiver;
+use sns_archive_common::{ArchiveIndex, Archiver};
 
 /// Archive various social networking services
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Args {
     /// Services to archive
-    #[clap(subcommand)] sns: Sns,
+    #[clap(subcommand)]
+    sns: Sns,
 
     /// Config file location
     #[arg(short, long, default_value_os_t = default_config_path())]
     config: PathBuf,
+
+    /// Write a JSON report of every handled post and a summary to this file
+    #[arg(long, global = true, conflicts_with = "dry_run")]
+    report: Option<PathBuf>,
+
+    /// Format of the report file
+    #[arg(long, global = true, value_enum, default_value_t)]
+    report_format: ReportFormat,
+
+    /// Print the posts that would be downloaded and where to, without downloading or writing
+    /// anything
+    #[arg(long, global = true)]
+    dry_run: bool,
 }
 
 #[derive(Subcommand, Debug)]
 enum Sns {
+    /// Run every service configured in the config file
+    All,
+    /// Keep running and poll every configured service on the intervals set in the config file
+    ///
+    /// Stops on SIGTERM or Ctrl-C.
+    Daemon,
     /// Download Weverse posts and moments
     #[command(verbatim_doc_comment)]
-    Weverse,
+    Weverse {
+        #[clap(subcommand)]
+        command: Option<WeverseCommand>,
+    },
     /// Download Youtube videos
     Youtube,
     /// Download Naver Blog post images
@@ -35,12 +65,69 @@ enum Sns {
     },
     /// Download Weibo user posts
     Weibo,
+    /// Download Twitter user posts
+    Twitter,
     /// Download TikTok videos
     Tiktok {
         /// tiktok page html
         #[arg(short, long)]
         input_file: Option<PathBuf>,
     },
+    /// Manage the archive index of downloaded posts
+    Index {
+        #[clap(subcommand)]
+        command: IndexCommand,
+    },
+    /// Manage the config file
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum IndexCommand {
+    /// Add posts in existing archive folders to the index
+    Migrate,
+}
+
+#[derive(Subcommand, Debug)]
+enum WeverseCommand {
+    /// Save the access and refresh tokens of a browser logged in to weverse.io
+    ///
+    /// The tokens are asked for unless a cookie export is given.
+    Login {
+        /// Cookies of weverse.io exported from a browser, as cookies.txt or JSON
+        #[arg(long)]
+        cookies: Option<PathBuf>,
+        /// Account to save the tokens for instead of the email in the config file
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// Delete the saved tokens
+    Logout {
+        /// Account to log out instead of the email in the config file
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// Show when the saved tokens expire
+    Status {
+        /// Only show this account instead of every account in the config file
+        #[arg(long)]
+        email: Option<String>,
+    },
+    /// List the communities the account joined, to use as artists in the config file
+    Communities {
+        /// Account to list the communities of instead of the email in the config file
+        #[arg(long)]
+        email: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Report unknown keys, invalid regexes, unusable or shared download paths and missing programs
+    Check,
 }
 
 fn default_config_path() -> PathBuf {
@@ -61,58 +148,179 @@ async fn main() {
     }
 }
 
+impl Sns {
+    /// Config file section of the service, `None` for subcommands that are not a single service
+    fn config_section(&self) -> Option<&'static str> {
+        match self {
+            Self::All
+            | Self::Daemon
+            | Self::Index { .. }
+            | Self::Config { .. }
+            | Self::Weverse { command: Some(_) } => None,
+            Self::Weverse { command: None } => Some("weverse"),
+            Self::Youtube => Some("youtube"),
+            Self::NaverPost => Some("naver_post"),
+            Self::XiaoHongShu { .. } => Some("xiaohongshu"),
+            Self::Weibo => Some("weibo"),
+            Self::Tiktok { .. } => Some("tiktok"),
+            Self::Twitter => Some("twitter"),
+        }
+    }
+
+    /// Whether the subcommand downloads posts once and can write a report of them
+    fn writes_report(&self) -> bool {
+        !matches!(
+            self,
+            Self::Daemon
+                | Self::Index { .. }
+                | Self::Config { .. }
+                | Self::Weverse { command: Some(_) }
+        )
+    }
+}
+
 async fn run() -> Result<()> {
     let args = Args::parse();
+    if args.report.is_some() && !args.sns.writes_report() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--report can only be used with subcommands that download posts once",
+            )
+            .exit();
+    }
+    // Checking must not stop at the first error like reading the config does
+    if let Sns::Config {
+        command: ConfigCommand::Check,
+    } = args.sns
+    {
+        return check_config(&args.config);
+    }
     let conf = Config::read(args.config)?;
+    // Account commands don't use the index, so it must not be created for them
+    if let Sns::Weverse {
+        command: Some(command),
+    } = args.sns
+    {
+        if args.dry_run {
+            return Err(anyhow!(
+                "--dry-run is not supported by weverse account commands"
+            ));
+        }
+        return weverse_account(conf, command).await;
+    }
+    let index = Arc::new(open_index(&conf, args.dry_run)?);
+    let report = args.report.map(|path| ReportFile {
+        path,
+        format: args.report_format,
+    });
 
-    match args.sns {
-        Sns::Weverse => {
-            if let Some(conf) = conf.weverse {
-                sns_archive::weverse::download(conf).await?;
-            } else {
-                return Err(anyhow!("Missing weverse section in config file"));
+    let section = match args.sns.config_section() {
+        Some(section) => section,
+        None => match args.sns {
+            Sns::Index { .. } if args.dry_run => {
+                return Err(anyhow!("--dry-run is not supported by the index command"))
             }
-        }
-        Sns::Youtube => {
-            if let Some(conf) = conf.youtube {
-                sns_archive::youtube::download(conf)?;
-            } else {
-                return Err(anyhow!("Missing youtube section in config file"));
+            Sns::Index {
+                command: IndexCommand::Migrate,
+            } => return migrate_index(conf, index).await,
+            _ if args.dry_run => {
+                return sns_archive::dry_run::print_plan(&sns_archive::archivers(conf, index)).await
             }
+            Sns::Daemon => return sns_archive::daemon::run(conf, index).await,
+            _ => return sns_archive::all::download(conf, index, report.as_ref()).await,
+        },
+    };
+    let missing_section = || anyhow!("Missing {} section in config file", section);
+
+    // Services that need extra input from the command line
+    let archiver: Box<dyn Archiver> = match args.sns {
+        Sns::XiaoHongShu { json_file } => {
+            let conf = conf.xiaohongshu.ok_or_else(missing_section)?;
+            Box::new(XiaoHongShuArchiver::new(conf, index, Some(json_file)))
         }
-        Sns::NaverPost => {
-            if let Some(conf) = conf.naver_post {
-                sns_archive::naver_post::download_members(conf.members).await?;
-            } else {
-                return Err(anyhow!("Missing naver post section in config file"));
-            }
+        Sns::Tiktok {
+            input_file: Some(input_file),
+        } => {
+            let conf = conf.tiktok.ok_or_else(missing_section)?;
+            Box::new(TikTokArchiver::new(conf, index, Some(input_file)))
         }
-        Sns::XiaoHongShu { json_file: f } => {
-            if let Some(conf) = conf.xiaohongshu {
-                sns_archive::xiaohongshu::download(f, conf).await?;
-            } else {
-                return Err(anyhow!("Missing xiaohongshu section in config file"));
-            }
+        _ => sns_archive::archivers(conf, index)
+            .into_iter()
+            .find(|a| a.config_section() == section)
+            .ok_or_else(missing_section)?,
+    };
+
+    if args.dry_run {
+        return sns_archive::dry_run::print_plan(std::slice::from_ref(&archiver)).await;
+    }
+
+    let result = ServiceResult::run(archiver.as_ref()).await;
+    println!("{}: {}", result.name, result.report);
+    if let Some(report) = &report {
+        report.write(std::slice::from_ref(&result))?;
+    }
+    result.result
+}
+
+/// Print the problems found in the config file
+fn check_config(path: &Path) -> Result<()> {
+    let problems = sns_archive::config::check::check(path)?;
+    for problem in &problems {
+        match problem.location {
+            Some(_) => println!("{}:{}", path.display(), problem),
+            None => println!("{}: {}", path.display(), problem),
         }
-        Sns::Weibo => {
-            if let Some(conf) = conf.weibo {
-                sns_archive::weibo::download(conf).await?;
-            } else {
-                return Err(anyhow!("Missing weibo section in config file"));
-            }
+    }
+    if problems.is_empty() {
+        println!("{}: no problems found", path.display());
+        Ok(())
+    } else {
+        Err(anyhow!("{} problems found in config file", problems.len()))
+    }
+}
+
+/// Manage the saved Weverse login of the account in the config file
+async fn weverse_account(conf: Config, command: WeverseCommand) -> Result<()> {
+    let conf = conf
+        .weverse
+        .ok_or_else(|| anyhow!("Missing weverse section in config file"))?;
+    match command {
+        WeverseCommand::Login { cookies, email } => {
+            account::login(&conf, email.as_deref(), cookies.as_deref()).await
         }
-        Sns::Tiktok { input_file } => {
-            if let Some(conf) = conf.tiktok {
-                if let Some(input) = input_file {
-                    sns_archive::tiktok::download_from_html(input).await?;
-                } else {
-                    sns_archive::tiktok::download(conf).await?;
-                }
-            } else {
-                return Err(anyhow!("Missing tiktok section in config file"));
-            }
+        WeverseCommand::Logout { email } => account::logout(&conf, email.as_deref()).await,
+        WeverseCommand::Status { email } => account::status(&conf, email.as_deref()).await,
+        WeverseCommand::Communities { email } => {
+            account::communities(&conf, email.as_deref()).await
         }
     }
+}
 
+/// Open the archive index
+///
+/// A dry run must not create the index, so an index that doesn't exist yet is replaced with an
+/// empty one in memory.
+fn open_index(conf: &Config, dry_run: bool) -> Result<ArchiveIndex> {
+    let path = conf.index_path()?;
+    if dry_run && !path.exists() {
+        ArchiveIndex::open_in_memory()
+    } else {
+        ArchiveIndex::open(path)
+    }
+}
+
+/// Add existing downloads of every configured service to the archive index
+async fn migrate_index(conf: Config, index: Arc<ArchiveIndex>) -> Result<()> {
+    let xiaohongshu = conf.xiaohongshu.clone();
+    let mut archivers = sns_archive::archivers(conf, index.clone());
+    if let Some(conf) = xiaohongshu {
+        archivers.push(Box::new(XiaoHongShuArchiver::new(conf, index, None)));
+    }
+
+    for archiver in archivers {
+        let added = archiver.migrate_index().await?;
+        println!("{}: added {} posts to the index", archiver.name(), added);
+    }
     Ok(())
 }
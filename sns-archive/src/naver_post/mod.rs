@@ -1,26 +1,186 @@
-use anyhow::Result;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use naver_blog::{ImageType, NaverBlogClient};
+use regex::Regex;
+use reqwest_middleware::ClientWithMiddleware;
+use sns_archive_common::{
+    http_client, scan_dir, ArchiveIndex, Archiver, DiscoveredPost, PollTarget, PostRecord, Report,
+};
+
+use crate::config::daemon::poll_interval;
+use crate::config::naver_post::{NPMember, NaverPostConfig};
+
+/// Service name in the archive index
+const SERVICE: &str = "naver_post";
+
+pub struct NaverPostArchiver {
+    conf: NaverPostConfig,
+    index: Arc<ArchiveIndex>,
+    reqwest_client: ClientWithMiddleware,
+    report: Mutex<Report>,
+}
 
-use crate::config::naver_post::NPMember;
+impl NaverPostArchiver {
+    pub fn new(conf: NaverPostConfig, index: Arc<ArchiveIndex>) -> Self {
+        Self {
+            reqwest_client: http_client(conf.rate_limit.policy(Default::default())),
+            conf,
+            index,
+            report: Mutex::new(Report::default()),
+        }
+    }
 
-pub async fn download_members(members: Vec<NPMember>) -> Result<()> {
-    let reqwest_client = reqwest::Client::new();
-    let client = NaverBlogClient::new(&reqwest_client);
-    for member in members {
-        client
+    /// Client that skips posts that are in the archive index
+    fn client(&self) -> NaverBlogClient<'_> {
+        NaverBlogClient::new(&self.reqwest_client).skip_post(|member, id| {
+            matches!(
+                self.index.contains(SERVICE, &post_key(member, id)),
+                Ok(true)
+            )
+        })
+    }
+
+    async fn download_member(&self, client: &NaverBlogClient<'_>, member: &NPMember) -> Result<()> {
+        let result = client
             .download_member::<ProgressBar>(
                 &member.id,
-                member.download_path,
+                &member.download_path,
                 member.filter.as_ref(),
                 member.limit,
                 ImageType::default(),
                 member.until_post,
                 member.since_post,
             )
-            .await?;
+            .await;
+        let downloaded = match result {
+            Ok(downloaded) => downloaded,
+            Err(e) => {
+                let e = e.into();
+                let record = PostRecord::new(SERVICE, &member.id).failed(&e);
+                self.report.lock().unwrap().add(record);
+                return Err(e);
+            }
+        };
+        for post in downloaded {
+            let path = member.download_path.join(&post.slug);
+            let entry = self
+                .index
+                .insert_files(
+                    SERVICE,
+                    &post_key(&member.id, post.post_id),
+                    &post.slug,
+                    vec![path],
+                )
+                .await?;
+            let record = PostRecord::new(SERVICE, &member.id).downloaded(&entry);
+            self.report.lock().unwrap().add(record);
+        }
+        Ok(())
     }
+}
+
+/// Post IDs are only unique per blog, so the index key includes the member
+fn post_key(member: &str, id: impl Display) -> String {
+    format!("{}/{}", member, id)
+}
 
-    Ok(())
+#[async_trait(?Send)]
+impl Archiver for NaverPostArchiver {
+    fn name(&self) -> &'static str {
+        "Naver Post"
+    }
+
+    fn config_section(&self) -> &'static str {
+        SERVICE
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredPost>> {
+        let client = self.client();
+        let mut discovered = Vec::new();
+        for member in &self.conf.members {
+            let posts = client
+                .discover_member(
+                    &member.id,
+                    &member.download_path,
+                    member.filter.as_ref(),
+                    member.limit,
+                    member.until_post,
+                    member.since_post,
+                )
+                .await?;
+            discovered.extend(posts.into_iter().map(|p| DiscoveredPost {
+                account: member.id.clone(),
+                id: p.post_id.to_string(),
+                path: member.download_path.join(&p.slug),
+                slug: p.slug,
+            }));
+        }
+        Ok(discovered)
+    }
+
+    async fn download(&self) -> Result<()> {
+        let client = self.client();
+        for member in &self.conf.members {
+            self.download_member(&client, member).await?;
+        }
+        Ok(())
+    }
+
+    fn take_report(&self) -> Report {
+        std::mem::take(&mut self.report.lock().unwrap())
+    }
+
+    fn poll_targets(&self) -> Vec<PollTarget> {
+        self.conf
+            .members
+            .iter()
+            .map(|m| PollTarget {
+                name: m.id.clone(),
+                interval: poll_interval([m.poll_interval_mins, self.conf.poll_interval_mins]),
+            })
+            .collect()
+    }
+
+    async fn download_target(&self, target: &str) -> Result<()> {
+        let member = self
+            .conf
+            .members
+            .iter()
+            .find(|m| m.id == target)
+            .ok_or_else(|| anyhow!("unknown Naver Post member {}", target))?;
+        self.download_member(&self.client(), member).await
+    }
+
+    fn download_dirs(&self) -> Vec<PathBuf> {
+        self.conf
+            .members
+            .iter()
+            .map(|m| m.download_path.clone())
+            .collect()
+    }
+
+    async fn migrate_index(&self) -> Result<usize> {
+        let mut added = 0;
+        for member in &self.conf.members {
+            let re = Regex::new(&format!(
+                r"^\d{{8}}-{}-(?P<id>\d+)-",
+                regex::escape(&member.id)
+            ))?;
+            let posts = scan_dir(&member.download_path, &re)?
+                .into_iter()
+                .map(|mut p| {
+                    p.post_id = post_key(&member.id, &p.post_id);
+                    p
+                })
+                .collect();
+            added += self.index.migrate(SERVICE, posts).await?;
+        }
+        Ok(added)
+    }
 }
 
 struct ProgressBar(indicatif::ProgressBar);
@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use sns_archive_common::{Archiver, PostRecord, Report};
+
+/// File format of the run report
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum ReportFormat {
+    /// One JSON object per line, a record for every post followed by the summary
+    #[default]
+    Ndjson,
+    /// A single JSON object with every post and the summary
+    Json,
+}
+
+/// Where to write the run report, set with `--report`
+#[derive(Clone, Debug)]
+pub struct ReportFile {
+    pub path: PathBuf,
+    pub format: ReportFormat,
+}
+
+impl ReportFile {
+    pub fn write(&self, results: &[ServiceResult]) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+        write(&mut writer, self.format, results)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Result of running a single archiver
+pub struct ServiceResult {
+    pub name: &'static str,
+    pub section: &'static str,
+    pub elapsed: Duration,
+    pub result: Result<()>,
+    pub report: Report,
+}
+
+impl ServiceResult {
+    /// Download all new posts of `archiver`
+    pub async fn run(archiver: &dyn Archiver) -> Self {
+        let start = Instant::now();
+        let result = archiver.download().await;
+        Self {
+            name: archiver.name(),
+            section: archiver.config_section(),
+            elapsed: start.elapsed(),
+            result,
+            report: archiver.take_report(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    downloaded: usize,
+    skipped: usize,
+    failed: usize,
+    services: Vec<ServiceSummary<'a>>,
+}
+
+#[derive(Serialize)]
+struct ServiceSummary<'a> {
+    service: &'a str,
+    name: &'a str,
+    ok: bool,
+    elapsed_secs: f64,
+    downloaded: usize,
+    skipped: usize,
+    failed: usize,
+    /// Error the service stopped with and its causes, outermost first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    error: Vec<String>,
+}
+
+impl<'a> Summary<'a> {
+    fn new(results: &'a [ServiceResult]) -> Self {
+        let services: Vec<_> = results
+            .iter()
+            .map(|r| ServiceSummary {
+                service: r.section,
+                name: r.name,
+                ok: r.result.is_ok(),
+                elapsed_secs: r.elapsed.as_secs_f64(),
+                downloaded: r.report.downloaded,
+                skipped: r.report.skipped,
+                failed: r.report.failed,
+                error: match &r.result {
+                    Ok(_) => Vec::new(),
+                    Err(e) => e.chain().map(|e| e.to_string()).collect(),
+                },
+            })
+            .collect();
+        Self {
+            downloaded: services.iter().map(|s| s.downloaded).sum(),
+            skipped: services.iter().map(|s| s.skipped).sum(),
+            failed: services.iter().map(|s| s.failed).sum(),
+            services,
+        }
+    }
+}
+
+/// Line of an NDJSON report
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Line<'a> {
+    Post(&'a PostRecord),
+    Summary(&'a Summary<'a>),
+}
+
+/// Write the posts and summary of `results` to `writer`
+pub fn write(
+    mut writer: impl Write,
+    format: ReportFormat,
+    results: &[ServiceResult],
+) -> Result<()> {
+    let posts = results.iter().flat_map(|r| r.report.posts.iter());
+    let summary = Summary::new(results);
+
+    match format {
+        ReportFormat::Ndjson => {
+            for post in posts {
+                serde_json::to_writer(&mut writer, &Line::Post(post))?;
+                writeln!(writer)?;
+            }
+            serde_json::to_writer(&mut writer, &Line::Summary(&summary))?;
+            writeln!(writer)?;
+        }
+        ReportFormat::Json => {
+            #[derive(Serialize)]
+            struct Document<'a> {
+                posts: Vec<&'a PostRecord>,
+                summary: &'a Summary<'a>,
+            }
+            let document = Document {
+                posts: posts.collect(),
+                summary: &summary,
+            };
+            serde_json::to_writer_pretty(&mut writer, &document)?;
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ndjson() {
+        let mut report = Report::default();
+        report.add(
+            PostRecord::new("weibo", "1")
+                .post("100", "20230101-100-a")
+                .skipped(),
+        );
+        report.add(PostRecord::new("weibo", "1").failed(&anyhow::anyhow!("rate limited")));
+        let results = [ServiceResult {
+            name: "Weibo",
+            section: "weibo",
+            elapsed: Duration::from_secs(1),
+            result: Err(anyhow::anyhow!("errors occured")),
+            report,
+        }];
+
+        let mut contents = Vec::new();
+        write(&mut contents, ReportFormat::Ndjson, &results).unwrap();
+        let contents = String::from_utf8(contents).unwrap();
+
+        let lines: Vec<serde_json::Value> = contents
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["type"], "post");
+        assert_eq!(lines[0]["status"], "skipped");
+        assert_eq!(lines[1]["status"], "failed");
+        assert_eq!(lines[1]["error"][0], "rate limited");
+        assert!(lines[1]["post_id"].is_null());
+        assert_eq!(lines[2]["type"], "summary");
+        assert_eq!(lines[2]["failed"], 1);
+        assert_eq!(lines[2]["services"][0]["ok"], false);
+    }
+}
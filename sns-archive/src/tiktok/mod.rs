@@ -1,73 +1,229 @@
-use std::path::Path;
-use anyhow::Result;
-use reqwest::Client;
-use sns_archive_common::{SavablePost, osstr_starts_with};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest_middleware::ClientWithMiddleware;
+use sns_archive_common::{
+    http_client, osstr_starts_with, scan_dir, ArchiveIndex, Archiver, DiscoveredPost, IndexEntry,
+    PollTarget, PostRecord, Report, SavablePost,
+};
 use tiktok::{TikTokClient, TikTokVideo};
 use tokio::fs;
 
+use crate::config::daemon::poll_interval;
 use crate::config::tiktok::TikTokConfig;
 
-pub async fn download(conf: TikTokConfig) -> Result<()> {
-    let client = Client::new();
-    let tt_client = TikTokClient::new(&client);
-    for (user, user_config) in conf.users {
-        println!("Downloading {} videos", &user);
-        let videos = tt_client.latest_user_videos(&user).await?;
+/// Service name in the archive index
+const SERVICE: &str = "tiktok";
+
+pub struct TikTokArchiver {
+    conf: TikTokConfig,
+    index: Arc<ArchiveIndex>,
+    /// Download videos from a saved TikTok page instead of the configured users
+    input_file: Option<PathBuf>,
+    client: ClientWithMiddleware,
+    report: Mutex<Report>,
+}
+
+impl TikTokArchiver {
+    pub fn new(conf: TikTokConfig, index: Arc<ArchiveIndex>, input_file: Option<PathBuf>) -> Self {
+        Self {
+            client: http_client(conf.rate_limit.policy(Default::default())),
+            conf,
+            index,
+            input_file,
+            report: Mutex::new(Report::default()),
+        }
+    }
+
+    /// Videos to download grouped by download directory
+    async fn videos(&self) -> Result<Vec<(String, PathBuf, Vec<TikTokVideo>)>> {
+        let tt_client = TikTokClient::new(&self.client);
+        if let Some(input_file) = &self.input_file {
+            let html = fs::read_to_string(input_file).await?;
+            let videos = tt_client.videos_from_html(&html).await?;
+            return Ok(vec![(String::new(), std::env::current_dir()?, videos)]);
+        }
+
+        let mut all_videos = Vec::new();
+        for (user, user_config) in &self.conf.users {
+            let videos = tt_client.latest_user_videos(user).await?;
+            all_videos.push((user.clone(), user_config.download_path.clone(), videos));
+        }
+        Ok(all_videos)
+    }
+
+    /// Download videos to `download_path`, stops at the first failed video
+    async fn download_videos(
+        &self,
+        user: &str,
+        download_path: &Path,
+        videos: Vec<TikTokVideo>,
+    ) -> Result<()> {
+        if !user.is_empty() {
+            println!("Downloading {} videos", user);
+        }
         for tt_video in videos {
-            download_video(&user_config.download_path, &client, tt_video).await?;
+            let mut record = PostRecord::new(SERVICE, user);
+            let result = match tt_video.slug() {
+                Ok(slug) => {
+                    record = record.post(tt_video.id(), &slug);
+                    download_video(download_path, &self.client, &self.index, &tt_video, &slug).await
+                }
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(entry) => self.report.lock().unwrap().add(record.finish(Ok(entry))),
+                Err(e) => {
+                    self.report.lock().unwrap().add(record.failed(&e));
+                    return Err(e);
+                }
+            }
         }
+        Ok(())
     }
-    Ok(())
 }
 
-pub async fn download_from_html(input_file: impl AsRef<Path>) -> Result<()> {
-    let client = Client::new();
-    let tt_client = TikTokClient::new(&client);
-    let html = fs::read_to_string(input_file).await?;
-    let videos = tt_client.videos_from_html(&html).await?;
-    for tt_video in videos {
-        download_video(std::env::current_dir()?, &client, tt_video).await?;
+#[async_trait(?Send)]
+impl Archiver for TikTokArchiver {
+    fn name(&self) -> &'static str {
+        "TikTok"
+    }
+
+    fn config_section(&self) -> &'static str {
+        SERVICE
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredPost>> {
+        let mut discovered = Vec::new();
+        for (user, download_path, videos) in self.videos().await? {
+            for video in videos {
+                let slug = video.slug()?;
+                if self.index.contains(SERVICE, video.id())?
+                    || !video_files(&download_path, &slug).await?.is_empty()
+                {
+                    continue;
+                }
+                discovered.push(DiscoveredPost {
+                    account: user.clone(),
+                    id: video.id().to_owned(),
+                    path: download_path.join(&slug),
+                    slug,
+                });
+            }
+        }
+        Ok(discovered)
+    }
+
+    async fn download(&self) -> Result<()> {
+        for (user, download_path, videos) in self.videos().await? {
+            self.download_videos(&user, &download_path, videos).await?;
+        }
+        Ok(())
+    }
+
+    fn take_report(&self) -> Report {
+        std::mem::take(&mut self.report.lock().unwrap())
+    }
+
+    fn poll_targets(&self) -> Vec<PollTarget> {
+        self.conf
+            .users
+            .iter()
+            .map(|(user, user_config)| PollTarget {
+                name: user.clone(),
+                interval: poll_interval([
+                    user_config.poll_interval_mins,
+                    self.conf.poll_interval_mins,
+                ]),
+            })
+            .collect()
+    }
+
+    async fn download_target(&self, target: &str) -> Result<()> {
+        let (user, user_config) = self
+            .conf
+            .users
+            .get_key_value(target)
+            .ok_or_else(|| anyhow!("unknown TikTok user {}", target))?;
+        let videos = TikTokClient::new(&self.client)
+            .latest_user_videos(user)
+            .await?;
+        self.download_videos(user, &user_config.download_path, videos)
+            .await
+    }
+
+    fn download_dirs(&self) -> Vec<PathBuf> {
+        self.conf
+            .users
+            .values()
+            .map(|u| u.download_path.clone())
+            .collect()
+    }
+
+    async fn migrate_index(&self) -> Result<usize> {
+        static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d{8}_(?P<id>\d+)_").unwrap());
+
+        let mut added = 0;
+        for user_config in self.conf.users.values() {
+            let posts = scan_dir(&user_config.download_path, &RE)?;
+            added += self.index.migrate(SERVICE, posts).await?;
+        }
+        Ok(added)
     }
-    Ok(())
 }
 
-#[derive(Debug)]
-enum DownloadStatus {
-    Downloaded,
-    Skipped,
+/// Find all files starting with the slug
+async fn video_files(download_dir: impl AsRef<Path>, slug: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut read_dir = match fs::read_dir(download_dir.as_ref()).await {
+        Ok(r) => r,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(f) = read_dir.next_entry().await? {
+        if osstr_starts_with(&f.file_name(), slug) {
+            files.push(f.path());
+        }
+    }
+    Ok(files)
 }
 
 async fn download_video(
     download_dir: impl AsRef<Path>,
-    client: &Client,
-    video: TikTokVideo,
-) -> Result<DownloadStatus> {
-    let slug = video.slug()?;
+    client: &ClientWithMiddleware,
+    index: &ArchiveIndex,
+    video: &TikTokVideo,
+    slug: &str,
+) -> Result<Option<IndexEntry>> {
     fs::create_dir_all(&download_dir).await?;
-    let mut read_dir = fs::read_dir(download_dir.as_ref()).await?;
-    while let Some(f) = read_dir.next_entry().await? {
-        if osstr_starts_with(&f.file_name(), &slug) {
-            return Ok(DownloadStatus::Skipped);
-        }
+    let existing = video_files(download_dir.as_ref(), slug).await?;
+    if index.check(SERVICE, video.id(), slug, existing).await? {
+        return Ok(None);
     }
 
     // Create temporary directory
-    let temp_dir = download_dir
-        .as_ref()
-        .join(format!(".{}.temp", video.slug()?));
+    let temp_dir = download_dir.as_ref().join(format!(".{}.temp", slug));
     fs::create_dir_all(&temp_dir).await?;
 
     // Download to temporary directory
     video.download(client, &temp_dir).await?;
 
     // Move files out of temporary directory
+    let mut files = Vec::new();
     let mut read_dir = fs::read_dir(&temp_dir).await?;
     while let Some(f) = read_dir.next_entry().await? {
-        fs::rename(f.path(), &download_dir.as_ref().join(f.file_name())).await?;
+        let file = download_dir.as_ref().join(f.file_name());
+        fs::rename(f.path(), &file).await?;
+        files.push(file);
     }
     fs::remove_dir(temp_dir).await?;
+    let entry = index.insert_files(SERVICE, video.id(), slug, files).await?;
 
     println!("Downloaded {}", slug);
 
-    Ok(DownloadStatus::Downloaded)
+    Ok(Some(entry))
 }
@@ -0,0 +1,248 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::{future, StreamExt};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest_middleware::ClientWithMiddleware;
+use sns_archive_common::{
+    http_client, scan_dir, ArchiveIndex, Archiver, DiscoveredPost, IndexEntry, PollTarget,
+    PostRecord, PostStatus, Report, SavablePost,
+};
+use time::UtcOffset;
+use tokio::fs;
+use twitter::{TwitterClient, TwitterPost};
+
+use crate::config::daemon::poll_interval;
+use crate::config::twitter::TwitterConfig;
+
+/// Service name in the archive index
+const SERVICE: &str = "twitter";
+
+pub struct TwitterArchiver {
+    conf: TwitterConfig,
+    index: Arc<ArchiveIndex>,
+    client: ClientWithMiddleware,
+    report: Mutex<Report>,
+}
+
+impl TwitterArchiver {
+    pub fn new(conf: TwitterConfig, index: Arc<ArchiveIndex>) -> Self {
+        Self {
+            client: http_client(conf.rate_limit.policy(twitter::request_policy())),
+            conf,
+            index,
+            report: Mutex::new(Report::default()),
+        }
+    }
+
+    fn twitter_client(&self) -> Result<TwitterClient<'_>> {
+        let utc_offset = i8::try_from(self.conf.timezone_offset)
+            .ok()
+            .and_then(|hours| UtcOffset::from_hms(hours, 0, 0).ok())
+            .ok_or_else(|| anyhow!("invalid timezone_offset {}", self.conf.timezone_offset))?;
+        Ok(TwitterClient::new(&self.client, &self.conf.bearer).utc_offset(utc_offset))
+    }
+
+    fn user_path(&self, user: &str) -> PathBuf {
+        self.conf.download_path.join(user)
+    }
+
+    /// Download new posts of a user, returns whether any post failed
+    async fn download_user(&self, user: &str) -> Result<bool> {
+        let client = &self.client;
+        let twitter_client = self.twitter_client()?;
+        let download_path = self.user_path(user);
+
+        let mut errored = false;
+        let mut posts = twitter_client.posts(user).await?;
+        let posts_stream = posts.as_stream(client).await;
+        futures::pin_mut!(posts_stream);
+        fs::create_dir_all(&download_path).await?;
+        posts_stream
+            .map(|p| download_post(&download_path, client, &self.index, user, p))
+            .buffered(self.conf.max_connections)
+            .take_while(|r| {
+                let ret = match r.status {
+                    PostStatus::Skipped => false,
+                    PostStatus::Downloaded => true,
+                    PostStatus::Failed => {
+                        println!("Error: {}", r.error.join(": "));
+                        errored = true;
+                        true
+                    }
+                };
+                self.report.lock().unwrap().add(r.clone());
+                future::ready(ret)
+            })
+            .collect::<Vec<_>>()
+            .await;
+        Ok(errored)
+    }
+}
+
+#[async_trait(?Send)]
+impl Archiver for TwitterArchiver {
+    fn name(&self) -> &'static str {
+        "Twitter"
+    }
+
+    fn config_section(&self) -> &'static str {
+        SERVICE
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredPost>> {
+        let twitter_client = self.twitter_client()?;
+
+        let mut discovered = Vec::new();
+
+        for user in &self.conf.users {
+            let download_path = self.user_path(user);
+            let mut posts = twitter_client.posts(user).await?;
+            let posts_stream = posts.as_stream(&self.client).await;
+            futures::pin_mut!(posts_stream);
+            while let Some(post) = posts_stream.next().await {
+                let post = post?;
+                let slug = post.slug()?;
+                if self.index.contains(SERVICE, &post.id)? || download_path.join(&slug).exists() {
+                    break;
+                }
+                discovered.push(DiscoveredPost {
+                    account: user.clone(),
+                    id: post.id.clone(),
+                    path: download_path.join(&slug),
+                    slug,
+                });
+            }
+        }
+
+        Ok(discovered)
+    }
+
+    async fn download(&self) -> Result<()> {
+        let mut errored = false;
+        for user in &self.conf.users {
+            errored |= self.download_user(user).await?;
+        }
+
+        if errored {
+            Err(Error.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn take_report(&self) -> Report {
+        std::mem::take(&mut self.report.lock().unwrap())
+    }
+
+    fn poll_targets(&self) -> Vec<PollTarget> {
+        self.conf
+            .users
+            .iter()
+            .map(|user| PollTarget {
+                name: user.clone(),
+                interval: poll_interval([self.conf.poll_interval_mins]),
+            })
+            .collect()
+    }
+
+    async fn download_target(&self, target: &str) -> Result<()> {
+        if !self.conf.users.iter().any(|u| u == target) {
+            return Err(anyhow!("unknown Twitter user {}", target));
+        }
+        if self.download_user(target).await? {
+            Err(Error.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn download_dirs(&self) -> Vec<PathBuf> {
+        self.conf.users.iter().map(|u| self.user_path(u)).collect()
+    }
+
+    async fn migrate_index(&self) -> Result<usize> {
+        static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d{8}-(?P<id>\d+)-").unwrap());
+
+        let mut added = 0;
+        for user in &self.conf.users {
+            let posts = scan_dir(self.user_path(user), &RE)?;
+            added += self.index.migrate(SERVICE, posts).await?;
+        }
+        Ok(added)
+    }
+}
+
+#[derive(Debug)]
+struct Error;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "errors occured wile downloading Twitter posts")
+    }
+}
+
+impl std::error::Error for Error {}
+
+async fn download_post(
+    download_dir: impl AsRef<Path>,
+    client: &ClientWithMiddleware,
+    index: &ArchiveIndex,
+    account: &str,
+    post: Result<TwitterPost>,
+) -> PostRecord {
+    let record = PostRecord::new(SERVICE, account);
+    let (post, slug) = match post.and_then(|p| p.slug().map(|slug| (p, slug))) {
+        Ok(post) => post,
+        Err(e) => return record.failed(&e),
+    };
+    let record = record.post(&post.id, &slug);
+
+    let result = async {
+        let post_dir = download_dir.as_ref().join(&slug);
+        let existing = if post_dir.exists() {
+            vec![post_dir]
+        } else {
+            Vec::new()
+        };
+        if index.check(SERVICE, &post.id, &slug, existing).await? {
+            return Ok(None);
+        }
+
+        download_post_real(download_dir.as_ref(), client, index, &post)
+            .await
+            .map(Some)
+    }
+    .await;
+    record.finish(result)
+}
+
+async fn download_post_real(
+    path: impl AsRef<Path>,
+    client: &ClientWithMiddleware,
+    index: &ArchiveIndex,
+    post: &TwitterPost,
+) -> Result<IndexEntry> {
+    // Create temporary directory
+    let slug = post.slug()?;
+    let temp_dir = path.as_ref().join(format!(".{}.temp", slug));
+    fs::create_dir_all(&temp_dir).await?;
+    let final_dir = path.as_ref().join(&slug);
+
+    // Download to temp directory
+    post.download(client, &temp_dir).await?;
+
+    // Move temp directory to final location
+    fs::rename(&temp_dir, &final_dir).await?;
+    let entry = index
+        .insert_files(SERVICE, &post.id, &slug, vec![final_dir])
+        .await?;
+
+    println!("Downloaded {}", slug);
+
+    Ok(entry)
+}
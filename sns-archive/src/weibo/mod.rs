@@ -1,54 +1,184 @@
 use std::fmt::Display;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use futures::{future, StreamExt};
-use reqwest::Client;
-use sns_archive_common::SavablePost;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest_middleware::ClientWithMiddleware;
+use sns_archive_common::{
+    http_client, scan_dir, ArchiveIndex, Archiver, DiscoveredPost, IndexEntry, PollTarget,
+    PostRecord, PostStatus, Report, SavablePost,
+};
 use tokio::fs;
 use weibo::{WeiboClient, WeiboPost};
 
+use crate::config::daemon::poll_interval;
 use crate::config::weibo::{WeiboConfig, WeiboUserConfig};
 
-pub async fn download(conf: WeiboConfig) -> Result<()> {
-    let client = Client::new();
-    let weibo_client = WeiboClient::init(&client).await?;
+/// Service name in the archive index
+const SERVICE: &str = "weibo";
 
-    let mut errored = false;
+pub struct WeiboArchiver {
+    conf: WeiboConfig,
+    index: Arc<ArchiveIndex>,
+    client: ClientWithMiddleware,
+    report: Mutex<Report>,
+}
+
+impl WeiboArchiver {
+    pub fn new(conf: WeiboConfig, index: Arc<ArchiveIndex>) -> Self {
+        Self {
+            client: http_client(conf.rate_limit.policy(weibo::request_policy())),
+            conf,
+            index,
+            report: Mutex::new(Report::default()),
+        }
+    }
 
-    for WeiboUserConfig {
-        user,
-        download_path,
-    } in conf.users
-    {
-        // Download posts
-        let mut posts = weibo_client.posts(user).await?;
-        let posts_stream = posts.as_stream(&client).await;
+    /// Download new posts of a user, returns whether any post failed
+    async fn download_user(&self, user_config: &WeiboUserConfig) -> Result<bool> {
+        let client = &self.client;
+        let weibo_client = WeiboClient::init(client).await?;
+        let WeiboUserConfig {
+            user,
+            download_path,
+            ..
+        } = user_config;
+
+        let mut errored = false;
+        let mut posts = weibo_client.posts(*user).await?;
+        let posts_stream = posts.as_stream(client).await;
         futures::pin_mut!(posts_stream);
         fs::create_dir_all(&download_path).await?;
+        let account = user.to_string();
         posts_stream
-            .map(|p| download_post(&download_path, &client, p))
-            .buffered(conf.max_connections)
+            .map(|p| download_post(download_path, client, &self.index, &account, p))
+            .buffered(self.conf.max_connections)
             .take_while(|r| {
-                let ret = match r {
-                    Ok(DownloadStatus::Skipped) => false,
-                    Ok(DownloadStatus::Downloaded) => true,
-                    Err(e) => {
-                        println!("Error: {:?}", e);
+                let ret = match r.status {
+                    PostStatus::Skipped => false,
+                    PostStatus::Downloaded => true,
+                    PostStatus::Failed => {
+                        println!("Error: {}", r.error.join(": "));
                         errored = true;
                         true
                     }
                 };
+                self.report.lock().unwrap().add(r.clone());
                 future::ready(ret)
             })
             .collect::<Vec<_>>()
             .await;
+        Ok(errored)
+    }
+}
+
+#[async_trait(?Send)]
+impl Archiver for WeiboArchiver {
+    fn name(&self) -> &'static str {
+        "Weibo"
+    }
+
+    fn config_section(&self) -> &'static str {
+        SERVICE
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredPost>> {
+        let weibo_client = WeiboClient::init(&self.client).await?;
+
+        let mut discovered = Vec::new();
+
+        for WeiboUserConfig {
+            user,
+            download_path,
+            ..
+        } in &self.conf.users
+        {
+            let mut posts = weibo_client.posts(*user).await?;
+            let posts_stream = posts.as_stream(&self.client).await;
+            futures::pin_mut!(posts_stream);
+            while let Some(post) = posts_stream.next().await {
+                let post = post?;
+                let slug = post.slug()?;
+                if self.index.contains(SERVICE, &post.id.to_string())?
+                    || download_path.join(&slug).exists()
+                {
+                    break;
+                }
+                discovered.push(DiscoveredPost {
+                    account: user.to_string(),
+                    id: post.id.to_string(),
+                    path: download_path.join(&slug),
+                    slug,
+                });
+            }
+        }
+
+        Ok(discovered)
     }
 
-    if errored {
-        Err(Error.into())
-    } else {
-        Ok(())
+    async fn download(&self) -> Result<()> {
+        let mut errored = false;
+        for user_config in &self.conf.users {
+            errored |= self.download_user(user_config).await?;
+        }
+
+        if errored {
+            Err(Error.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn take_report(&self) -> Report {
+        std::mem::take(&mut self.report.lock().unwrap())
+    }
+
+    fn poll_targets(&self) -> Vec<PollTarget> {
+        self.conf
+            .users
+            .iter()
+            .map(|u| PollTarget {
+                name: u.user.to_string(),
+                interval: poll_interval([u.poll_interval_mins, self.conf.poll_interval_mins]),
+            })
+            .collect()
+    }
+
+    async fn download_target(&self, target: &str) -> Result<()> {
+        let user_config = self
+            .conf
+            .users
+            .iter()
+            .find(|u| u.user.to_string() == target)
+            .ok_or_else(|| anyhow!("unknown Weibo user {}", target))?;
+        if self.download_user(user_config).await? {
+            Err(Error.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn download_dirs(&self) -> Vec<PathBuf> {
+        self.conf
+            .users
+            .iter()
+            .map(|u| u.download_path.clone())
+            .collect()
+    }
+
+    async fn migrate_index(&self) -> Result<usize> {
+        static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d{8}-(?P<id>\d+)-").unwrap());
+
+        let mut added = 0;
+        for user_config in &self.conf.users {
+            let posts = scan_dir(&user_config.download_path, &RE)?;
+            added += self.index.migrate(SERVICE, posts).await?;
+        }
+        Ok(added)
     }
 }
 
@@ -63,32 +193,46 @@ impl Display for Error {
 
 impl std::error::Error for Error {}
 
-#[derive(Debug)]
-enum DownloadStatus {
-    Downloaded,
-    Skipped,
-}
-
 async fn download_post(
     download_dir: impl AsRef<Path>,
-    client: &Client,
+    client: &ClientWithMiddleware,
+    index: &ArchiveIndex,
+    account: &str,
     post: Result<WeiboPost>,
-) -> Result<DownloadStatus> {
-    let post = post?;
-    let slug = post.slug()?;
-    if download_dir.as_ref().join(&slug).exists() {
-        return Ok(DownloadStatus::Skipped);
+) -> PostRecord {
+    let record = PostRecord::new(SERVICE, account);
+    let (post, slug) = match post.and_then(|p| p.slug().map(|slug| (p, slug))) {
+        Ok(post) => post,
+        Err(e) => return record.failed(&e),
+    };
+    let post_id = post.id.to_string();
+    let record = record.post(&post_id, &slug);
+
+    let result = async {
+        let post_dir = download_dir.as_ref().join(&slug);
+        let existing = if post_dir.exists() {
+            vec![post_dir]
+        } else {
+            Vec::new()
+        };
+        if index.check(SERVICE, &post_id, &slug, existing).await? {
+            return Ok(None);
+        }
+
+        download_post_real(download_dir.as_ref(), client, index, &post)
+            .await
+            .map(Some)
     }
-
-    download_post_real(download_dir.as_ref(), client, &post).await?;
-    Ok(DownloadStatus::Downloaded)
+    .await;
+    record.finish(result)
 }
 
 async fn download_post_real(
     path: impl AsRef<Path>,
-    client: &Client,
+    client: &ClientWithMiddleware,
+    index: &ArchiveIndex,
     post: &WeiboPost,
-) -> Result<()> {
+) -> Result<IndexEntry> {
     // Create temporary directory
     let slug = post.slug()?;
     let temp_dir = path.as_ref().join(format!(".{}.temp", post.slug()?));
@@ -100,8 +244,11 @@ async fn download_post_real(
 
     // Move temp directory to final location
     fs::rename(&temp_dir, &final_dir).await?;
+    let entry = index
+        .insert_files(SERVICE, &post.id.to_string(), &slug, vec![final_dir])
+        .await?;
 
     println!("Downloaded {}", slug);
 
-    Ok(())
+    Ok(entry)
 }
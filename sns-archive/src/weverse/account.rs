@@ -0,0 +1,113 @@
+//! Saved Weverse logins, managed with `sns-archive weverse login`, `logout` and `status`, and
+//! the communities they joined
+
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use sns_archive_common::http_client;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use weverse::{AuthenticatedWeverseClient, LoginInfo, Tokens};
+
+use crate::config::weverse::WeverseConfig;
+
+/// Save the tokens of a browser session after checking they work
+///
+/// The tokens are read from a cookie export if one is given, otherwise they are asked for. They
+/// are saved for `email`, or the default account of the config.
+pub async fn login(
+    conf: &WeverseConfig,
+    email: Option<&str>,
+    cookies: Option<&Path>,
+) -> Result<()> {
+    let email = email.unwrap_or(&conf.email);
+    let tokens = match cookies {
+        Some(path) => Tokens::from_cookies(&std::fs::read_to_string(path)?)?,
+        None => {
+            println!(
+                "Log in to weverse.io in a browser and copy the we2_access_token and \
+                we2_refresh_token cookies"
+            );
+            Tokens {
+                access: prompt("Access token: ")?,
+                refresh: prompt("Refresh token: ")?,
+            }
+        }
+    };
+
+    let client = http_client(conf.rate_limit.policy(Default::default()));
+    let login_info = LoginInfo::new(email);
+    AuthenticatedWeverseClient::login_with_tokens(&client, &login_info, &tokens).await?;
+    println!("weverse: logged in as {}", email);
+    Ok(())
+}
+
+/// Delete the saved tokens of `email`, or the default account of the config
+pub async fn logout(conf: &WeverseConfig, email: Option<&str>) -> Result<()> {
+    let email = email.unwrap_or(&conf.email);
+    if weverse::logout(&LoginInfo::new(email)).await? {
+        println!("weverse: logged out {}", email);
+    } else {
+        println!("weverse: {} is not logged in", email);
+    }
+    Ok(())
+}
+
+/// Print when the saved tokens of `email` expire, or of every account in the config
+pub async fn status(conf: &WeverseConfig, email: Option<&str>) -> Result<()> {
+    let emails = match email {
+        Some(email) => vec![email],
+        None => conf.emails(),
+    };
+    for email in emails {
+        let Some(status) = weverse::login_status(&LoginInfo::new(email)).await? else {
+            println!("weverse: {} is not logged in", email);
+            continue;
+        };
+        println!("weverse: logged in as {}", email);
+        println!("  access token {}", expiry(status.access_expiry)?);
+        println!("  refresh token {}", expiry(status.refresh_expiry)?);
+    }
+    Ok(())
+}
+
+/// Print the communities joined by `email`, or the default account of the config
+pub async fn communities(conf: &WeverseConfig, email: Option<&str>) -> Result<()> {
+    let email = email.unwrap_or(&conf.email);
+    let client = http_client(conf.rate_limit.policy(Default::default()));
+    let weverse = AuthenticatedWeverseClient::login(&client, &LoginInfo::new(email)).await?;
+    for community in weverse.joined_communities().await? {
+        println!(
+            "{}\t{}\thttps://weverse.io/{}",
+            community.id.id(),
+            community.name,
+            community.url_path
+        );
+    }
+    Ok(())
+}
+
+fn expiry(time: Option<OffsetDateTime>) -> Result<String> {
+    let Some(time) = time else {
+        return Ok("expiry unknown".to_owned());
+    };
+    let formatted = time.format(&Rfc3339)?;
+    if time <= OffsetDateTime::now_utc() {
+        Ok(format!("expired {}", formatted))
+    } else {
+        Ok(format!("expires {}", formatted))
+    }
+}
+
+fn prompt(message: &str) -> Result<String> {
+    print!("{}", message);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    let line = line.trim();
+    if line.is_empty() {
+        return Err(anyhow!("no token given"));
+    }
+    Ok(line.to_owned())
+}
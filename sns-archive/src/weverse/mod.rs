@@ -1,121 +1,734 @@
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Display;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use futures::{future, stream, StreamExt};
-use reqwest::Client;
-use sns_archive_common::{SavablePost, osstr_starts_with};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::Serialize;
+use sns_archive_common::{
+    http_client, osstr_starts_with, scan_dir, set_mtime, ArchiveIndex, Archiver, DiscoveredPost,
+    IndexEntry, PollTarget, PostRecord, PostStatus, Report, SavablePost,
+};
 use tokio::fs;
-use weverse::endpoint::artist_tab_posts::ArtistPostShort;
+use weverse::endpoint::artist_tab_posts::{ArtistPostShort, ArtistPosts};
+use weverse::endpoint::comments::Comment;
+use weverse::endpoint::letter::Letter;
+use weverse::endpoint::media::MediaPost;
+use weverse::endpoint::notice::{Notice, NoticeShort};
 use weverse::endpoint::post::ArtistPost;
-use weverse::{AuthenticatedWeverseClient, LoginInfo};
-
-use crate::config::weverse::WeverseConfig;
-
-pub async fn download(conf: WeverseConfig) -> Result<()> {
-    let client = Client::new();
-    let login_info = LoginInfo::new(&conf.email);
-    let weverse_client = AuthenticatedWeverseClient::login(&client, &login_info).await?;
-
-    let mut errored = false;
-
-    for (artist, artist_config) in conf.artists {
-        // Download posts
-        if let Some(artist_download_path) = &artist_config.artist_download_path {
-            println!("Downloading {} posts", artist);
-            let mut posts = weverse_client
-                .artist_posts(
-                    &artist,
-                    artist_config.artist_stop_id,
-                    artist_config.artist_download_limit,
-                )
-                .await?;
-            let posts_stream = posts.as_stream(&client).await;
-            futures::pin_mut!(posts_stream);
-            fs::create_dir_all(artist_download_path).await?;
-            posts_stream
-                .map(|p| download_post(artist_download_path, &client, &weverse_client, p))
-                .buffered(conf.max_connections)
-                .take_while(|r| {
-                    let ret = match r {
-                        Ok(DownloadStatus::Skipped) => {
-                            artist_config.artist_download_limit.is_some()
-                        }
-                        Ok(DownloadStatus::Downloaded) => true,
-                        Err(e) => {
-                            println!("Error: {:?}", e);
-                            errored = true;
-                            true
-                        }
+use weverse::{AuthenticatedWeverseClient, LoginInfo, WeverseError};
+
+use crate::config::daemon::poll_interval;
+use crate::config::weverse::{ArtistConfig, WeverseConfig};
+
+pub mod account;
+
+/// Service name in the archive index
+const SERVICE: &str = "weverse";
+
+pub struct WeverseArchiver {
+    conf: WeverseConfig,
+    index: Arc<ArchiveIndex>,
+    client: ClientWithMiddleware,
+    /// Logged in clients by email, kept between downloads so the daemon only logs in once
+    weverse_clients: tokio::sync::Mutex<HashMap<String, AuthenticatedWeverseClient>>,
+    report: Mutex<Report>,
+}
+
+/// Parts of an artist that are downloaded separately
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Section {
+    Posts,
+    Moments,
+    Lives,
+    Comments,
+    Media,
+    Notices,
+    Letters,
+}
+
+impl Section {
+    const ALL: [Section; 7] = [
+        Section::Posts,
+        Section::Moments,
+        Section::Lives,
+        Section::Comments,
+        Section::Media,
+        Section::Notices,
+        Section::Letters,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Posts => "posts",
+            Self::Moments => "moments",
+            Self::Lives => "lives",
+            Self::Comments => "comments",
+            Self::Media => "media",
+            Self::Notices => "notices",
+            Self::Letters => "letters",
+        }
+    }
+}
+
+impl WeverseArchiver {
+    pub fn new(conf: WeverseConfig, index: Arc<ArchiveIndex>) -> Self {
+        Self {
+            client: http_client(conf.rate_limit.policy(Default::default())),
+            conf,
+            index,
+            weverse_clients: tokio::sync::Mutex::new(HashMap::new()),
+            report: Mutex::new(Report::default()),
+        }
+    }
+
+    fn record(&self, post: &PostRecord) {
+        if post.status == PostStatus::Failed {
+            println!("Error: {}", post.error.join(": "));
+        }
+        self.report.lock().unwrap().add(post.clone());
+    }
+
+    /// Record the result of a post and return whether to continue with the older posts of its
+    /// section
+    ///
+    /// A section stops at the first post that was already downloaded, unless `past_skipped` as
+    /// posts older than it may still be missing. Failed posts set `errored` but don't stop it.
+    fn keep_going(&self, post: &PostRecord, past_skipped: bool, errored: &mut bool) -> bool {
+        self.record(post);
+        match post.status {
+            PostStatus::Skipped => past_skipped,
+            PostStatus::Downloaded => true,
+            PostStatus::Failed => {
+                *errored = true;
+                true
+            }
+        }
+    }
+
+    /// Logged in Weverse client of an account, logs in on first use and refreshes the token
+    /// afterwards
+    async fn weverse_client(&self, email: &str) -> Result<AuthenticatedWeverseClient> {
+        let mut weverse_clients = self.weverse_clients.lock().await;
+        if let Some(weverse_client) = weverse_clients.get_mut(email) {
+            match weverse_client.refresh().await {
+                Ok(()) => return Ok(weverse_client.clone()),
+                // Log in with the saved tokens again, the account may have logged in since
+                Err(e) if matches!(e.downcast_ref(), Some(WeverseError::TokenExpired { .. })) => {
+                    weverse_clients.remove(email);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let login_info = LoginInfo::new(email);
+        let weverse_client = AuthenticatedWeverseClient::login(&self.client, &login_info).await?;
+        weverse_clients.insert(email.to_owned(), weverse_client.clone());
+        Ok(weverse_client)
+    }
+
+    /// Download a section of an artist, returns whether any post failed
+    async fn download_section(
+        &self,
+        weverse_client: &AuthenticatedWeverseClient,
+        artist: &str,
+        artist_config: &ArtistConfig,
+        section: Section,
+    ) -> Result<bool> {
+        let client = &self.client;
+        let mut errored = false;
+
+        match section {
+            Section::Posts => {
+                let Some(artist_download_path) = &artist_config.artist_download_path else {
+                    return Ok(false);
+                };
+                println!("Downloading {} posts", artist);
+                let mut posts = weverse_client
+                    .artist_posts(
+                        artist,
+                        artist_config.artist_stop_id.clone(),
+                        artist_config.artist_download_limit,
+                        artist_config.member_filter(),
+                        artist_config.date_range(),
+                    )
+                    .await?;
+                let posts_stream = posts.as_stream(client).await;
+                futures::pin_mut!(posts_stream);
+                fs::create_dir_all(artist_download_path).await?;
+                posts_stream
+                    .map(|p| {
+                        download_post(
+                            artist_download_path,
+                            client,
+                            weverse_client,
+                            &self.index,
+                            artist,
+                            artist_config,
+                            p,
+                        )
+                    })
+                    .buffered(self.conf.max_connections)
+                    .take_while(|r| {
+                        let past_skipped = artist_config.artist_download_limit.is_some()
+                            || artist_config.date_range().is_bounded();
+                        future::ready(self.keep_going(r, past_skipped, &mut errored))
+                    })
+                    .collect::<Vec<_>>()
+                    .await;
+            }
+            Section::Moments => {
+                let Some(moments_download_path) = &artist_config.moments_download_path else {
+                    return Ok(false);
+                };
+                println!("Downloading {} moments", artist);
+                let latest_moments = weverse_client
+                    .artist_moments(artist, &artist_config.member_filter())
+                    .await?;
+                fs::create_dir_all(moments_download_path).await?;
+                stream::iter(latest_moments.iter())
+                    .map(|p| {
+                        download_member_moments(
+                            moments_download_path,
+                            client,
+                            weverse_client,
+                            &self.index,
+                            artist,
+                            artist_config,
+                            p.clone(),
+                        )
+                    })
+                    .buffer_unordered(self.conf.max_connections)
+                    .collect::<Vec<_>>()
+                    .await
+                    .into_iter()
+                    .flatten()
+                    .for_each(|r| {
+                        // A skipped moment doesn't stop the moments of other members
+                        self.keep_going(&r, true, &mut errored);
+                    });
+            }
+            Section::Lives => {
+                let Some(lives_download_path) = &artist_config.lives_download_path else {
+                    return Ok(false);
+                };
+                println!("Downloading {} lives", artist);
+                let mut posts = weverse_client
+                    .lives(
+                        artist,
+                        artist_config.lives_stop_id.clone(),
+                        artist_config.lives_download_limit,
+                        artist_config.member_filter(),
+                        artist_config.date_range(),
+                    )
+                    .await?;
+                let posts_stream = posts.as_stream(client).await;
+                futures::pin_mut!(posts_stream);
+                fs::create_dir_all(lives_download_path).await?;
+                posts_stream
+                    .map(|p| {
+                        download_live(
+                            lives_download_path,
+                            client,
+                            weverse_client,
+                            &self.index,
+                            artist,
+                            artist_config,
+                            p,
+                        )
+                    })
+                    .buffered(self.conf.max_connections)
+                    .take_while(|r| {
+                        let past_skipped = artist_config.lives_download_limit.is_some()
+                            || artist_config.date_range().is_bounded();
+                        future::ready(self.keep_going(r, past_skipped, &mut errored))
+                    })
+                    .collect::<Vec<_>>()
+                    .await;
+            }
+            Section::Comments => {
+                let Some(comments_download_path) = &artist_config.artist_comments_download_path
+                else {
+                    return Ok(false);
+                };
+                println!("Downloading {} comments", artist);
+                let mut comments = weverse_client.community_artist_comments(artist).await?;
+                let comments_stream = comments.as_stream(client).await;
+                futures::pin_mut!(comments_stream);
+                fs::create_dir_all(comments_download_path).await?;
+                comments_stream
+                    .map(|c| download_comment(comments_download_path, &self.index, artist, c))
+                    .buffered(self.conf.max_connections)
+                    .take_while(|r| future::ready(self.keep_going(r, false, &mut errored)))
+                    .collect::<Vec<_>>()
+                    .await;
+            }
+            Section::Media => {
+                let Some(media_download_path) = &artist_config.media_download_path else {
+                    return Ok(false);
+                };
+                println!("Downloading {} media", artist);
+                fs::create_dir_all(media_download_path).await?;
+                for category_id in media_category_ids(weverse_client, artist, artist_config).await?
+                {
+                    let mut posts = weverse_client.media(artist, category_id).await?;
+                    let posts_stream = posts.as_stream(client).await;
+                    futures::pin_mut!(posts_stream);
+                    posts_stream
+                        .map(|p| {
+                            download_post_dir(
+                                media_download_path,
+                                client,
+                                &self.index,
+                                artist,
+                                |p: &MediaPost| p.id().to_owned(),
+                                p.map(|mut p| {
+                                    p.set_video_policy(artist_config.video_policy());
+                                    p
+                                }),
+                            )
+                        })
+                        .buffered(self.conf.max_connections)
+                        .take_while(|r| future::ready(self.keep_going(r, false, &mut errored)))
+                        .collect::<Vec<_>>()
+                        .await;
+                }
+            }
+            Section::Notices => {
+                let Some(notices_download_path) = &artist_config.notices_download_path else {
+                    return Ok(false);
+                };
+                println!("Downloading {} notices", artist);
+                let mut notices = weverse_client.notices(artist).await?;
+                let notices_stream = notices.as_stream(client).await;
+                futures::pin_mut!(notices_stream);
+                fs::create_dir_all(notices_download_path).await?;
+                notices_stream
+                    .map(|n| {
+                        download_notice(
+                            notices_download_path,
+                            client,
+                            weverse_client,
+                            &self.index,
+                            artist,
+                            n,
+                        )
+                    })
+                    .buffered(self.conf.max_connections)
+                    .take_while(|r| future::ready(self.keep_going(r, false, &mut errored)))
+                    .collect::<Vec<_>>()
+                    .await;
+            }
+            Section::Letters => {
+                let Some(letters_download_path) = &artist_config.letters_download_path else {
+                    return Ok(false);
+                };
+                println!("Downloading {} letters", artist);
+                let mut letters = weverse_client.letters(artist).await?;
+                let letters_stream = letters.as_stream(client).await;
+                futures::pin_mut!(letters_stream);
+                fs::create_dir_all(letters_download_path).await?;
+                letters_stream
+                    .map(|l| {
+                        download_post_dir(
+                            letters_download_path,
+                            client,
+                            &self.index,
+                            artist,
+                            |l: &Letter| letter_index_id(l.id()),
+                            l,
+                        )
+                    })
+                    .buffered(self.conf.max_connections)
+                    .take_while(|r| future::ready(self.keep_going(r, false, &mut errored)))
+                    .collect::<Vec<_>>()
+                    .await;
+            }
+        }
+
+        Ok(errored)
+    }
+}
+
+#[async_trait(?Send)]
+impl Archiver for WeverseArchiver {
+    fn name(&self) -> &'static str {
+        "Weverse"
+    }
+
+    fn config_section(&self) -> &'static str {
+        SERVICE
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredPost>> {
+        let client = &self.client;
+        let mut discovered = Vec::new();
+
+        for (artist, artist_config) in &self.conf.artists {
+            let weverse_client = self.weverse_client(self.conf.email(artist_config)).await?;
+
+            // Discover posts
+            if let Some(artist_download_path) = &artist_config.artist_download_path {
+                let mut posts = weverse_client
+                    .artist_posts(
+                        artist,
+                        artist_config.artist_stop_id.clone(),
+                        artist_config.artist_download_limit,
+                        artist_config.member_filter(),
+                        artist_config.date_range(),
+                    )
+                    .await?;
+                discovered.extend(
+                    discover_posts(
+                        artist,
+                        artist_download_path,
+                        client,
+                        &self.index,
+                        &mut posts,
+                        artist_config.artist_download_limit.is_some()
+                            || artist_config.date_range().is_bounded(),
+                        false,
+                    )
+                    .await?,
+                );
+            }
+
+            // Discover moments
+            if let Some(moments_download_path) = &artist_config.moments_download_path {
+                let latest_moments = weverse_client
+                    .artist_moments(artist, &artist_config.member_filter())
+                    .await?;
+                let mut visited: HashSet<_> =
+                    latest_moments.iter().map(|m| m.id().to_owned()).collect();
+                let mut queue = VecDeque::from(latest_moments);
+                while let Some(post) = queue.pop_front() {
+                    let slug = post.slug()?;
+                    let exists = self.index.contains(SERVICE, post.id())?
+                        || moments_download_path.join(&slug).exists();
+                    if exists && !artist_config.moments_backfill {
+                        continue;
+                    }
+                    if !exists {
+                        discovered.push(DiscoveredPost {
+                            account: artist.clone(),
+                            id: post.id().to_owned(),
+                            path: moments_download_path.join(&slug),
+                            slug,
+                        });
+                    }
+                    let newer_moment_id = if artist_config.moments_backfill {
+                        post.newer_moment_id()
+                    } else {
+                        None
                     };
-                    future::ready(ret)
-                })
-                .collect::<Vec<_>>()
-                .await;
-        }
-
-        // Download moments
-        if let Some(moments_download_path) = &artist_config.moments_download_path {
-            println!("Downloading {} moments", artist);
-            let latest_moments = weverse_client.artist_moments(&artist).await?;
-            fs::create_dir_all(moments_download_path).await?;
-            stream::iter(latest_moments.iter())
-                .map(|p| {
-                    download_member_moments(
-                        moments_download_path,
-                        &client,
-                        &weverse_client,
-                        p.clone(),
+                    for moment_id in [post.next_moment_id(), newer_moment_id]
+                        .into_iter()
+                        .flatten()
+                    {
+                        if visited.insert(moment_id.clone()) {
+                            queue.push_back(weverse_client.post(&moment_id).await?);
+                        }
+                    }
+                }
+            }
+
+            // Discover lives
+            if let Some(lives_download_path) = &artist_config.lives_download_path {
+                let mut posts = weverse_client
+                    .lives(
+                        artist,
+                        artist_config.lives_stop_id.clone(),
+                        artist_config.lives_download_limit,
+                        artist_config.member_filter(),
+                        artist_config.date_range(),
+                    )
+                    .await?;
+                discovered.extend(
+                    discover_posts(
+                        artist,
+                        lives_download_path,
+                        client,
+                        &self.index,
+                        &mut posts,
+                        artist_config.lives_download_limit.is_some()
+                            || artist_config.date_range().is_bounded(),
+                        true,
                     )
-                })
-                .buffer_unordered(conf.max_connections)
-                .collect::<Vec<_>>()
-                .await
-                .into_iter()
-                .filter_map(|r| r.err())
-                .for_each(|e| {
-                    println!("Error: {:?}", e);
-                    errored = true;
-                });
-        }
-
-        // Download lives
-        if let Some(lives_download_path) = &artist_config.lives_download_path {
-            println!("Downloading {} lives", artist);
-            let mut posts = weverse_client
-                .lives(
-                    &artist,
-                    artist_config.lives_stop_id,
-                    artist_config.lives_download_limit,
-                )
-                .await?;
-            let posts_stream = posts.as_stream(&client).await;
-            futures::pin_mut!(posts_stream);
-            fs::create_dir_all(lives_download_path).await?;
-            posts_stream
-                .map(|p| download_live(lives_download_path, &client, &weverse_client, p))
-                .buffered(conf.max_connections)
-                .take_while(|r| {
-                    let ret = match r {
-                        Ok(DownloadStatus::Skipped) => artist_config.lives_download_limit.is_some(),
-                        Ok(DownloadStatus::Downloaded) => true,
-                        Err(e) => {
-                            println!("Error: {:?}", e);
-                            errored = true;
-                            true
+                    .await?,
+                );
+            }
+
+            // Discover comments
+            if let Some(comments_download_path) = &artist_config.artist_comments_download_path {
+                let mut comments = weverse_client.community_artist_comments(artist).await?;
+                let comments_stream = comments.as_stream(client).await;
+                futures::pin_mut!(comments_stream);
+                while let Some(comment) = comments_stream.next().await {
+                    let comment = comment?;
+                    let slug = comment.slug()?;
+                    let path = comments_download_path.join(format!("{}.json", slug));
+                    if self
+                        .index
+                        .contains(SERVICE, &comment_index_id(&comment.id))?
+                        || path.exists()
+                    {
+                        break;
+                    }
+                    discovered.push(DiscoveredPost {
+                        account: artist.clone(),
+                        id: comment.id,
+                        path,
+                        slug,
+                    });
+                }
+            }
+
+            // Discover media
+            if let Some(media_download_path) = &artist_config.media_download_path {
+                for category_id in
+                    media_category_ids(&weverse_client, artist, artist_config).await?
+                {
+                    let mut posts = weverse_client.media(artist, category_id).await?;
+                    let posts_stream = posts.as_stream(client).await;
+                    futures::pin_mut!(posts_stream);
+                    while let Some(post) = posts_stream.next().await {
+                        let post = post?;
+                        let slug = post.slug()?;
+                        if self.index.contains(SERVICE, post.id())?
+                            || media_download_path.join(&slug).exists()
+                        {
+                            break;
                         }
-                    };
-                    future::ready(ret)
-                })
-                .collect::<Vec<_>>()
-                .await;
+                        discovered.push(DiscoveredPost {
+                            account: artist.clone(),
+                            id: post.id().to_owned(),
+                            path: media_download_path.join(&slug),
+                            slug,
+                        });
+                    }
+                }
+            }
+
+            // Discover notices
+            if let Some(notices_download_path) = &artist_config.notices_download_path {
+                let mut notices = weverse_client.notices(artist).await?;
+                let notices_stream = notices.as_stream(client).await;
+                futures::pin_mut!(notices_stream);
+                while let Some(notice) = notices_stream.next().await {
+                    let notice = notice?;
+                    let slug = notice.slug()?;
+                    let id = notice_index_id(notice.id);
+                    if self.index.contains(SERVICE, &id)?
+                        || notices_download_path.join(&slug).exists()
+                    {
+                        break;
+                    }
+                    discovered.push(DiscoveredPost {
+                        account: artist.clone(),
+                        id,
+                        path: notices_download_path.join(&slug),
+                        slug,
+                    });
+                }
+            }
+
+            // Discover letters
+            if let Some(letters_download_path) = &artist_config.letters_download_path {
+                let mut letters = weverse_client.letters(artist).await?;
+                let letters_stream = letters.as_stream(client).await;
+                futures::pin_mut!(letters_stream);
+                while let Some(letter) = letters_stream.next().await {
+                    let letter = letter?;
+                    let slug = letter.slug()?;
+                    let id = letter_index_id(letter.id());
+                    if self.index.contains(SERVICE, &id)?
+                        || letters_download_path.join(&slug).exists()
+                    {
+                        break;
+                    }
+                    discovered.push(DiscoveredPost {
+                        account: artist.clone(),
+                        id,
+                        path: letters_download_path.join(&slug),
+                        slug,
+                    });
+                }
+            }
         }
+
+        Ok(discovered)
     }
 
-    if errored {
-        Err(Error.into())
-    } else {
-        Ok(())
+    async fn download(&self) -> Result<()> {
+        let mut errored = false;
+        for (artist, artist_config) in &self.conf.artists {
+            let weverse_client = self.weverse_client(self.conf.email(artist_config)).await?;
+            for section in Section::ALL {
+                errored |= self
+                    .download_section(&weverse_client, artist, artist_config, section)
+                    .await?;
+            }
+        }
+
+        if errored {
+            Err(Error.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn take_report(&self) -> Report {
+        std::mem::take(&mut self.report.lock().unwrap())
+    }
+
+    /// Every configured section of every artist is polled on its own
+    fn poll_targets(&self) -> Vec<PollTarget> {
+        let mut targets = Vec::new();
+        for (artist, artist_config) in &self.conf.artists {
+            let sections = [
+                (
+                    &artist_config.artist_download_path,
+                    artist_config.artist_poll_interval_mins,
+                    Section::Posts,
+                ),
+                (
+                    &artist_config.moments_download_path,
+                    artist_config.moments_poll_interval_mins,
+                    Section::Moments,
+                ),
+                (
+                    &artist_config.lives_download_path,
+                    artist_config.lives_poll_interval_mins,
+                    Section::Lives,
+                ),
+                (
+                    &artist_config.artist_comments_download_path,
+                    artist_config.artist_comments_poll_interval_mins,
+                    Section::Comments,
+                ),
+                (
+                    &artist_config.media_download_path,
+                    artist_config.media_poll_interval_mins,
+                    Section::Media,
+                ),
+                (
+                    &artist_config.notices_download_path,
+                    artist_config.notices_poll_interval_mins,
+                    Section::Notices,
+                ),
+                (
+                    &artist_config.letters_download_path,
+                    artist_config.letters_poll_interval_mins,
+                    Section::Letters,
+                ),
+            ];
+            for (path, interval, section) in sections {
+                if path.is_some() {
+                    targets.push(PollTarget {
+                        name: format!("{}/{}", artist, section.name()),
+                        interval: poll_interval([interval, self.conf.poll_interval_mins]),
+                    });
+                }
+            }
+        }
+        targets
+    }
+
+    async fn download_target(&self, target: &str) -> Result<()> {
+        let (artist, artist_config, section) = target
+            .rsplit_once('/')
+            .and_then(|(artist, section)| {
+                let section = Section::ALL.into_iter().find(|s| s.name() == section)?;
+                let (artist, artist_config) = self.conf.artists.get_key_value(artist)?;
+                Some((artist, artist_config, section))
+            })
+            .ok_or_else(|| anyhow!("unknown Weverse poll target {}", target))?;
+
+        let weverse_client = self.weverse_client(self.conf.email(artist_config)).await?;
+        if self
+            .download_section(&weverse_client, artist, artist_config, section)
+            .await?
+        {
+            Err(Error.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn download_dirs(&self) -> Vec<PathBuf> {
+        self.conf
+            .artists
+            .values()
+            .flat_map(|a| {
+                [
+                    &a.artist_download_path,
+                    &a.moments_download_path,
+                    &a.lives_download_path,
+                    &a.artist_comments_download_path,
+                    &a.media_download_path,
+                    &a.notices_download_path,
+                    &a.letters_download_path,
+                ]
+            })
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    async fn migrate_index(&self) -> Result<usize> {
+        // Posts, moments, lives, media and comments all start with the date and post ID
+        static POST_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d{8}-(?P<id>\d+-\d+)-").unwrap());
+        static NOTICE_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^\d{8}-(?P<id>\d+)-notice-").unwrap());
+        static LETTER_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r"^\d{8}-(?P<id>[^-]+-[^-]+)-").unwrap());
+
+        let mut added = 0;
+        for artist_config in self.conf.artists.values() {
+            // Directory, pattern of its entries and index ID of the captured ID
+            let dirs: [(&Option<PathBuf>, &Regex, fn(&str) -> String); 7] = [
+                (&artist_config.artist_download_path, &POST_RE, str::to_owned),
+                (
+                    &artist_config.moments_download_path,
+                    &POST_RE,
+                    str::to_owned,
+                ),
+                (&artist_config.lives_download_path, &POST_RE, str::to_owned),
+                (&artist_config.media_download_path, &POST_RE, str::to_owned),
+                (
+                    &artist_config.artist_comments_download_path,
+                    &POST_RE,
+                    comment_index_id,
+                ),
+                (&artist_config.notices_download_path, &NOTICE_RE, |id| {
+                    notice_index_id(id)
+                }),
+                (
+                    &artist_config.letters_download_path,
+                    &LETTER_RE,
+                    letter_index_id,
+                ),
+            ];
+            for (dir, re, index_id) in dirs {
+                let Some(dir) = dir else {
+                    continue;
+                };
+                let posts = scan_dir(dir, re)?
+                    .into_iter()
+                    .map(|mut post| {
+                        post.post_id = index_id(&post.post_id);
+                        post
+                    })
+                    .collect();
+                added += self.index.migrate(SERVICE, posts).await?;
+            }
+        }
+        Ok(added)
     }
 }
 
@@ -130,106 +743,520 @@ impl Display for Error {
 
 impl std::error::Error for Error {}
 
-#[derive(Debug)]
-enum DownloadStatus {
-    Downloaded,
-    Skipped,
+/// Collect posts from `posts` until an already downloaded one is found, or keep going past
+/// downloaded posts if `skip_existing` is set
+async fn discover_posts(
+    artist: &str,
+    download_dir: impl AsRef<Path>,
+    client: &ClientWithMiddleware,
+    index: &ArchiveIndex,
+    posts: &mut ArtistPosts,
+    skip_existing: bool,
+    live: bool,
+) -> Result<Vec<DiscoveredPost>> {
+    let mut discovered = Vec::new();
+    let posts_stream = posts.as_stream(client).await;
+    futures::pin_mut!(posts_stream);
+    while let Some(post) = posts_stream.next().await {
+        let post = post?;
+        let slug = post.slug()?;
+        let exists = if live {
+            !live_files(download_dir.as_ref(), &slug).await?.is_empty()
+        } else {
+            download_dir.as_ref().join(&slug).exists()
+        };
+        if exists || index.contains(SERVICE, &post.post_id)? {
+            if skip_existing {
+                continue;
+            }
+            break;
+        }
+        discovered.push(DiscoveredPost {
+            account: artist.to_owned(),
+            id: post.post_id.clone(),
+            path: download_dir.as_ref().join(&slug),
+            slug,
+        });
+    }
+    Ok(discovered)
+}
+
+/// Lives are saved as files rather than directories, find all files starting with the slug
+async fn live_files(download_dir: impl AsRef<Path>, slug: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut read_dir = match fs::read_dir(download_dir.as_ref()).await {
+        Ok(r) => r,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(f) = read_dir.next_entry().await? {
+        if osstr_starts_with(&f.file_name(), slug) {
+            files.push(f.path());
+        }
+    }
+    Ok(files)
+}
+
+/// Posts are saved as a directory named after the slug
+fn post_dir(download_dir: impl AsRef<Path>, slug: &str) -> Vec<PathBuf> {
+    let dir = download_dir.as_ref().join(slug);
+    if dir.exists() {
+        vec![dir]
+    } else {
+        Vec::new()
+    }
 }
 
 async fn download_live(
     download_dir: impl AsRef<Path>,
-    client: &Client,
-    weverse_client: &AuthenticatedWeverseClient<'_>,
+    client: &ClientWithMiddleware,
+    weverse_client: &AuthenticatedWeverseClient,
+    index: &ArchiveIndex,
+    artist: &str,
+    artist_config: &ArtistConfig,
     post: Result<ArtistPostShort>,
-) -> Result<DownloadStatus> {
-    let post = post?;
-    let slug = post.slug()?;
-    let mut read_dir = fs::read_dir(download_dir.as_ref()).await?;
-    while let Some(f) = read_dir.next_entry().await? {
-        if osstr_starts_with(&f.file_name(), &slug) {
-            return Ok(DownloadStatus::Skipped);
+) -> PostRecord {
+    let record = PostRecord::new(SERVICE, artist);
+    let (post, slug) = match post.and_then(|p| p.slug().map(|slug| (p, slug))) {
+        Ok(post) => post,
+        Err(e) => return record.failed(&e),
+    };
+    let record = record.post(&post.post_id, &slug);
+
+    let result = async {
+        let existing = live_files(download_dir.as_ref(), &slug).await?;
+        if index.check(SERVICE, &post.post_id, &slug, existing).await? {
+            return Ok(None);
         }
-    }
 
-    // Create temporary directory
-    let temp_dir = download_dir
-        .as_ref()
-        .join(format!(".{}.temp", post.slug()?));
-    fs::create_dir_all(&temp_dir).await?;
+        // Create temporary directory
+        let temp_dir = download_dir.as_ref().join(format!(".{}.temp", slug));
+        fs::create_dir_all(&temp_dir).await?;
 
-    // Download to temporary directory
-    let post = weverse_client.post(&post.post_id).await?;
-    post.download(client, &temp_dir).await?;
+        // Download to temporary directory
+        let mut post = weverse_client.post(&post.post_id).await?;
+        post.set_video_policy(artist_config.video_policy());
+        post.download(client, &temp_dir).await?;
 
-    // Move files out of temporary directory
-    let mut read_dir = fs::read_dir(&temp_dir).await?;
-    while let Some(f) = read_dir.next_entry().await? {
-        fs::rename(f.path(), &download_dir.as_ref().join(f.file_name())).await?;
-    }
-    fs::remove_dir(temp_dir).await?;
+        // Move files out of temporary directory
+        let mut files = Vec::new();
+        let mut read_dir = fs::read_dir(&temp_dir).await?;
+        while let Some(f) = read_dir.next_entry().await? {
+            let file = download_dir.as_ref().join(f.file_name());
+            fs::rename(f.path(), &file).await?;
+            files.push(file);
+        }
+        fs::remove_dir(temp_dir).await?;
+        let entry = index.insert_files(SERVICE, post.id(), &slug, files).await?;
 
-    println!("Downloaded {}", slug);
+        println!("Downloaded {}", slug);
 
-    Ok(DownloadStatus::Downloaded)
+        Ok(Some(entry))
+    }
+    .await;
+    record.finish(result)
 }
 
 async fn download_post(
     download_dir: impl AsRef<Path>,
-    client: &Client,
-    weverse_client: &AuthenticatedWeverseClient<'_>,
+    client: &ClientWithMiddleware,
+    weverse_client: &AuthenticatedWeverseClient,
+    index: &ArchiveIndex,
+    artist: &str,
+    artist_config: &ArtistConfig,
     post: Result<ArtistPostShort>,
-) -> Result<DownloadStatus> {
-    let post = post?;
-    let slug = post.slug()?;
-    if download_dir.as_ref().join(&slug).exists() {
-        return Ok(DownloadStatus::Skipped);
-    }
+) -> PostRecord {
+    let record = PostRecord::new(SERVICE, artist);
+    let (post, slug) = match post.and_then(|p| p.slug().map(|slug| (p, slug))) {
+        Ok(post) => post,
+        Err(e) => return record.failed(&e),
+    };
+    let record = record.post(&post.post_id, &slug);
+
+    let result = async {
+        let existing = post_dir(download_dir.as_ref(), &slug);
+        if index.check(SERVICE, &post.post_id, &slug, existing).await? {
+            return Ok(None);
+        }
 
-    let post = weverse_client.post(&post.post_id).await?;
-    download_post_real(download_dir.as_ref(), client, &post).await?;
-    Ok(DownloadStatus::Downloaded)
+        let mut post = weverse_client.post(&post.post_id).await?;
+        post.set_video_policy(artist_config.video_policy());
+        download_post_real(
+            download_dir.as_ref(),
+            client,
+            weverse_client,
+            index,
+            artist_config,
+            &post,
+        )
+        .await
+        .map(Some)
+    }
+    .await;
+    record.finish(result)
 }
 
+/// Download a chain of moments, stopping at the first moment that has already been downloaded
+///
+/// With `moments_backfill` every moment that can still be reached from the chain is downloaded
+/// instead, walking to both older and newer moments and past the ones already downloaded.
 async fn download_member_moments(
     download_dir: impl AsRef<Path>,
-    client: &Client,
-    weverse_client: &AuthenticatedWeverseClient<'_>,
+    client: &ClientWithMiddleware,
+    weverse_client: &AuthenticatedWeverseClient,
+    index: &ArchiveIndex,
+    artist: &str,
+    artist_config: &ArtistConfig,
     first_post: ArtistPost,
-) -> Result<()> {
-    let mut post = first_post;
-    loop {
-        let slug = post.slug()?;
-        if download_dir.as_ref().join(&slug).exists() {
+) -> Vec<PostRecord> {
+    let backfill = artist_config.moments_backfill;
+    let mut records = Vec::new();
+    let mut visited = HashSet::from([first_post.id().to_owned()]);
+    let mut queue = VecDeque::from([first_post]);
+    while let Some(mut post) = queue.pop_front() {
+        post.set_video_policy(artist_config.video_policy());
+        let record = PostRecord::new(SERVICE, artist);
+        let slug = match post.slug() {
+            Ok(slug) => slug,
+            Err(e) => {
+                records.push(record.failed(&e));
+                if backfill {
+                    continue;
+                }
+                break;
+            }
+        };
+        let record = record.post(post.id(), &slug);
+
+        let result = async {
+            let existing = post_dir(download_dir.as_ref(), &slug);
+            if index.check(SERVICE, post.id(), &slug, existing).await? {
+                return Ok(None);
+            }
+            download_post_real(
+                download_dir.as_ref(),
+                client,
+                weverse_client,
+                index,
+                artist_config,
+                &post,
+            )
+            .await
+            .map(Some)
+        }
+        .await;
+        let record = record.finish(result);
+        let status = record.status;
+        records.push(record);
+        if !backfill && status != PostStatus::Downloaded {
             break;
         }
-        download_post_real(download_dir.as_ref(), client, &post).await?;
-        if let Some(next_post_id) = post.next_moment_id() {
-            post = weverse_client.post(&next_post_id).await?;
+
+        let newer_moment_id = if backfill {
+            post.newer_moment_id()
         } else {
-            break;
+            None
+        };
+        for moment_id in [post.next_moment_id(), newer_moment_id]
+            .into_iter()
+            .flatten()
+        {
+            if !visited.insert(moment_id.clone()) {
+                continue;
+            }
+            match weverse_client.post(&moment_id).await {
+                Ok(moment) => queue.push_back(moment),
+                Err(e) => records.push(PostRecord::new(SERVICE, artist).failed(&e)),
+            }
         }
     }
-    Ok(())
+    records
 }
 
 async fn download_post_real(
     path: impl AsRef<Path>,
-    client: &Client,
+    client: &ClientWithMiddleware,
+    weverse_client: &AuthenticatedWeverseClient,
+    index: &ArchiveIndex,
+    artist_config: &ArtistConfig,
     post: &ArtistPost,
-) -> Result<()> {
+) -> Result<IndexEntry> {
     // Create temporary directory
     let slug = post.slug()?;
     let temp_dir = path.as_ref().join(format!(".{}.temp", post.slug()?));
     fs::create_dir_all(&temp_dir).await?;
     let final_dir = path.as_ref().join(&slug);
 
-    // Download to temp directory
+    // Download to temp directory, comments first so they get the post's mtime as well
+    if artist_config.artist_comments {
+        let comments = artist_comments(
+            client,
+            weverse_client,
+            post.id(),
+            artist_config.artist_comment_parents,
+        )
+        .await?;
+        if !comments.is_empty() {
+            let filename = temp_dir.join(format!("{}-comments.json", slug));
+            fs::write(filename, serde_json::to_vec_pretty(&comments)?).await?;
+        }
+    }
     post.download(client, &temp_dir).await?;
 
     // Move temp directory to final location
     fs::rename(&temp_dir, &final_dir).await?;
+    let entry = index
+        .insert_files(SERVICE, post.id(), &slug, vec![final_dir])
+        .await?;
 
     println!("Downloaded {}", slug);
 
-    Ok(())
+    Ok(entry)
+}
+
+/// IDs of the configured media categories, or `None` for all media if no categories are configured
+async fn media_category_ids(
+    weverse_client: &AuthenticatedWeverseClient,
+    artist: &str,
+    artist_config: &ArtistConfig,
+) -> Result<Vec<Option<u64>>> {
+    let Some(titles) = &artist_config.media_categories else {
+        return Ok(vec![None]);
+    };
+    let categories = weverse_client.media_categories(artist).await?;
+    titles
+        .iter()
+        .map(|title| {
+            categories
+                .iter()
+                .find(|c| c.title.eq_ignore_ascii_case(title))
+                .map(|c| Some(c.id))
+                .ok_or_else(|| anyhow!("unknown {} media category {}", artist, title))
+        })
+        .collect()
+}
+
+/// Download a post into its own directory, for posts that are indexed under `index_id`
+async fn download_post_dir<P: SavablePost + Sync>(
+    download_dir: impl AsRef<Path>,
+    client: &ClientWithMiddleware,
+    index: &ArchiveIndex,
+    artist: &str,
+    index_id: impl FnOnce(&P) -> String,
+    post: Result<P>,
+) -> PostRecord {
+    let record = PostRecord::new(SERVICE, artist);
+    let (post, slug) = match post.and_then(|p| p.slug().map(|slug| (p, slug))) {
+        Ok(post) => post,
+        Err(e) => return record.failed(&e),
+    };
+    let id = index_id(&post);
+    let record = record.post(&id, &slug);
+
+    let result = async {
+        let existing = post_dir(download_dir.as_ref(), &slug);
+        if index.check(SERVICE, &id, &slug, existing).await? {
+            return Ok(None);
+        }
+
+        // Download to temporary directory
+        let temp_dir = download_dir.as_ref().join(format!(".{}.temp", slug));
+        fs::create_dir_all(&temp_dir).await?;
+        post.download(client, &temp_dir).await?;
+
+        // Move temporary directory to final location
+        let final_dir = download_dir.as_ref().join(&slug);
+        fs::rename(&temp_dir, &final_dir).await?;
+        let entry = index
+            .insert_files(SERVICE, &id, &slug, vec![final_dir])
+            .await?;
+
+        println!("Downloaded {}", slug);
+
+        Ok(Some(entry))
+    }
+    .await;
+    record.finish(result)
+}
+
+/// Download a notice, only fetching its body if it isn't downloaded yet
+async fn download_notice(
+    download_dir: &Path,
+    client: &ClientWithMiddleware,
+    weverse_client: &AuthenticatedWeverseClient,
+    index: &ArchiveIndex,
+    artist: &str,
+    notice: Result<NoticeShort>,
+) -> PostRecord {
+    let record = PostRecord::new(SERVICE, artist);
+    let (notice, slug) = match notice.and_then(|n| n.slug().map(|slug| (n, slug))) {
+        Ok(notice) => notice,
+        Err(e) => return record.failed(&e),
+    };
+    let id = notice_index_id(notice.id);
+    let existing = post_dir(download_dir, &slug);
+    match index.check(SERVICE, &id, &slug, existing).await {
+        Ok(true) => return record.post(&id, &slug).skipped(),
+        Ok(false) => {}
+        Err(e) => return record.post(&id, &slug).failed(&e),
+    }
+
+    download_post_dir(
+        download_dir,
+        client,
+        index,
+        artist,
+        |n: &Notice| notice_index_id(n.id()),
+        weverse_client.notice(notice.id).await,
+    )
+    .await
+}
+
+/// Comments share the index with posts, prefix their IDs so they can't clash
+fn comment_index_id(id: &str) -> String {
+    format!("comment-{}", id)
+}
+
+fn notice_index_id(id: impl Display) -> String {
+    format!("notice-{}", id)
+}
+
+fn letter_index_id(id: &str) -> String {
+    format!("letter-{}", id)
+}
+
+/// Save a comment from the community feed as a JSON file named after its slug
+async fn download_comment(
+    download_dir: impl AsRef<Path>,
+    index: &ArchiveIndex,
+    artist: &str,
+    comment: Result<Comment>,
+) -> PostRecord {
+    let record = PostRecord::new(SERVICE, artist);
+    let (comment, slug) = match comment.and_then(|c| c.slug().map(|slug| (c, slug))) {
+        Ok(comment) => comment,
+        Err(e) => return record.failed(&e),
+    };
+    let index_id = comment_index_id(&comment.id);
+    let record = record.post(&index_id, &slug);
+
+    let result = async {
+        let file = download_dir.as_ref().join(format!("{}.json", slug));
+        let existing = if file.exists() {
+            vec![file.clone()]
+        } else {
+            Vec::new()
+        };
+        if index.check(SERVICE, &index_id, &slug, existing).await? {
+            return Ok(None);
+        }
+
+        // Write to temporary directory
+        let temp_dir = download_dir.as_ref().join(format!(".{}.temp", slug));
+        fs::create_dir_all(&temp_dir).await?;
+        let temp_file = temp_dir.join(format!("{}.json", slug));
+        fs::write(&temp_file, serde_json::to_vec_pretty(&comment)?).await?;
+        set_mtime(&temp_file, &comment.time)?;
+
+        // Move file out of temporary directory
+        fs::rename(&temp_file, &file).await?;
+        fs::remove_dir(temp_dir).await?;
+        let entry = index
+            .insert_files(SERVICE, &index_id, &slug, vec![file])
+            .await?;
+
+        println!("Downloaded {}", slug);
+
+        Ok(Some(entry))
+    }
+    .await;
+    record.finish(result)
+}
+
+/// Artist comment and the comment it replies to
+#[derive(Serialize, Debug)]
+struct SavedComment {
+    #[serde(flatten)]
+    comment: Comment,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent_comment: Option<Comment>,
+}
+
+/// All comments written by artists under a post
+async fn artist_comments(
+    client: &ClientWithMiddleware,
+    weverse_client: &AuthenticatedWeverseClient,
+    post_id: &str,
+    with_parents: bool,
+) -> Result<Vec<SavedComment>> {
+    let mut comments = weverse_client.artist_comments(post_id);
+    let comments_stream = comments.as_stream(client).await;
+    futures::pin_mut!(comments_stream);
+
+    let mut saved = Vec::new();
+    while let Some(comment) = comments_stream.next().await {
+        let comment = comment?;
+        let parent_comment = match comment.parent_comment_id() {
+            Some(id) if with_parents => Some(weverse_client.comment(id).await?),
+            _ => None,
+        };
+        saved.push(SavedComment {
+            comment,
+            parent_comment,
+        });
+    }
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn migrate_every_section() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |section: &str| dir.path().join(section);
+        let conf: WeverseConfig = toml::from_str(&format!(
+            r#"
+            email = "a@example.com"
+            [artists.dreamcatcher]
+            artist_download_path = "{}"
+            artist_comments_download_path = "{}"
+            notices_download_path = "{}"
+            letters_download_path = "{}"
+            "#,
+            path("artist").display(),
+            path("comments").display(),
+            path("notices").display(),
+            path("letters").display(),
+        ))
+        .unwrap();
+        let entries = [
+            "artist/20230510-0-119057265-YOOHYEON-안농",
+            "comments/20230511-0-49999-SIYEON-hi.json",
+            "notices/20230201-1001-notice-Dreamcatcher Concert Notice",
+            "letters/20230301-l-1-JIU-To InSomnia",
+        ];
+        for entry in entries {
+            let path = dir.path().join(entry);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            match path.extension() {
+                Some(_) => std::fs::write(&path, b"{}").unwrap(),
+                None => std::fs::create_dir(&path).unwrap(),
+            }
+        }
+
+        let index = Arc::new(ArchiveIndex::open(dir.path().join("index.sqlite3")).unwrap());
+        let archiver = WeverseArchiver::new(conf, index.clone());
+        assert_eq!(archiver.migrate_index().await.unwrap(), 4);
+        for id in [
+            "0-119057265",
+            "comment-0-49999",
+            "notice-1001",
+            "letter-l-1",
+        ] {
+            assert!(index.contains(SERVICE, id).unwrap(), "{}", id);
+        }
+        assert_eq!(archiver.migrate_index().await.unwrap(), 0);
+    }
 }
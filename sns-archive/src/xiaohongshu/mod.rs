@@ -1,9 +1,17 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Deserializer};
+use sns_archive_common::{
+    http_client, scan_dir, ArchiveIndex, Archiver, DiscoveredPost, IndexEntry, PostRecord, Report,
+};
 use thirtyfour::prelude::*;
 use thirtyfour::CapabilitiesHelper;
 use thirtyfour::PageLoadStrategy;
@@ -15,6 +23,9 @@ use crate::config::xiaohongshu::XiaoHongShuConfig;
 
 static DRIVER_ADDR: &str = "http://localhost:4444";
 
+/// Service name in the archive index
+const SERVICE: &str = "xiaohongshu";
+
 #[derive(Deserialize, Debug)]
 struct XHSResponse {
     data: XHSData,
@@ -77,53 +88,192 @@ where
     Ok(s)
 }
 
-pub async fn download(json_file: impl AsRef<Path>, config: XiaoHongShuConfig) -> Result<()> {
-    // Parse JSON
-    let s = fs::read_to_string(json_file.as_ref()).await?;
-    let parsed: XHSResponse = serde_json::from_str(&s)?;
-
-    // Create directory
-    fs::create_dir_all(&config.download_path).await?;
-
-    // Create selenium driver
-    let _driver = process::Command::new("geckodriver")
-        .arg("--port=4444")
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .kill_on_drop(true)
-        .spawn()?;
-    let mut caps = DesiredCapabilities::firefox();
-    caps.set_page_load_strategy(PageLoadStrategy::None)?;
-    let driver = loop {
-        match WebDriver::new(DRIVER_ADDR, caps.clone()).await {
-            Ok(d) => break d,
-            _ => time::sleep(Duration::from_secs(1)).await,
+pub struct XiaoHongShuArchiver {
+    conf: XiaoHongShuConfig,
+    index: Arc<ArchiveIndex>,
+    /// API response from the XiaoHongShu app, only needed to download or discover posts
+    json_file: Option<PathBuf>,
+    report: Mutex<Report>,
+}
+
+impl XiaoHongShuArchiver {
+    pub fn new(
+        conf: XiaoHongShuConfig,
+        index: Arc<ArchiveIndex>,
+        json_file: Option<PathBuf>,
+    ) -> Self {
+        Self {
+            conf,
+            index,
+            json_file,
+            report: Mutex::new(Report::default()),
         }
-        println!("Waiting for driver...");
-    };
+    }
 
-    // Download posts
-    for post in parsed.data.notes {
-        download_post(&driver, post, &config.download_path).await?;
+    async fn notes(&self) -> Result<Vec<XHSNote>> {
+        let json_file = self
+            .json_file
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing XiaoHongShu API response"))?;
+        let s = fs::read_to_string(json_file).await?;
+        let parsed: XHSResponse = serde_json::from_str(&s)?;
+        Ok(parsed.data.notes)
     }
+}
 
-    // Close window
-    driver.close_window().await?;
-    driver.quit().await?;
+#[async_trait(?Send)]
+impl Archiver for XiaoHongShuArchiver {
+    fn name(&self) -> &'static str {
+        "Xiaohongshu"
+    }
 
-    Ok(())
+    fn config_section(&self) -> &'static str {
+        SERVICE
+    }
+
+    /// The post date is only known after visiting the post, so the slug here lacks the date
+    async fn discover(&self) -> Result<Vec<DiscoveredPost>> {
+        let mut discovered = Vec::new();
+        for post in self.notes().await? {
+            let user_dir = self.conf.download_path.join(&post.user.nickname);
+            if self.index.contains(SERVICE, &post.id)?
+                || !downloaded_paths(&user_dir, &post.id).is_empty()
+            {
+                continue;
+            }
+            let slug = sanitize_filename::sanitize(format!(
+                "{}-{}-{}",
+                &post.user.nickname, &post.id, &post.display_title
+            ));
+            discovered.push(DiscoveredPost {
+                account: post.user.nickname.clone(),
+                id: post.id.clone(),
+                path: user_dir.join(&slug),
+                slug,
+            });
+        }
+        Ok(discovered)
+    }
+
+    async fn download(&self) -> Result<()> {
+        let notes = self.notes().await?;
+
+        // Create directory
+        fs::create_dir_all(&self.conf.download_path).await?;
+
+        // Create selenium driver
+        let _driver = process::Command::new("geckodriver")
+            .arg("--port=4444")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let mut caps = DesiredCapabilities::firefox();
+        caps.set_page_load_strategy(PageLoadStrategy::None)?;
+        let driver = loop {
+            match WebDriver::new(DRIVER_ADDR, caps.clone()).await {
+                Ok(d) => break d,
+                _ => time::sleep(Duration::from_secs(1)).await,
+            }
+            println!("Waiting for driver...");
+        };
+
+        // Download posts
+        let client = http_client(self.conf.rate_limit.policy(Default::default()));
+        for post in notes {
+            // Replaced by the full slug once the post is downloaded
+            let slug = sanitize_filename::sanitize(format!(
+                "{}-{}-{}",
+                &post.user.nickname, &post.id, &post.display_title
+            ));
+            let record = PostRecord::new(SERVICE, &post.user.nickname).post(&post.id, &slug);
+            let result = download_post(
+                &driver,
+                &client,
+                &self.index,
+                post,
+                &self.conf.download_path,
+            )
+            .await;
+            let mut report = self.report.lock().unwrap();
+            match result {
+                Ok(entry) => report.add(record.finish(Ok(entry))),
+                Err(e) => {
+                    report.add(record.failed(&e));
+                    return Err(e);
+                }
+            }
+        }
+
+        // Close window
+        driver.close_window().await?;
+        driver.quit().await?;
+
+        Ok(())
+    }
+
+    fn take_report(&self) -> Report {
+        std::mem::take(&mut self.report.lock().unwrap())
+    }
+
+    /// Posts are saved in a directory per user
+    async fn migrate_index(&self) -> Result<usize> {
+        static RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"-(?P<id>[0-9a-f]{24})-").unwrap());
+
+        let mut added = 0;
+        let user_dirs = match std::fs::read_dir(&self.conf.download_path) {
+            Ok(r) => r,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        for user_dir in user_dirs {
+            let user_dir = user_dir?;
+            if user_dir.file_type()?.is_dir() {
+                let posts = scan_dir(user_dir.path(), &RE)?;
+                added += self.index.migrate(SERVICE, posts).await?;
+            }
+        }
+        Ok(added)
+    }
+}
+
+/// Find files in the user directory that belong to the post
+fn downloaded_paths(user_dir: impl AsRef<Path>, id: &str) -> Vec<PathBuf> {
+    std::fs::read_dir(user_dir)
+        .map(|r| {
+            r.flatten()
+                .filter(|direntry| direntry.file_name().to_string_lossy().contains(id))
+                .map(|direntry| direntry.path())
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
-async fn download_post(driver: &WebDriver, post: XHSNote, dir: impl AsRef<Path>) -> Result<()> {
+/// Returns the downloaded post, or `None` if it was skipped because it already exists
+async fn download_post(
+    driver: &WebDriver,
+    client: &ClientWithMiddleware,
+    index: &ArchiveIndex,
+    post: XHSNote,
+    dir: impl AsRef<Path>,
+) -> Result<Option<IndexEntry>> {
     // Create user directory
     let user_dir = dir.as_ref().join(&post.user.nickname);
     fs::create_dir_all(&user_dir).await?;
 
     // Check if already downloaded
-    for direntry in std::fs::read_dir(&user_dir)?.flatten() {
-        if direntry.file_name().to_string_lossy().contains(&post.id) {
-            return Ok(());
-        }
+    // The full slug is only known after visiting the post, use the existing name instead
+    let existing = downloaded_paths(&user_dir, &post.id);
+    let existing_slug = existing
+        .first()
+        .and_then(|p| p.file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if index
+        .check(SERVICE, &post.id, &existing_slug, existing)
+        .await?
+    {
+        return Ok(None);
     }
 
     // Create post directory
@@ -158,7 +308,7 @@ async fn download_post(driver: &WebDriver, post: XHSNote, dir: impl AsRef<Path>)
     for (i, image) in post.images_list.into_iter().enumerate() {
         let filename = format!("{}-img{:02}", &prefix, i + 1);
         let path = post_dir.join(&filename);
-        download_file(image.url, path).await?;
+        download_file(client, image.url, path).await?;
     }
 
     // Download video
@@ -175,7 +325,7 @@ async fn download_post(driver: &WebDriver, post: XHSNote, dir: impl AsRef<Path>)
         };
         let filename = format!("{}-vid", &prefix);
         let path = post_dir.join(&filename);
-        download_file(url, path).await?;
+        download_file(client, url, path).await?;
     }
 
     // Write content file
@@ -191,13 +341,21 @@ async fn download_post(driver: &WebDriver, post: XHSNote, dir: impl AsRef<Path>)
         file.write_all(file_contents.as_bytes()).await?;
     }
 
+    let entry = index
+        .insert_files(SERVICE, &post.id, &prefix, vec![post_dir])
+        .await?;
+
     println!("Downloaded {}", prefix);
 
-    Ok(())
+    Ok(Some(entry))
 }
 
-async fn download_file(url: impl AsRef<str>, path: impl AsRef<Path>) -> Result<()> {
-    let data = reqwest::get(url.as_ref()).await?.bytes().await?;
+async fn download_file(
+    client: &ClientWithMiddleware,
+    url: impl AsRef<str>,
+    path: impl AsRef<Path>,
+) -> Result<()> {
+    let data = client.get(url.as_ref()).send().await?.bytes().await?;
     let kind = infer::get(&data).ok_or_else(|| anyhow::anyhow!("Unknown file type"))?;
     let mut file = fs::File::create(path.as_ref().with_extension(kind.extension())).await?;
     file.write_all(&data).await?;
@@ -3,13 +3,76 @@ use std::fmt::Display;
 use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::Mutex;
 use std::{fs, mem};
 
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use sns_archive_common::{Archiver, DiscoveredPost, PollTarget, PostRecord, Report};
 
+use crate::config::daemon::poll_interval;
 use crate::config::youtube::{YTChannel, YoutubeConfig};
 
+pub struct YoutubeArchiver {
+    conf: YoutubeConfig,
+    report: Mutex<Report>,
+}
+
+impl YoutubeArchiver {
+    pub fn new(conf: YoutubeConfig) -> Self {
+        Self {
+            conf,
+            report: Mutex::new(Report::default()),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Archiver for YoutubeArchiver {
+    fn name(&self) -> &'static str {
+        "YouTube"
+    }
+
+    fn config_section(&self) -> &'static str {
+        "youtube"
+    }
+
+    async fn discover(&self) -> Result<Vec<DiscoveredPost>> {
+        let conf = self.conf.clone();
+        tokio::task::spawn_blocking(move || discover(&conf)).await?
+    }
+
+    /// yt-dlp does not tell us how many videos were downloaded, so only failed channels are
+    /// counted in the report
+    async fn download(&self) -> Result<()> {
+        let conf = self.conf.clone();
+        let result = tokio::task::spawn_blocking(move || download(conf)).await?;
+        if let Err(e) = &result {
+            let mut report = self.report.lock().unwrap();
+            for channel in &e.0 {
+                let error = anyhow!("yt-dlp failed to download {}", channel.channel_id);
+                report.add(
+                    PostRecord::new(self.config_section(), &channel.display_name).failed(&error),
+                );
+            }
+        }
+        Ok(result?)
+    }
+
+    fn take_report(&self) -> Report {
+        mem::take(&mut self.report.lock().unwrap())
+    }
+
+    fn poll_targets(&self) -> Vec<PollTarget> {
+        vec![PollTarget {
+            name: String::new(),
+            interval: poll_interval([self.conf.poll_interval_mins]),
+        }]
+    }
+}
+
 #[derive(Debug)]
 pub struct YTError(Vec<YTChannel>);
 
@@ -25,7 +88,7 @@ impl Display for YTError {
 
 impl std::error::Error for YTError {}
 
-pub fn download(config: YoutubeConfig) -> Result<(), YTError> {
+fn download(config: YoutubeConfig) -> Result<(), YTError> {
     let mut errored_channels = vec![];
 
     for channel in config.channels {
@@ -89,6 +152,49 @@ pub fn download(config: YoutubeConfig) -> Result<(), YTError> {
     }
 }
 
+/// Ask yt-dlp which videos it would download
+fn discover(config: &YoutubeConfig) -> Result<Vec<DiscoveredPost>> {
+    let mut discovered = vec![];
+
+    for channel in config.channels.iter().filter(|c| c.enabled) {
+        let dir = config.download_path.join(&channel.display_name);
+        let new_channel = !directory_exists(&dir);
+        let mut args = generate_cmd_args(
+            channel,
+            &dir,
+            &config.filter,
+            new_channel,
+            &config.archive_path,
+            &config.proxy,
+        );
+        args.push("--simulate".into());
+        args.push("--print".into());
+        args.push("%(id)s\t%(filename)s".into());
+
+        let output = Command::new("yt-dlp")
+            .stderr(Stdio::inherit())
+            .args(&args)
+            .output()?;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if let Some((id, filename)) = line.split_once('\t') {
+                let path = PathBuf::from(filename);
+                let slug = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                discovered.push(DiscoveredPost {
+                    account: channel.display_name.clone(),
+                    id: id.to_owned(),
+                    slug,
+                    path,
+                });
+            }
+        }
+    }
+
+    Ok(discovered)
+}
+
 fn generate_cmd_args(
     channel: &YTChannel,
     target_dir: impl AsRef<Path>,
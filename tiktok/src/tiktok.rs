@@ -4,7 +4,7 @@ use std::process::Stdio;
 
 use anyhow::Result;
 use async_trait::async_trait;
-use reqwest::Client;
+use reqwest_middleware::ClientWithMiddleware;
 use scraper::{Html, Selector};
 use serde::Deserialize;
 use sns_archive_common::{set_mtime, SavablePost};
@@ -19,11 +19,11 @@ pub struct TikTokVideo {
 }
 
 pub struct TikTokClient<'a> {
-    reqwest_client: &'a Client,
+    reqwest_client: &'a ClientWithMiddleware,
 }
 
 impl<'a> TikTokClient<'a> {
-    pub fn new(client: &'a Client) -> Self {
+    pub fn new(client: &'a ClientWithMiddleware) -> Self {
         Self {
             reqwest_client: client,
         }
@@ -84,6 +84,11 @@ impl<'a> TikTokClient<'a> {
 }
 
 impl TikTokVideo {
+    /// TikTok video ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     fn url(&self) -> String {
         format!("https://www.tiktok.com/@{}/video/{}", self.user, self.id)
     }
@@ -91,7 +96,11 @@ impl TikTokVideo {
 
 #[async_trait]
 impl SavablePost for TikTokVideo {
-    async fn download(&self, client: &Client, directory: impl AsRef<Path> + Send) -> Result<()> {
+    async fn download(
+        &self,
+        client: &ClientWithMiddleware,
+        directory: impl AsRef<Path> + Send,
+    ) -> Result<()> {
         let filename = directory.as_ref().join(format!("{}.mp4", self.slug()?));
         let filename_temp = directory
             .as_ref()
@@ -136,7 +145,11 @@ impl SavablePost for TikTokVideo {
     }
 }
 
-async fn snaptik_get_video(client: &Client, token: &str, url: &str) -> Result<String> {
+async fn snaptik_get_video(
+    client: &ClientWithMiddleware,
+    token: &str,
+    url: &str,
+) -> Result<String> {
     // Query snaptik
     let form = reqwest::multipart::Form::new()
         .text("url", url.to_owned())
@@ -165,7 +178,7 @@ async fn snaptik_get_video(client: &Client, token: &str, url: &str) -> Result<St
     Ok(url)
 }
 
-async fn snaptik_token(client: &Client) -> Result<String> {
+async fn snaptik_token(client: &ClientWithMiddleware) -> Result<String> {
     let text = client
         .get("https://snaptik.app/en")
         .send()
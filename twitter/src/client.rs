@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use reqwest::{header, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use serde::Deserialize;
+use sns_archive_common::RequestPolicy;
+use time::UtcOffset;
+
+use crate::twitter_posts::TwitterPosts;
+
+static API_BASE: &str = "https://api.twitter.com";
+
+/// Request policy for clients used with Twitter
+///
+/// Twitter rate limits are counted in 15 minute windows, so rate limited requests may need to
+/// wait much longer than the default backoff allows.
+pub fn request_policy() -> RequestPolicy {
+    RequestPolicy {
+        max_backoff: Duration::from_secs(15 * 60),
+        ..Default::default()
+    }
+}
+
+pub struct TwitterClient<'a> {
+    reqwest_client: &'a ClientWithMiddleware,
+    bearer: String,
+    api_base: Url,
+    utc_offset: UtcOffset,
+}
+
+impl<'a> TwitterClient<'a> {
+    /// Create a new TwitterClient authenticated with an app bearer token
+    pub fn new(reqwest_client: &'a ClientWithMiddleware, bearer: &str) -> Self {
+        Self {
+            reqwest_client,
+            bearer: bearer.to_owned(),
+            api_base: Url::parse(API_BASE).unwrap(),
+            utc_offset: UtcOffset::UTC,
+        }
+    }
+
+    /// Use a different API location, e.g. a server replaying recorded responses
+    pub fn api_base(mut self, api_base: Url) -> Self {
+        self.api_base = api_base;
+        self
+    }
+
+    /// Offset of the timezone used for dates in slugs and saved text
+    pub fn utc_offset(mut self, utc_offset: UtcOffset) -> Self {
+        self.utc_offset = utc_offset;
+        self
+    }
+
+    /// Get stream of tweets of a user, newest first
+    pub async fn posts(&self, username: &str) -> Result<TwitterPosts> {
+        let user_id = self.user_id(username).await?;
+        Ok(TwitterPosts::new(
+            self.api_base.clone(),
+            self.bearer.clone(),
+            user_id,
+            self.utc_offset,
+        ))
+    }
+
+    async fn user_id(&self, username: &str) -> Result<String> {
+        #[derive(Deserialize)]
+        struct UserResponse {
+            data: Option<User>,
+        }
+
+        #[derive(Deserialize)]
+        struct User {
+            id: String,
+        }
+
+        let url = self
+            .api_base
+            .join(&format!("/2/users/by/username/{}", username))?;
+        let resp: UserResponse = self
+            .reqwest_client
+            .get(url)
+            .header(header::AUTHORIZATION, format!("Bearer {}", self.bearer))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        // Unknown users are reported in an errors field with a successful status
+        resp.data
+            .map(|u| u.id)
+            .ok_or_else(|| anyhow!("Twitter user {} not found", username))
+    }
+}
@@ -0,0 +1,9 @@
+mod client;
+#[cfg(test)]
+mod test_server;
+mod twitter_post;
+mod twitter_posts;
+
+pub use client::{request_policy, TwitterClient};
+pub use twitter_post::TwitterPost;
+pub use twitter_posts::TwitterPosts;
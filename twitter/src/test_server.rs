@@ -0,0 +1,33 @@
+use reqwest::Url;
+use sns_archive_common::test_server::{Response, TestServer};
+
+/// Serve recorded API responses from `fixtures/`, media files contain their own name
+///
+/// `{{server}}` in fixtures is replaced with the address of the server.
+pub(crate) async fn serve_fixtures() -> Url {
+    let server = TestServer::start(|request| {
+        let path = request.path();
+        let body = match path {
+            "/2/users/by/username/hf_dreamcatcher" => include_str!("../fixtures/user.json"),
+            "/2/users/by/username/nobody" => include_str!("../fixtures/user_not_found.json"),
+            "/2/users/858220000000000000/tweets"
+                if request
+                    .target
+                    .contains("pagination_token=7140dibdnow9c7btw4") =>
+            {
+                include_str!("../fixtures/tweets_page2.json")
+            }
+            "/2/users/858220000000000000/tweets" => include_str!("../fixtures/tweets_page1.json"),
+            _ => match path.strip_prefix("/media/") {
+                Some(file) => {
+                    return Response::ok(file.rsplit_once('.').map_or(file, |(stem, _)| stem))
+                }
+                None => return Response::not_found(),
+            },
+        };
+        let server = request.base.as_str().trim_end_matches('/');
+        Response::ok(body.replace("{{server}}", server))
+    })
+    .await;
+    server.base().clone()
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Deserializer};
+use sns_archive_common::{set_mtime, streamed_download, SavablePost};
+use time::format_description::well_known::Rfc3339;
+use time::format_description::FormatItem;
+use time::{format_description, OffsetDateTime, UtcOffset};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// Tweet as returned by the API, media and users are returned separately in [`Includes`]
+#[derive(Deserialize, Debug)]
+pub(crate) struct Tweet {
+    id: String,
+    text: String,
+    #[serde(deserialize_with = "deserialize_datetime")]
+    created_at: OffsetDateTime,
+    author_id: String,
+    attachments: Option<Attachments>,
+    entities: Option<Entities>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Attachments {
+    #[serde(default)]
+    media_keys: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Entities {
+    #[serde(default)]
+    urls: Vec<TweetUrl>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TweetUrl {
+    expanded_url: String,
+    /// Set for links to the tweet's own media
+    media_key: Option<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub(crate) struct Includes {
+    #[serde(default)]
+    media: Vec<Media>,
+    #[serde(default)]
+    users: Vec<User>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct Media {
+    media_key: String,
+    #[serde(rename = "type")]
+    kind: MediaKind,
+    /// Only set for photos
+    url: Option<String>,
+    /// Only set for videos and gifs
+    #[serde(default)]
+    variants: Vec<Variant>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum MediaKind {
+    Photo,
+    Video,
+    AnimatedGif,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct Variant {
+    bit_rate: Option<u64>,
+    content_type: String,
+    url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct User {
+    id: String,
+    username: String,
+}
+
+#[derive(Debug)]
+pub struct TwitterPost {
+    pub id: String,
+    created_at: OffsetDateTime,
+    username: String,
+    text: String,
+    links: Vec<String>,
+    media: Vec<Media>,
+}
+
+impl TwitterPost {
+    pub(crate) fn new(tweet: Tweet, includes: &Includes, utc_offset: UtcOffset) -> Result<Self> {
+        let username = includes
+            .users
+            .iter()
+            .find(|u| u.id == tweet.author_id)
+            .map(|u| u.username.clone())
+            .ok_or_else(|| anyhow!("Missing author of tweet {}", tweet.id))?;
+        let media_by_key: HashMap<_, _> =
+            includes.media.iter().map(|m| (&m.media_key, m)).collect();
+        let media = tweet
+            .attachments
+            .map(|a| a.media_keys)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|k| media_by_key.get(k).map(|m| (*m).clone()))
+            .collect();
+        let links = tweet
+            .entities
+            .map(|e| e.urls)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|u| u.media_key.is_none())
+            .map(|u| u.expanded_url)
+            .collect();
+
+        Ok(Self {
+            id: tweet.id,
+            created_at: tweet.created_at.to_offset(utc_offset),
+            username,
+            text: tweet.text,
+            links,
+            media,
+        })
+    }
+
+    /// URLs of the original size of attached photos
+    pub(crate) fn images(&self) -> impl Iterator<Item = Result<Url>> + '_ {
+        self.media
+            .iter()
+            .filter(|m| m.kind == MediaKind::Photo)
+            .filter_map(|m| m.url.as_deref())
+            .map(|url| {
+                let mut url = Url::parse(url)?;
+                url.query_pairs_mut().append_pair("name", "orig");
+                Ok(url)
+            })
+    }
+
+    /// URLs of the highest bit rate version of attached videos and gifs
+    pub(crate) fn videos(&self) -> impl Iterator<Item = &str> + '_ {
+        self.media
+            .iter()
+            .filter(|m| m.kind != MediaKind::Photo)
+            .filter_map(|m| {
+                m.variants
+                    .iter()
+                    .filter(|v| v.content_type == "video/mp4")
+                    .max_by_key(|v| v.bit_rate.unwrap_or_default())
+            })
+            .map(|v| v.url.as_str())
+    }
+
+    async fn write_text(&self, path: impl AsRef<Path>) -> Result<()> {
+        let url = format!("https://x.com/{}/status/{}", self.username, self.id);
+        let time = self.created_at.format(&Rfc3339)?;
+
+        let mut file = fs::File::create(path).await?;
+        file.write_all(format!("url: {}\n", url).as_bytes()).await?;
+        file.write_all(format!("user: {}\n", self.username).as_bytes())
+            .await?;
+        file.write_all(format!("created_at: {}\n", time).as_bytes())
+            .await?;
+        for link in &self.links {
+            file.write_all(format!("link: {}\n", link).as_bytes())
+                .await?;
+        }
+        file.write_all(format!("\n{}", self.text).as_bytes())
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn deserialize_datetime<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    OffsetDateTime::parse(&s, &Rfc3339).map_err(serde::de::Error::custom)
+}
+
+#[async_trait]
+impl SavablePost for TwitterPost {
+    async fn download(
+        &self,
+        client: &ClientWithMiddleware,
+        directory: impl AsRef<Path> + Send,
+    ) -> Result<()> {
+        let slug = self.slug()?;
+
+        // Write text
+        {
+            let filename = format!("{}-content.txt", &slug);
+            let path = directory.as_ref().join(filename);
+            self.write_text(path).await?;
+        }
+
+        // Download images
+        let images: Vec<_> = self.images().collect::<Result<_>>()?;
+        for (i, url) in images.into_iter().enumerate() {
+            let ext = Path::new(url.path())
+                .extension()
+                .map(|e| e.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "jpg".to_owned());
+            let filename = format!("{}-img{:02}.{}", &slug, i + 1, ext);
+            streamed_download(client, url, directory.as_ref().join(filename)).await?;
+        }
+
+        // Download videos
+        for (i, url) in self.videos().enumerate() {
+            let filename = format!("{}-vid{:02}.mp4", &slug, i + 1);
+            streamed_download(client, url, directory.as_ref().join(filename)).await?;
+        }
+
+        // Set mtime on directory and all files in it
+        set_mtime(&directory, &self.created_at)?;
+        let mut read_dir = fs::read_dir(directory).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            set_mtime(entry.path(), &self.created_at)?;
+        }
+
+        Ok(())
+    }
+
+    fn slug(&self) -> Result<String> {
+        static FORMAT: Lazy<Vec<FormatItem>> =
+            Lazy::new(|| format_description::parse("[year][month][day]").unwrap());
+        let date = self.created_at.format(&FORMAT)?;
+        Ok(format!("{}-{}-{}", date, self.id, self.username))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_server::serve_fixtures;
+    use crate::TwitterClient;
+
+    #[tokio::test]
+    async fn download_post() {
+        let server = serve_fixtures().await;
+        let client = sns_archive_common::http_client(Default::default());
+        let twitter_client = TwitterClient::new(&client, "bearer").api_base(server);
+        let mut posts = twitter_client.posts("hf_dreamcatcher").await.unwrap();
+        let stream = posts.as_stream(&client).await;
+        futures::pin_mut!(stream);
+
+        let dir = std::env::temp_dir().join(format!("sns-archive-twitter-{}", std::process::id()));
+        fs::create_dir_all(&dir).await.unwrap();
+        for _ in 0..2 {
+            let post = futures::StreamExt::next(&mut stream)
+                .await
+                .unwrap()
+                .unwrap();
+            post.download(&client, &dir).await.unwrap();
+        }
+
+        let mut files = Vec::new();
+        let mut read_dir = fs::read_dir(&dir).await.unwrap();
+        while let Some(entry) = read_dir.next_entry().await.unwrap() {
+            files.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                "20230510-1656690000000000001-hf_dreamcatcher-content.txt",
+                "20230510-1656690000000000001-hf_dreamcatcher-vid01.mp4",
+                "20230511-1656690000000000002-hf_dreamcatcher-content.txt",
+                "20230511-1656690000000000002-hf_dreamcatcher-img01.jpg",
+                "20230511-1656690000000000002-hf_dreamcatcher-img02.png",
+            ]
+        );
+
+        let text = fs::read_to_string(
+            dir.join("20230511-1656690000000000002-hf_dreamcatcher-content.txt"),
+        )
+        .await
+        .unwrap();
+        assert!(text.starts_with("url: https://x.com/hf_dreamcatcher/status/1656690000000000002\n"));
+        assert!(text.contains("link: https://dreamcatcher.example/apocalypse\n"));
+        assert!(!text.contains("/photo/1"));
+
+        // Videos use the highest bit rate
+        let video = fs::read(dir.join("20230510-1656690000000000001-hf_dreamcatcher-vid01.mp4"))
+            .await
+            .unwrap();
+        assert_eq!(video, b"video-2176000");
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use futures::Stream;
+use reqwest::{header, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use serde::Deserialize;
+use time::UtcOffset;
+
+use crate::twitter_post::{Includes, Tweet, TwitterPost};
+
+pub struct TwitterPosts {
+    api_base: Url,
+    bearer: String,
+    user_id: String,
+    utc_offset: UtcOffset,
+    fetch_state: FetchState,
+}
+
+#[derive(Default)]
+struct FetchState {
+    errored: bool,
+    /// Set once the last page has been fetched
+    finished: bool,
+    next_token: Option<String>,
+    posts: VecDeque<TwitterPost>,
+}
+
+impl TwitterPosts {
+    pub(crate) fn new(
+        api_base: Url,
+        bearer: String,
+        user_id: String,
+        utc_offset: UtcOffset,
+    ) -> Self {
+        Self {
+            api_base,
+            bearer,
+            user_id,
+            utc_offset,
+            fetch_state: Default::default(),
+        }
+    }
+
+    pub async fn as_stream<'a>(
+        &'a mut self,
+        client: &'a ClientWithMiddleware,
+    ) -> impl Stream<Item = Result<TwitterPost>> + 'a {
+        self.fetch_state = Default::default();
+        futures::stream::unfold(self, |state| async {
+            // Stop if previously errored
+            if state.fetch_state.errored {
+                return None;
+            }
+
+            // Pages can be empty, keep going until a post is found or there are no more pages
+            loop {
+                if let Some(post) = state.fetch_state.posts.pop_front() {
+                    return Some((Ok(post), state));
+                }
+                if state.fetch_state.finished {
+                    return None;
+                }
+
+                match state.get_page(client).await {
+                    Ok(page) => {
+                        state.fetch_state.posts.extend(page.posts);
+                        state.fetch_state.finished = page.next_token.is_none();
+                        state.fetch_state.next_token = page.next_token;
+                    }
+                    Err(e) => {
+                        state.fetch_state.errored = true;
+                        return Some((Err(e), state));
+                    }
+                }
+            }
+        })
+    }
+
+    async fn get_page(&self, client: &ClientWithMiddleware) -> Result<Page> {
+        #[derive(Deserialize, Debug)]
+        struct TimelineResponse {
+            #[serde(default)]
+            data: Vec<Tweet>,
+            #[serde(default)]
+            includes: Includes,
+            meta: Meta,
+        }
+
+        #[derive(Deserialize, Debug)]
+        struct Meta {
+            next_token: Option<String>,
+        }
+
+        let url = self
+            .api_base
+            .join(&format!("/2/users/{}/tweets", self.user_id))?;
+        let mut query = vec![
+            ("max_results", "100"),
+            ("exclude", "retweets"),
+            ("tweet.fields", "created_at,attachments,entities"),
+            ("expansions", "author_id,attachments.media_keys"),
+            ("media.fields", "url,variants"),
+            ("user.fields", "username"),
+        ];
+        if let Some(token) = &self.fetch_state.next_token {
+            query.push(("pagination_token", token));
+        }
+
+        let resp: TimelineResponse = client
+            .get(url)
+            .query(&query)
+            .header(header::AUTHORIZATION, format!("Bearer {}", self.bearer))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let posts = resp
+            .data
+            .into_iter()
+            .map(|tweet| TwitterPost::new(tweet, &resp.includes, self.utc_offset))
+            .collect::<Result<_>>()?;
+        Ok(Page {
+            posts,
+            next_token: resp.meta.next_token,
+        })
+    }
+}
+
+struct Page {
+    posts: Vec<TwitterPost>,
+    next_token: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+    use sns_archive_common::SavablePost;
+
+    use super::*;
+    use crate::test_server::serve_fixtures;
+    use crate::TwitterClient;
+
+    #[tokio::test]
+    async fn paginated_posts() {
+        let server = serve_fixtures().await;
+        let client = sns_archive_common::http_client(Default::default());
+        let twitter_client = TwitterClient::new(&client, "bearer")
+            .api_base(server)
+            .utc_offset(UtcOffset::from_hms(9, 0, 0).unwrap());
+
+        let mut posts = twitter_client.posts("hf_dreamcatcher").await.unwrap();
+        let posts: Vec<_> = posts
+            .as_stream(&client)
+            .await
+            .map(|p| p.unwrap())
+            .collect()
+            .await;
+
+        let slugs: Vec<_> = posts.iter().map(|p| p.slug().unwrap()).collect();
+        assert_eq!(
+            slugs,
+            [
+                // Posted 2023-05-11 16:30 UTC, already the next day in KST
+                "20230512-1656690000000000002-hf_dreamcatcher",
+                "20230510-1656690000000000001-hf_dreamcatcher",
+                "20230509-1656690000000000000-hf_dreamcatcher",
+            ]
+        );
+        assert_eq!(posts[0].images().count(), 2);
+        assert_eq!(posts[1].videos().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn unknown_user() {
+        let server = serve_fixtures().await;
+        let client = sns_archive_common::http_client(Default::default());
+        let twitter_client = TwitterClient::new(&client, "bearer").api_base(server);
+        assert!(twitter_client.posts("nobody").await.is_err());
+    }
+}
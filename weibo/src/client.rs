@@ -1,16 +1,28 @@
 use anyhow::Result;
-use reqwest::Client;
+use reqwest::StatusCode;
+use reqwest_middleware::ClientWithMiddleware;
+use sns_archive_common::RequestPolicy;
 
 use crate::weibo_auth::weibo_cookie;
 use crate::weibo_posts::WeiboPosts;
 
+/// Request policy for clients used with Weibo
+///
+/// Weibo responds with `414 URI Too Long` when requests are made too quickly, so it is retried
+/// like other rate limiting responses.
+pub fn request_policy() -> RequestPolicy {
+    let mut policy = RequestPolicy::default();
+    policy.retry_statuses.push(StatusCode::URI_TOO_LONG);
+    policy
+}
+
 pub struct WeiboClient<'a> {
-    reqwest_client: &'a Client,
+    reqwest_client: &'a ClientWithMiddleware,
 }
 
 impl<'a> WeiboClient<'a> {
     /// Create a new WeiboClient
-    pub async fn init(reqwest_client: &'a Client) -> Result<WeiboClient<'a>> {
+    pub async fn init(reqwest_client: &'a ClientWithMiddleware) -> Result<WeiboClient<'a>> {
         Ok(Self { reqwest_client })
     }
 
@@ -3,6 +3,6 @@ mod weibo_auth;
 mod weibo_post;
 mod weibo_posts;
 
-pub use client::WeiboClient;
+pub use client::{request_policy, WeiboClient};
 pub use weibo_post::WeiboPost;
 pub use weibo_posts::WeiboPosts;
@@ -1,6 +1,6 @@
 use anyhow::Result;
 use regex::Regex;
-use reqwest::Client;
+use reqwest_middleware::ClientWithMiddleware;
 use serde::Deserialize;
 
 pub struct WeiboAuth {
@@ -8,7 +8,7 @@ pub struct WeiboAuth {
     pub cookies: String,
 }
 
-pub async fn weibo_cookie(client: &Client) -> Result<WeiboAuth> {
+pub async fn weibo_cookie(client: &ClientWithMiddleware) -> Result<WeiboAuth> {
     static URL: &str = "https://passport.weibo.com/visitor/genvisitor2";
     let re = Regex::new(r"(?:visitor_gray_callback|gen_callback)\((.*)\)").unwrap();
 
@@ -5,7 +5,8 @@ use anyhow::Result;
 use async_trait::async_trait;
 use indexmap::IndexMap;
 use once_cell::sync::Lazy;
-use reqwest::{header, Client, Url};
+use reqwest::{header, Url};
+use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Deserializer};
 use sns_archive_common::{set_mtime, streamed_download, SavablePost};
 use time::format_description::well_known::Rfc3339;
@@ -103,7 +104,11 @@ struct WeiboUrl {
 
 #[async_trait]
 impl SavablePost for WeiboPost {
-    async fn download(&self, client: &Client, directory: impl AsRef<Path> + Send) -> Result<()> {
+    async fn download(
+        &self,
+        client: &ClientWithMiddleware,
+        directory: impl AsRef<Path> + Send,
+    ) -> Result<()> {
         // Generate output location
         let slug = self.slug()?;
 
@@ -199,7 +204,11 @@ impl WeiboUrl {
     }
 }
 
-async fn download_image(client: &Client, img_id: &str, path: impl AsRef<Path>) -> Result<()> {
+async fn download_image(
+    client: &ClientWithMiddleware,
+    img_id: &str,
+    path: impl AsRef<Path>,
+) -> Result<()> {
     // Download
     let url = format!("https://wx2.sinaimg.cn/large/{img_id}.jpg");
     let data = client.get(url).send().await?.bytes().await?;
@@ -216,7 +225,11 @@ async fn download_image(client: &Client, img_id: &str, path: impl AsRef<Path>) -
     Ok(())
 }
 
-async fn download_video(client: &Client, url: &str, path: impl AsRef<Path>) -> Result<()> {
+async fn download_video(
+    client: &ClientWithMiddleware,
+    url: &str,
+    path: impl AsRef<Path>,
+) -> Result<()> {
     #[derive(Deserialize)]
     struct WeiboVideo {
         data: WeiboData,
@@ -1,10 +1,9 @@
 use std::collections::{BinaryHeap, VecDeque};
-use std::time::Duration;
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use futures::Stream;
 use reqwest::header;
-use reqwest::{Client, StatusCode};
+use reqwest_middleware::ClientWithMiddleware;
 use serde::Deserialize;
 
 use crate::weibo_auth::WeiboAuth;
@@ -45,7 +44,7 @@ impl WeiboPosts {
 
     pub async fn as_stream<'a>(
         &'a mut self,
-        client: &'a Client,
+        client: &'a ClientWithMiddleware,
     ) -> impl Stream<Item = Result<WeiboPost>> + 'a {
         self.fetch_state = Default::default();
         futures::stream::unfold(self, |state| async {
@@ -60,8 +59,8 @@ impl WeiboPosts {
 
             match get_page(client, &state.auth, state.user, state.fetch_state.page).await {
                 Ok(data) => {
-                    state.fetch_state.posts.extend(data.posts.into_iter());
-                    state.fetch_state.pinned.extend(data.pinned.into_iter());
+                    state.fetch_state.posts.extend(data.posts);
+                    state.fetch_state.pinned.extend(data.pinned);
                     state.fetch_state.page += 1;
                 }
                 Err(e) => {
@@ -106,7 +105,12 @@ struct GetPageData {
     pinned: Vec<WeiboPost>,
 }
 
-async fn get_page(client: &Client, auth: &WeiboAuth, uid: u64, page: u64) -> Result<GetPageData> {
+async fn get_page(
+    client: &ClientWithMiddleware,
+    auth: &WeiboAuth,
+    uid: u64,
+    page: u64,
+) -> Result<GetPageData> {
     static URL: &str = "https://weibo.com/ajax/statuses/mymblog";
 
     #[derive(Deserialize, Debug)]
@@ -119,30 +123,18 @@ async fn get_page(client: &Client, auth: &WeiboAuth, uid: u64, page: u64) -> Res
         list: Vec<WeiboPost>,
     }
 
-    let mut retry_414 = 0;
-    let mut posts = loop {
-        let resp = client
-            .get(URL)
-            .query(&[("uid", &uid.to_string()), ("page", &page.to_string())])
-            .header(header::COOKIE, format!("SUB={}", auth.cookies))
-            .header(header::REFERER, format!("https://weibo.com/u/{}", uid))
-            .send()
-            .await?;
-
-        if resp.status().as_u16() == StatusCode::URI_TOO_LONG {
-            if retry_414 == 0 {
-                eprintln!("status code 414");
-            }
-            if retry_414 >= 10 {
-                return Err(anyhow!("error 414"));
-            }
-            retry_414 += 1;
-            tokio::time::sleep(Duration::from_secs(5)).await;
-            continue;
-        }
-
-        break resp.error_for_status()?.json::<Mymblog>().await?.data.list;
-    };
+    let mut posts = client
+        .get(URL)
+        .query(&[("uid", &uid.to_string()), ("page", &page.to_string())])
+        .header(header::COOKIE, format!("SUB={}", auth.cookies))
+        .header(header::REFERER, format!("https://weibo.com/u/{}", uid))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Mymblog>()
+        .await?
+        .data
+        .list;
 
     for p in posts.iter_mut() {
         p.set_tid(auth.tid.clone());
@@ -0,0 +1 @@
+const e=["0123456789abcdef0123456789abcdef01234567","HMAC_ACTIVE"];export default e;
@@ -1,38 +1,239 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::LazyLock;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock};
 
 use anyhow::Result;
 use base64::engine::general_purpose;
 use base64::Engine as _;
 use hmac::{Hmac, Mac};
 use regex::Regex;
-use reqwest::{header, Client, Url};
+use reqwest::{header, Method, Response, StatusCode, Url};
+use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Serialize};
 use sha1::Sha1;
 use time::OffsetDateTime;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
+use uuid::Uuid;
 
+use crate::domains::Domains;
 use crate::endpoint::me::me;
+use crate::endpoint::REFERER;
 use crate::error::WeverseError;
 
-pub(crate) async fn get_secret(client: &Client) -> Result<Vec<u8>> {
+/// Signs and sends API requests
+///
+/// The secret requests are signed with is scraped from the website once, then kept in memory and
+/// in a cache file shared between runs. It is scraped again when a signed request is rejected,
+/// in case Weverse changed it. A secret that was scraped in this run is known to be current, so
+/// requests signed with it that are rejected are not retried.
+#[derive(Clone, Default, Debug)]
+pub(crate) struct Signer {
+    domains: Domains,
+    secret: Arc<Mutex<Option<Secret>>>,
+    cache_file: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug)]
+struct Secret {
+    key: Vec<u8>,
+    /// Scraped from the website in this run rather than loaded from the cache file
+    verified: bool,
+}
+
+impl Signer {
+    pub(crate) fn new(domains: Domains, cache_file: Option<PathBuf>) -> Self {
+        Self {
+            domains,
+            secret: Default::default(),
+            cache_file,
+        }
+    }
+
+    pub(crate) fn domains(&self) -> &Domains {
+        &self.domains
+    }
+
+    /// Send a signed GET request to the API
+    pub(crate) async fn get(
+        &self,
+        client: &ClientWithMiddleware,
+        path: &str,
+        auth: &str,
+    ) -> Result<Response> {
+        self.send(client, Method::GET, path, auth).await
+    }
+
+    /// Send a signed request to the API, with a newly scraped secret if the cached one is rejected
+    pub(crate) async fn send(
+        &self,
+        client: &ClientWithMiddleware,
+        method: Method,
+        path: &str,
+        auth: &str,
+    ) -> Result<Response> {
+        let secret = self.secret(client).await?;
+        let response = self
+            .send_signed(client, method.clone(), path, auth, &secret.key)
+            .await?;
+        // A verified secret is current, the authorization must have been rejected
+        let rejected = matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        );
+        if secret.verified || !rejected {
+            return Ok(response);
+        }
+
+        match self.rescrape(client, &secret.key).await? {
+            Some(key) => self.send_signed(client, method, path, auth, &key).await,
+            None => Ok(response),
+        }
+    }
+
+    async fn send_signed(
+        &self,
+        client: &ClientWithMiddleware,
+        method: Method,
+        path: &str,
+        auth: &str,
+        secret: &[u8],
+    ) -> Result<Response> {
+        let url = compute_url(&self.domains, path, secret).await?;
+        Ok(client
+            .request(method, url.as_str())
+            .header(header::REFERER, REFERER)
+            .header(header::AUTHORIZATION, auth)
+            .send()
+            .await?)
+    }
+
+    /// Secret from memory, the cache file or the website, in that order
+    async fn secret(&self, client: &ClientWithMiddleware) -> Result<Secret> {
+        let mut secret = self.secret.lock().await;
+        if let Some(secret) = secret.as_ref() {
+            return Ok(secret.clone());
+        }
+        if let Some(key) = self.load_cached_secret().await {
+            let cached = Secret {
+                key,
+                verified: false,
+            };
+            *secret = Some(cached.clone());
+            return Ok(cached);
+        }
+
+        let key = get_secret(client, &self.domains).await?;
+        self.store_cached_secret(&key).await;
+        let scraped = Secret {
+            key,
+            verified: true,
+        };
+        *secret = Some(scraped.clone());
+        Ok(scraped)
+    }
+
+    /// Secret to retry a request signed with the `rejected` one with, `None` if the `rejected`
+    /// secret is still current
+    ///
+    /// The secret is only scraped again if no other request already replaced or verified it.
+    async fn rescrape(
+        &self,
+        client: &ClientWithMiddleware,
+        rejected: &[u8],
+    ) -> Result<Option<Vec<u8>>> {
+        let mut secret = self.secret.lock().await;
+        match secret.as_ref() {
+            Some(secret) if secret.key != rejected => return Ok(Some(secret.key.clone())),
+            Some(secret) if secret.verified => return Ok(None),
+            _ => {}
+        }
+
+        let key = get_secret(client, &self.domains).await?;
+        let changed = key != rejected;
+        if changed {
+            self.store_cached_secret(&key).await;
+        }
+        *secret = Some(Secret {
+            key: key.clone(),
+            verified: true,
+        });
+        Ok(changed.then_some(key))
+    }
+
+    /// Secrets in the cache file are keyed by website, so other domains don't share them
+    async fn load_cached_secret(&self) -> Option<Vec<u8>> {
+        let contents = fs::read_to_string(self.cache_file.as_ref()?).await.ok()?;
+        let secrets: HashMap<String, String> = toml::from_str(&contents).ok()?;
+        secrets
+            .get(&self.domains.web)
+            .map(|s| s.as_bytes().to_vec())
+    }
+
+    /// Failing to write the cache only costs scraping the secret again next run
+    async fn store_cached_secret(&self, secret: &[u8]) {
+        let Some(filename) = &self.cache_file else {
+            return;
+        };
+        let Ok(secret) = std::str::from_utf8(secret) else {
+            return;
+        };
+        let contents = fs::read_to_string(filename).await.unwrap_or_default();
+        let mut secrets: HashMap<String, String> = toml::from_str(&contents).unwrap_or_default();
+        secrets.insert(self.domains.web.clone(), secret.to_owned());
+        let Ok(contents) = toml::to_string(&secrets) else {
+            return;
+        };
+        let _ = write_replacing(filename, &contents).await;
+    }
+}
+
+/// Write to a temporary file next to `path` and move it over `path`, so runs reading the file
+/// at the same time never see it half written
+async fn write_replacing(path: &Path, contents: &str) -> std::io::Result<()> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    fs::create_dir_all(directory).await?;
+    let temp_path = directory.join(format!(".weverse_secret-{}", Uuid::new_v4()));
+    let result = async {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .await?;
+        file.write_all(contents.as_bytes()).await?;
+        file.sync_all().await?;
+        fs::rename(&temp_path, path).await?;
+        Ok(())
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+static SECRET_CACHE_FILE_NAME: &str = "sns-archive/weverse_secret";
+
+pub(crate) fn secret_cache_file() -> Option<PathBuf> {
+    directories::BaseDirs::new().map(|d| d.data_dir().join(SECRET_CACHE_FILE_NAME))
+}
+
+/// Scrape the secret API requests are signed with from the scripts of the website
+pub(crate) async fn get_secret(
+    client: &ClientWithMiddleware,
+    domains: &Domains,
+) -> Result<Vec<u8>> {
     static JS_SEL: LazyLock<scraper::Selector> =
         LazyLock::new(|| scraper::Selector::parse("link").unwrap());
 
     // Get JS scripts
-    let resp = client
-        .get("https://weverse.io")
-        .send()
-        .await?
-        .text()
-        .await?;
+    let page = Url::parse(&domains.web)?;
+    let resp = client.get(page.clone()).send().await?.text().await?;
     let links: Vec<_> = scraper::Html::parse_document(&resp)
         .select(&JS_SEL)
         .filter_map(|l| l.attr("href"))
-        .map(|x| x.to_owned())
+        .filter_map(|x| page.join(x).ok())
         .collect();
 
     // Find first HMAC in JS script data
@@ -44,11 +245,11 @@ pub(crate) async fn get_secret(client: &Client) -> Result<Vec<u8>> {
 }
 
 /// Find anything that looks like an HMAC in js files
-async fn find_hmac(client: &Client, url: &str) -> Result<Vec<u8>, ()> {
+async fn find_hmac(client: &ClientWithMiddleware, url: &Url) -> Result<Vec<u8>, ()> {
     static SECRET_RE: LazyLock<Regex> =
         LazyLock::new(|| Regex::new(r#""(?P<hmac>[0-9a-f]{40})","HMAC_ACTIVE""#).unwrap());
 
-    let resp = client.get(url).send().await.map_err(|_| ())?;
+    let resp = client.get(url.clone()).send().await.map_err(|_| ())?;
     if !resp.status().is_success() {
         return Err(());
     }
@@ -59,7 +260,7 @@ async fn find_hmac(client: &Client, url: &str) -> Result<Vec<u8>, ()> {
     Err(())
 }
 
-pub(crate) async fn compute_url(base_url: &str, secret: &[u8]) -> Result<Url> {
+pub(crate) async fn compute_url(domains: &Domains, base_url: &str, secret: &[u8]) -> Result<Url> {
     let pad = OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000;
     let data_to_hash: Vec<_> = base_url
         .as_bytes()
@@ -73,8 +274,7 @@ pub(crate) async fn compute_url(base_url: &str, secret: &[u8]) -> Result<Url> {
     mac.update(&data_to_hash);
     let digest = general_purpose::STANDARD.encode(mac.finalize().into_bytes());
 
-    static DOMAIN: &str = "https://global.apis.naver.com/weverse/wevweb";
-    let url = format!("{}{}", DOMAIN, base_url);
+    let url = format!("{}{}", domains.api, base_url);
 
     let mut url = Url::parse(&url)?;
     url.query_pairs_mut()
@@ -96,31 +296,156 @@ impl LoginInfo {
             email: email.to_owned(),
         }
     }
+
+    pub(crate) fn email(&self) -> &str {
+        &self.email
+    }
 }
 
-pub(crate) async fn login(client: &Client, login_info: &LoginInfo) -> Result<String> {
+pub(crate) async fn login(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    logins: &SavedLogins,
+    login_info: &LoginInfo,
+) -> Result<SavedAuthorization> {
     // Check saved authorization
-    let auth = if let Ok(Some(auth)) = load_saved_authorization(&login_info.email).await {
+    let auth = match logins.load(&login_info.email).await? {
         // Check login status
-        validate_or_refresh_bearer(client, &login_info.email, &auth).await?
-    } else {
-        return Err(WeverseError::Login)?;
+        Some(auth) => {
+            validate_or_refresh_bearer(client, signer.domains(), logins, &login_info.email, &auth)
+                .await?
+        }
+        None => return Err(WeverseError::NotLoggedIn(login_info.email.clone()))?,
     };
 
     // Disable password login because it requires a captcha now
     // let access_token = email_login(client, login_info).await?;
 
     // Check login status
-    me(client, &auth.authorization).await?;
+    me(client, signer, &auth.bearer()).await?;
 
     // Save authorization
-    store_authorization(&login_info.email, &auth.authorization, &auth.refresh).await?;
+    logins.store(&login_info.email, &auth).await?;
+
+    Ok(auth)
+}
+
+/// Access and refresh tokens of a browser session
+#[derive(Clone, Debug)]
+pub struct Tokens {
+    pub access: String,
+    pub refresh: String,
+}
+
+impl Tokens {
+    /// Find the tokens in cookies exported from a browser logged in to weverse.io
+    ///
+    /// Both the `cookies.txt` format and JSON lists of cookies with a name and value are read.
+    pub fn from_cookies(export: &str) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct Cookie {
+            name: String,
+            value: String,
+        }
+        let cookies: Vec<(String, String)> = match serde_json::from_str::<Vec<Cookie>>(export) {
+            Ok(cookies) => cookies.into_iter().map(|c| (c.name, c.value)).collect(),
+            // Netscape format, tab separated with the name and value last
+            Err(_) => export
+                .lines()
+                .map(|l| l.strip_prefix("#HttpOnly_").unwrap_or(l))
+                .filter(|l| !l.starts_with('#'))
+                .filter_map(|l| {
+                    let fields: Vec<_> = l.split('\t').collect();
+                    match fields[..] {
+                        [_, _, _, _, _, name, value] => Some((name.to_owned(), value.to_owned())),
+                        _ => None,
+                    }
+                })
+                .collect(),
+        };
+
+        let find = |name: &str| {
+            cookies
+                .iter()
+                .rev()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+                .ok_or(WeverseError::Cookies)
+        };
+        Ok(Self {
+            access: find(ACCESS_TOKEN_COOKIE)?,
+            refresh: find(REFRESH_TOKEN_COOKIE)?,
+        })
+    }
+}
+
+static ACCESS_TOKEN_COOKIE: &str = "we2_access_token";
+static REFRESH_TOKEN_COOKIE: &str = "we2_refresh_token";
+
+/// Check tokens of a browser session work and save them for later logins
+pub(crate) async fn login_with_tokens(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    logins: &SavedLogins,
+    login_info: &LoginInfo,
+    tokens: &Tokens,
+) -> Result<SavedAuthorization> {
+    let auth = SavedAuthorization {
+        authorization: strip_bearer(tokens.access.trim()).to_owned(),
+        refresh: tokens.refresh.trim().to_owned(),
+    };
+    me(client, signer, &auth.bearer()).await?;
+    logins.store(&login_info.email, &auth).await?;
+    Ok(auth)
+}
+
+/// Expiry of the saved tokens of an account
+#[derive(Clone, Debug)]
+pub struct LoginStatus {
+    /// `None` if the token doesn't say when it expires
+    pub access_expiry: Option<OffsetDateTime>,
+    pub refresh_expiry: Option<OffsetDateTime>,
+}
+
+/// Expiry of the saved tokens of an account, `None` if it is not logged in
+pub async fn login_status(login_info: &LoginInfo) -> Result<Option<LoginStatus>> {
+    Ok(SavedLogins::default_file()?
+        .load(&login_info.email)
+        .await?
+        .map(|auth| LoginStatus {
+            access_expiry: token_expiry(&auth.authorization),
+            refresh_expiry: token_expiry(&auth.refresh),
+        }))
+}
+
+/// Delete the saved tokens of an account, returns whether there were any
+pub async fn logout(login_info: &LoginInfo) -> Result<bool> {
+    SavedLogins::default_file()?.remove(&login_info.email).await
+}
 
-    Ok(auth.authorization)
+/// Expiry in the claims of a JWT
+fn token_expiry(token: &str) -> Option<OffsetDateTime> {
+    #[derive(Deserialize)]
+    struct Claims {
+        exp: i64,
+    }
+    let payload = strip_bearer(token).split('.').nth(1)?;
+    let payload = general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: Claims = serde_json::from_slice(&payload).ok()?;
+    OffsetDateTime::from_unix_timestamp(claims.exp).ok()
+}
+
+fn strip_bearer(token: &str) -> &str {
+    token.strip_prefix("Bearer ").unwrap_or(token)
 }
 
-async fn validate_or_refresh_bearer(
-    client: &Client,
+/// Check the bearer token and get a new one with the refresh token if it is about to expire
+pub(crate) async fn validate_or_refresh_bearer(
+    client: &ClientWithMiddleware,
+    domains: &Domains,
+    logins: &SavedLogins,
     username: &str,
     auth: &SavedAuthorization,
 ) -> Result<SavedAuthorization> {
@@ -129,22 +454,27 @@ async fn validate_or_refresh_bearer(
     struct ValidateResponse {
         refresh_required: bool,
     }
-    let valid: ValidateResponse = client
-        .get("https://accountapi.weverse.io/api/v1/token/validate")
+    let response = client
+        .get(format!("{}/api/v1/token/validate", domains.account))
         .header("x-acc-service-id", "weverse")
-        .header(
-            header::AUTHORIZATION,
-            &format!("Bearer {}", auth.authorization),
-        )
+        .header(header::AUTHORIZATION, auth.bearer())
         .send()
-        .await?
-        .error_for_status()?
-        .json()
         .await?;
+    // An expired access token is rejected rather than reported as needing a refresh
+    if response.status() != StatusCode::UNAUTHORIZED {
+        let valid: ValidateResponse = response.error_for_status()?.json().await?;
+        if !valid.refresh_required {
+            // Return current auth if not update is required
+            return Ok(auth.clone());
+        }
+    }
 
-    if !valid.refresh_required {
-        // Return current auth if not update is required
-        return Ok(auth.clone());
+    let expired = || WeverseError::TokenExpired {
+        email: username.to_owned(),
+        expiry: token_expiry(&auth.refresh),
+    };
+    if token_expiry(&auth.refresh).is_some_and(|expiry| expiry <= OffsetDateTime::now_utc()) {
+        return Err(expired().into());
     }
 
     // Get a new bearer token with using the refresh token
@@ -159,90 +489,345 @@ async fn validate_or_refresh_bearer(
         access_token: String,
         refresh_token: String,
     }
-    let new_token: RefreshResponse = client
-        .post("https://accountapi.weverse.io/api/v1/token/refresh")
+    let response = client
+        .post(format!("{}/api/v1/token/refresh", domains.account))
         .json(&RefreshRequestData {
             refresh_token: auth.refresh.clone(),
         })
         .send()
-        .await?
-        .error_for_status()?
-        .json()
         .await?;
+    if response.status().is_client_error() {
+        return Err(expired().into());
+    }
+    let new_token: RefreshResponse = response.error_for_status()?.json().await?;
     let new_saved_auth = SavedAuthorization {
         authorization: new_token.access_token,
         refresh: new_token.refresh_token,
     };
-    store_authorization(username, &auth.authorization, &auth.refresh).await?;
+    logins.store(username, &new_saved_auth).await?;
 
     Ok(new_saved_auth)
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
-struct SavedAuthorization {
-    authorization: String,
-    refresh: String,
+pub(crate) struct SavedAuthorization {
+    pub(crate) authorization: String,
+    pub(crate) refresh: String,
+}
+
+impl SavedAuthorization {
+    /// Authorization header of requests, older logins were saved with the `Bearer` prefix
+    pub(crate) fn bearer(&self) -> String {
+        format!("Bearer {}", strip_bearer(&self.authorization))
+    }
 }
 
 static SAVED_AUTH_FILE_NAME: &str = "sns-archive/weverse_logins";
 
-fn saved_authorization_file() -> Result<PathBuf> {
-    directories::BaseDirs::new()
-        .map(|d| d.data_dir().join(SAVED_AUTH_FILE_NAME))
-        .ok_or_else(|| WeverseError::SavedAuthFile.into())
-}
-
-async fn load_saved_authorization(username: &str) -> Result<Option<SavedAuthorization>> {
-    let filename = saved_authorization_file()?;
-    let contents = fs::read_to_string(filename).await?;
-    let authorizations: HashMap<String, SavedAuthorization> = toml::from_str(&contents)?;
-    Ok(authorizations.get(username).cloned())
-}
-
-async fn store_authorization(username: &str, authoriazation: &str, refresh: &str) -> Result<()> {
-    static AUTH_FILE_MTX: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
-    let _guard = AUTH_FILE_MTX.lock().await;
-
-    let filename = saved_authorization_file()?;
-    fs::create_dir_all(&filename.parent().unwrap()).await?;
-    let contents = fs::read_to_string(&filename).await.unwrap_or_default();
-    let mut authorizations: HashMap<String, SavedAuthorization> = toml::from_str(&contents)?;
-    authorizations.insert(
-        username.to_string(),
-        SavedAuthorization {
-            authorization: authoriazation.to_string(),
-            refresh: refresh.to_string(),
-        },
-    );
-
-    let mut file = fs::File::create(filename).await?;
-    file.write_all(toml::to_string(&authorizations)?.as_bytes())
-        .await?;
+/// Held while changing a saved logins file
+static AUTH_FILE_MTX: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+/// File with the saved tokens of every logged in account, keyed by email
+///
+/// The file is only readable by its owner and replaced as a whole on every change, so a crash
+/// while saving can't lose the tokens of other accounts.
+#[derive(Clone, Debug)]
+pub(crate) struct SavedLogins {
+    path: PathBuf,
+}
+
+impl SavedLogins {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub(crate) fn default_file() -> Result<Self> {
+        directories::BaseDirs::new()
+            .map(|d| Self::new(d.data_dir().join(SAVED_AUTH_FILE_NAME)))
+            .ok_or_else(|| WeverseError::SavedAuthFile.into())
+    }
+
+    pub(crate) async fn load(&self, username: &str) -> Result<Option<SavedAuthorization>> {
+        Ok(self.read().await?.remove(username))
+    }
 
-    Ok(())
+    pub(crate) async fn store(&self, username: &str, auth: &SavedAuthorization) -> Result<()> {
+        let _guard = AUTH_FILE_MTX.lock().await;
+        let mut authorizations = self.read().await?;
+        authorizations.insert(username.to_owned(), auth.clone());
+        self.write(&authorizations).await
+    }
+
+    /// Delete the tokens of an account, returns whether there were any
+    pub(crate) async fn remove(&self, username: &str) -> Result<bool> {
+        let _guard = AUTH_FILE_MTX.lock().await;
+        let mut authorizations = self.read().await?;
+        if authorizations.remove(username).is_none() {
+            return Ok(false);
+        }
+        self.write(&authorizations).await?;
+        Ok(true)
+    }
+
+    async fn read(&self) -> Result<HashMap<String, SavedAuthorization>> {
+        match fs::read_to_string(&self.path).await {
+            Ok(contents) => Ok(toml::from_str(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write to a temporary file next to the saved logins and move it over them
+    async fn write(&self, authorizations: &HashMap<String, SavedAuthorization>) -> Result<()> {
+        let directory = self.path.parent().ok_or(WeverseError::SavedAuthFile)?;
+        fs::create_dir_all(directory).await?;
+        let temp_path = directory.join(format!(".weverse_logins-{}", Uuid::new_v4()));
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let result = async {
+            let mut file = options.open(&temp_path).await?;
+            file.write_all(toml::to_string(authorizations)?.as_bytes())
+                .await?;
+            file.sync_all().await?;
+            fs::rename(&temp_path, &self.path).await?;
+            Ok(())
+        }
+        .await;
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path).await;
+        }
+        result
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use dotenv::dotenv;
-
     use super::*;
+    use crate::mock::{MockServer, AUTH, REFRESH, SECRET};
 
     #[tokio::test]
     async fn endpoint_auth() {
-        let client = Client::new();
-        assert!(get_secret(&client).await.is_ok())
+        let server = MockServer::start().await;
+        let secret = get_secret(&server.client(), &server.domains())
+            .await
+            .unwrap();
+        assert_eq!(secret, SECRET.as_bytes());
     }
 
     #[tokio::test]
-    async fn try_login() {
-        // Read secrets
-        let _ = dotenv();
-        let email = std::env::var("WEVERSE_EMAIL").unwrap();
-        // let password = std::env::var("WEVERSE_PASSWORD").unwrap();
-        let login_info = LoginInfo { email };
-
-        let client = Client::new();
-        login(&client, &login_info).await.unwrap();
+    async fn signature() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let domains = server.domains();
+        let path = "/users/v1.0/users/me?appId=1&wpf=pc";
+        for (secret, status) in [(SECRET.as_bytes(), 200), (b"wrong".as_slice(), 401)] {
+            let url = compute_url(&domains, path, secret).await.unwrap();
+            let response = client
+                .get(url.as_str())
+                .header(header::AUTHORIZATION, AUTH)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+        }
+    }
+
+    #[tokio::test]
+    async fn validate_token() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let dir = tempfile::tempdir().unwrap();
+        let logins = SavedLogins::new(dir.path().join("weverse_logins"));
+        let auth = SavedAuthorization {
+            authorization: AUTH.to_owned(),
+            refresh: REFRESH.to_owned(),
+        };
+        let validated =
+            validate_or_refresh_bearer(&client, &server.domains(), &logins, "a@example.com", &auth)
+                .await
+                .unwrap();
+        assert_eq!(validated.authorization, AUTH);
+        me(&client, &server.signer(), &validated.bearer())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn refresh_saves_new_tokens() {
+        let server = MockServer::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let logins = SavedLogins::new(dir.path().join("weverse_logins"));
+        let auth = SavedAuthorization {
+            authorization: "expired-access-token".to_owned(),
+            refresh: REFRESH.to_owned(),
+        };
+        logins.store("a@example.com", &auth).await.unwrap();
+
+        let refreshed = validate_or_refresh_bearer(
+            &server.client(),
+            &server.domains(),
+            &logins,
+            "a@example.com",
+            &auth,
+        )
+        .await
+        .unwrap();
+        assert_eq!(refreshed.bearer(), AUTH);
+        assert_eq!(refreshed.refresh, "mock-refresh-token-2");
+        let saved = logins.load("a@example.com").await.unwrap().unwrap();
+        assert_eq!(saved.authorization, refreshed.authorization);
+        assert_eq!(saved.refresh, refreshed.refresh);
+    }
+
+    #[tokio::test]
+    async fn expired_refresh_token() {
+        let server = MockServer::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let logins = SavedLogins::new(dir.path().join("weverse_logins"));
+        let auth = SavedAuthorization {
+            authorization: "expired-access-token".to_owned(),
+            refresh: "expired-refresh-token".to_owned(),
+        };
+        let err = validate_or_refresh_bearer(
+            &server.client(),
+            &server.domains(),
+            &logins,
+            "a@example.com",
+            &auth,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<WeverseError>(),
+            Some(WeverseError::TokenExpired { email, .. }) if email == "a@example.com"
+        ));
+    }
+
+    #[tokio::test]
+    async fn saved_logins() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sns-archive/weverse_logins");
+        let logins = SavedLogins::new(path.clone());
+        assert!(logins.load("a@example.com").await.unwrap().is_none());
+
+        for email in ["a@example.com", "b@example.com"] {
+            let auth = SavedAuthorization {
+                authorization: format!("access-{}", email),
+                refresh: format!("refresh-{}", email),
+            };
+            logins.store(email, &auth).await.unwrap();
+        }
+        let a = logins.load("a@example.com").await.unwrap().unwrap();
+        assert_eq!(a.authorization, "access-a@example.com");
+        assert!(logins.remove("b@example.com").await.unwrap());
+        assert!(!logins.remove("b@example.com").await.unwrap());
+        assert!(logins.load("a@example.com").await.unwrap().is_some());
+
+        // Only the saved logins are left in the directory, readable by their owner only
+        let files: Vec<_> = std::fs::read_dir(path.parent().unwrap()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[tokio::test]
+    async fn secret_scraped_once() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let signer = server.signer();
+        for _ in 0..3 {
+            me(&client, &signer, AUTH).await.unwrap();
+        }
+        assert_eq!(server.requests("/web/"), 1);
+    }
+
+    #[tokio::test]
+    async fn stale_secret_rescraped() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let domains = server.domains();
+        let dir = tempfile::tempdir().unwrap();
+        let cache_file = dir.path().join("weverse_secret");
+        let stale = HashMap::from([(domains.web.clone(), "f".repeat(40))]);
+        std::fs::write(&cache_file, toml::to_string(&stale).unwrap()).unwrap();
+
+        let signer = Signer::new(domains.clone(), Some(cache_file.clone()));
+        me(&client, &signer, AUTH).await.unwrap();
+        assert_eq!(server.requests("/web/"), 1);
+        let cached: HashMap<String, String> =
+            toml::from_str(&std::fs::read_to_string(&cache_file).unwrap()).unwrap();
+        assert_eq!(cached[&domains.web], SECRET);
+
+        // The next run uses the cached secret without scraping
+        let signer = Signer::new(domains, Some(cache_file));
+        me(&client, &signer, AUTH).await.unwrap();
+        assert_eq!(server.requests("/web/"), 1);
+    }
+
+    #[tokio::test]
+    async fn current_secret_not_rescraped() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let domains = server.domains();
+        let dir = tempfile::tempdir().unwrap();
+        let cache_file = dir.path().join("weverse_secret");
+        let cached = HashMap::from([(domains.web.clone(), SECRET.to_owned())]);
+        std::fs::write(&cache_file, toml::to_string(&cached).unwrap()).unwrap();
+
+        // The secret is only scraped again after the first rejection, and as it is unchanged the
+        // request is not retried
+        let signer = Signer::new(domains, Some(cache_file.clone()));
+        for _ in 0..3 {
+            let response = signer
+                .get(
+                    &client,
+                    "/users/v1.0/users/me?appId=1&wpf=pc",
+                    "Bearer wrong",
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert_eq!(server.requests("/web/"), 1);
+        assert_eq!(server.requests("/api/users/v1.0/users/me"), 3);
+
+        // Only the cache file is left in the directory
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+    }
+
+    #[test]
+    fn tokens_from_cookies() {
+        let json = r#"[
+            {"domain": ".weverse.io", "name": "we2_access_token", "value": "access"},
+            {"domain": ".weverse.io", "name": "we2_refresh_token", "value": "refresh"}
+        ]"#;
+        let netscape = "# Netscape HTTP Cookie File\n\
+            .weverse.io\tTRUE\t/\tTRUE\t1700000000\twe2_access_token\taccess\n\
+            #HttpOnly_.weverse.io\tTRUE\t/\tTRUE\t1700000000\twe2_refresh_token\trefresh\n";
+        for export in [json, netscape] {
+            let tokens = Tokens::from_cookies(export).unwrap();
+            assert_eq!(tokens.access, "access");
+            assert_eq!(tokens.refresh, "refresh");
+        }
+        assert!(Tokens::from_cookies("[]").is_err());
+    }
+
+    #[test]
+    fn jwt_expiry() {
+        let claims = general_purpose::URL_SAFE_NO_PAD.encode(r#"{"sub":"1","exp":1700000000}"#);
+        let token = format!("Bearer eyJhbGciOiJIUzI1NiJ9.{}.c2lnbmF0dXJl", claims);
+        assert_eq!(
+            token_expiry(&token).unwrap().unix_timestamp(),
+            1_700_000_000
+        );
+        assert!(token_expiry("opaque-token").is_none());
     }
 }
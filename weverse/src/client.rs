@@ -2,54 +2,140 @@ use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 use anyhow::Result;
-use reqwest::Client;
+use reqwest_middleware::ClientWithMiddleware;
 
-use crate::auth::{login, LoginInfo};
-use crate::endpoint::artist_tab_posts::{ArtistPosts, Tab};
-use crate::endpoint::community_id::{community_id, CommunityId};
+use crate::auth::{
+    login, login_with_tokens, secret_cache_file, validate_or_refresh_bearer, LoginInfo,
+    SavedAuthorization, SavedLogins, Signer, Tokens,
+};
+use crate::domains::Domains;
+use crate::endpoint::artist_tab_posts::{ArtistPosts, DateRange, Tab};
+use crate::endpoint::comments::{comment, ArtistComments, Comment, CommentSource};
+use crate::endpoint::community_id::{
+    joined_communities, resolve_community, Community, CommunityId,
+};
+use crate::endpoint::letter::Letters;
+use crate::endpoint::media::{media_categories, MediaCategory, MediaPosts, MediaSource};
+use crate::endpoint::member::MemberFilter;
 use crate::endpoint::moments::Moments;
+use crate::endpoint::notice::{notice, Notice, Notices};
 use crate::endpoint::post::{post, ArtistPost};
 
 #[derive(Clone, Debug)]
-pub struct AuthenticatedWeverseClient<'a> {
-    reqwest_client: &'a Client,
-    auth: String,
+pub struct AuthenticatedWeverseClient {
+    reqwest_client: ClientWithMiddleware,
+    signer: Signer,
+    logins: SavedLogins,
+    login_info: LoginInfo,
+    auth: SavedAuthorization,
     community_id_map: Arc<Mutex<HashMap<String, CommunityId>>>,
 }
 
-impl<'a> AuthenticatedWeverseClient<'a> {
+impl AuthenticatedWeverseClient {
     /// Create a new AuthenticatedWeverseClient
     pub async fn login(
-        reqwest_client: &'a Client,
+        reqwest_client: &ClientWithMiddleware,
         login_info: &LoginInfo,
-    ) -> Result<AuthenticatedWeverseClient<'a>> {
-        let auth = login(reqwest_client, login_info).await?;
-        Ok(Self {
+    ) -> Result<AuthenticatedWeverseClient> {
+        let signer = Signer::new(Domains::default(), secret_cache_file());
+        Self::login_with(
             reqwest_client,
+            login_info,
+            signer,
+            SavedLogins::default_file()?,
+        )
+        .await
+    }
+
+    /// Log in with the tokens saved in `logins`, signing requests with `signer`
+    pub(crate) async fn login_with(
+        reqwest_client: &ClientWithMiddleware,
+        login_info: &LoginInfo,
+        signer: Signer,
+        logins: SavedLogins,
+    ) -> Result<AuthenticatedWeverseClient> {
+        let auth = login(reqwest_client, &signer, &logins, login_info).await?;
+        Ok(Self {
+            reqwest_client: reqwest_client.clone(),
+            signer,
+            logins,
+            login_info: login_info.clone(),
             auth,
             community_id_map: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
+    /// Log in with the tokens of a browser session and save them for later logins
+    pub async fn login_with_tokens(
+        reqwest_client: &ClientWithMiddleware,
+        login_info: &LoginInfo,
+        tokens: &Tokens,
+    ) -> Result<AuthenticatedWeverseClient> {
+        let signer = Signer::new(Domains::default(), secret_cache_file());
+        let logins = SavedLogins::default_file()?;
+        let auth = login_with_tokens(reqwest_client, &signer, &logins, login_info, tokens).await?;
+        Ok(Self {
+            reqwest_client: reqwest_client.clone(),
+            signer,
+            logins,
+            login_info: login_info.clone(),
+            auth,
+            community_id_map: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Get a new bearer token if the current one is about to expire
+    ///
+    /// Clients that are kept for a long time should call this before making requests. Fails with
+    /// [`WeverseError::TokenExpired`](crate::WeverseError::TokenExpired) once the refresh token
+    /// expired and the account has to log in again.
+    pub async fn refresh(&mut self) -> Result<()> {
+        self.auth = validate_or_refresh_bearer(
+            &self.reqwest_client,
+            self.signer.domains(),
+            &self.logins,
+            self.login_info.email(),
+            &self.auth,
+        )
+        .await?;
+        Ok(())
+    }
+
     pub async fn artist_posts(
         &self,
         artist: &str,
         min_id: Option<String>,
         limit: Option<usize>,
+        members: MemberFilter,
+        range: DateRange,
     ) -> Result<ArtistPosts> {
         let community_id = self.get_community_id(artist).await?;
         Ok(ArtistPosts::init(
             community_id,
             Tab::ArtistPosts,
-            self.auth.clone(),
+            self.auth.bearer(),
+            self.signer.clone(),
             min_id,
             limit,
+            members,
+            range,
         ))
     }
 
-    pub async fn artist_moments(&self, artist: &str) -> Result<Vec<ArtistPost>> {
+    pub async fn artist_moments(
+        &self,
+        artist: &str,
+        members: &MemberFilter,
+    ) -> Result<Vec<ArtistPost>> {
         let community_id = self.get_community_id(artist).await?;
-        Moments::get_latest_moments(self.reqwest_client, &self.auth, community_id).await
+        Moments::get_latest_moments(
+            &self.reqwest_client,
+            &self.signer,
+            &self.auth.bearer(),
+            community_id,
+            members,
+        )
+        .await
     }
 
     pub async fn lives(
@@ -57,27 +143,135 @@ impl<'a> AuthenticatedWeverseClient<'a> {
         artist: &str,
         min_id: Option<String>,
         limit: Option<usize>,
+        members: MemberFilter,
+        range: DateRange,
     ) -> Result<ArtistPosts> {
         let community_id = self.get_community_id(artist).await?;
         Ok(ArtistPosts::init(
             community_id,
             Tab::Lives,
-            self.auth.clone(),
+            self.auth.bearer(),
+            self.signer.clone(),
             min_id,
             limit,
+            members,
+            range,
+        ))
+    }
+
+    /// Categories of the media tab of a community
+    pub async fn media_categories(&self, artist: &str) -> Result<Vec<MediaCategory>> {
+        let community_id = self.get_community_id(artist).await?;
+        media_categories(
+            &self.reqwest_client,
+            &self.signer,
+            &self.auth.bearer(),
+            community_id,
+        )
+        .await
+    }
+
+    /// Media posts of a category, or every media post of the community if `category_id` is `None`
+    pub async fn media(&self, artist: &str, category_id: Option<u64>) -> Result<MediaPosts> {
+        let source = match category_id {
+            Some(category_id) => MediaSource::Category(category_id),
+            None => MediaSource::All(self.get_community_id(artist).await?),
+        };
+        Ok(MediaPosts::init(
+            source,
+            self.auth.bearer(),
+            self.signer.clone(),
+        ))
+    }
+
+    /// Notices of a community, newest first
+    pub async fn notices(&self, artist: &str) -> Result<Notices> {
+        let community_id = self.get_community_id(artist).await?;
+        Ok(Notices::init(
+            community_id,
+            self.auth.bearer(),
+            self.signer.clone(),
+        ))
+    }
+
+    pub async fn notice(&self, notice_id: u64) -> Result<Notice> {
+        notice(
+            &self.reqwest_client,
+            &self.signer,
+            &self.auth.bearer(),
+            notice_id,
+        )
+        .await
+    }
+
+    /// Letters artists wrote to their fans, newest first
+    pub async fn letters(&self, artist: &str) -> Result<Letters> {
+        let community_id = self.get_community_id(artist).await?;
+        Ok(Letters::init(
+            community_id,
+            self.auth.bearer(),
+            self.signer.clone(),
         ))
     }
 
     pub async fn post(&self, post_id: &str) -> Result<ArtistPost> {
-        post(self.reqwest_client, &self.auth, post_id).await
+        post(
+            &self.reqwest_client,
+            &self.signer,
+            &self.auth.bearer(),
+            post_id,
+        )
+        .await
+    }
+
+    /// Comments written by artists under a post
+    pub fn artist_comments(&self, post_id: &str) -> ArtistComments {
+        ArtistComments::init(
+            CommentSource::Post(post_id.to_owned()),
+            self.auth.bearer(),
+            self.signer.clone(),
+        )
+    }
+
+    /// Comments written by artists anywhere in their community, newest first
+    pub async fn community_artist_comments(&self, artist: &str) -> Result<ArtistComments> {
+        let community_id = self.get_community_id(artist).await?;
+        Ok(ArtistComments::init(
+            CommentSource::Community(community_id),
+            self.auth.bearer(),
+            self.signer.clone(),
+        ))
+    }
+
+    pub async fn comment(&self, comment_id: &str) -> Result<Comment> {
+        comment(
+            &self.reqwest_client,
+            &self.signer,
+            &self.auth.bearer(),
+            comment_id,
+        )
+        .await
+    }
+
+    /// Communities the account joined
+    pub async fn joined_communities(&self) -> Result<Vec<Community>> {
+        joined_communities(&self.reqwest_client, &self.signer, &self.auth.bearer()).await
     }
 
+    /// Community of an artist, given as the artist code in its URLs, a weverse.io URL, the
+    /// community ID or the name of a joined community
     async fn get_community_id(&self, artist: &str) -> Result<CommunityId> {
         if let Some(id) = self.community_id_map.lock().unwrap().get(artist) {
             return Ok(*id);
         }
 
-        let id = community_id(self.reqwest_client, artist, &self.auth).await?;
+        let id = resolve_community(
+            &self.reqwest_client,
+            &self.signer,
+            artist,
+            &self.auth.bearer(),
+        )
+        .await?;
         self.community_id_map
             .lock()
             .unwrap()
@@ -85,3 +279,41 @@ impl<'a> AuthenticatedWeverseClient<'a> {
         Ok(id)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::mock::{MockServer, AUTH, REFRESH};
+
+    #[tokio::test]
+    async fn login_with_saved_tokens() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let dir = tempfile::tempdir().unwrap();
+        let logins = SavedLogins::new(dir.path().join("weverse_logins"));
+        let auth = SavedAuthorization {
+            authorization: AUTH.to_owned(),
+            refresh: REFRESH.to_owned(),
+        };
+        logins.store("a@example.com", &auth).await.unwrap();
+        let cache_file = dir.path().join("weverse_secret");
+        let signer = Signer::new(server.domains(), Some(cache_file.clone()));
+
+        let weverse = AuthenticatedWeverseClient::login_with(
+            &client,
+            &LoginInfo::new("a@example.com"),
+            signer,
+            logins,
+        )
+        .await
+        .unwrap();
+        let mut notices = weverse.notices("dreamcatcher").await.unwrap();
+        let notices_stream = notices.as_stream(&client).await;
+        futures::pin_mut!(notices_stream);
+        let short = notices_stream.next().await.unwrap().unwrap();
+        weverse.notice(short.id).await.unwrap();
+        assert!(cache_file.exists());
+    }
+}
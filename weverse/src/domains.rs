@@ -0,0 +1,25 @@
+/// Base URLs of the services the client talks to
+///
+/// The defaults are the production services, tests point every domain to a local mock server.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) struct Domains {
+    /// Weverse API, requests to it are signed with the secret of the website
+    pub api: String,
+    /// Account API for validating and refreshing tokens
+    pub account: String,
+    /// Weverse website, its scripts contain the secret used to sign API requests
+    pub web: String,
+    /// Naver video API, lists the streams of lives and media videos
+    pub vod: String,
+}
+
+impl Default for Domains {
+    fn default() -> Self {
+        Self {
+            api: "https://global.apis.naver.com/weverse/wevweb".to_owned(),
+            account: "https://accountapi.weverse.io".to_owned(),
+            web: "https://weverse.io".to_owned(),
+            vod: "https://global.apis.naver.com/rmcnmv/rmcnmv".to_owned(),
+        }
+    }
+}
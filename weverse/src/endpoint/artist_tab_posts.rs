@@ -2,14 +2,14 @@ use std::collections::VecDeque;
 
 use anyhow::Result;
 use futures::Stream;
-use reqwest::{header, Client};
+use reqwest_middleware::ClientWithMiddleware;
 use serde::Deserialize;
 use time::OffsetDateTime;
 
 use super::community_id::CommunityId;
-use super::member::Member;
-use super::{APP_ID, REFERER};
-use crate::auth::{compute_url, get_secret};
+use super::member::{Member, MemberFilter};
+use super::APP_ID;
+use crate::auth::Signer;
 use crate::utils::{deserialize_timestamp, slug};
 
 #[derive(Debug)]
@@ -30,9 +30,12 @@ pub struct ArtistPosts {
     all_ids: VecDeque<ArtistPostShort>,
     community_id: CommunityId,
     auth: String,
+    signer: Signer,
     tab: Tab,
     min_id: Option<String>,
     limit: Option<usize>,
+    members: MemberFilter,
+    range: DateRange,
     count: usize,
     // For pagination
     page_state: PageState,
@@ -61,13 +64,41 @@ impl Tab {
     }
 }
 
+/// Time window of posts to download, `since` is inclusive and `until` exclusive
+#[derive(Clone, Copy, Default, Debug)]
+pub struct DateRange {
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+}
+
+impl DateRange {
+    /// Whether either end of the range is set
+    pub fn is_bounded(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+
+    /// Whether `time` is before the start of the range
+    pub fn is_before(&self, time: &OffsetDateTime) -> bool {
+        self.since.is_some_and(|since| *time < since)
+    }
+
+    /// Whether `time` is at or after the end of the range
+    pub fn is_after(&self, time: &OffsetDateTime) -> bool {
+        self.until.is_some_and(|until| *time >= until)
+    }
+}
+
 impl ArtistPosts {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn init(
         community_id: CommunityId,
         tab: Tab,
         auth: String,
+        signer: Signer,
         min_id: Option<String>,
         limit: Option<usize>,
+        members: MemberFilter,
+        range: DateRange,
     ) -> Self {
         Self {
             all_ids: VecDeque::new(),
@@ -75,15 +106,18 @@ impl ArtistPosts {
             tab,
             min_id,
             limit,
+            members,
+            range,
             count: 0,
             auth,
+            signer,
             page_state: PageState::Inital,
         }
     }
 
     pub async fn as_stream<'a>(
         &'a mut self,
-        client: &'a Client,
+        client: &'a ClientWithMiddleware,
     ) -> impl Stream<Item = Result<ArtistPostShort>> + 'a {
         futures::stream::unfold(self, |state| async {
             if let Some(limit) = state.limit {
@@ -102,36 +136,36 @@ impl ArtistPosts {
                 // Reached last page
                 PageState::Done => None,
 
-                // Fetch next page
-                _ => {
+                // Fetch pages until one has posts of the wanted members and dates
+                _ => loop {
                     if let Err(e) = state.fetch_next_page(client).await {
                         return Some((Err(e), state));
                     }
-                    state
-                        .all_ids
-                        .pop_front()
-                        .map(|post_id| (Ok(post_id), state))
-                }
+                    if let Some(post_id) = state.all_ids.pop_front() {
+                        return Some((Ok(post_id), state));
+                    }
+                    if let PageState::Done = state.page_state {
+                        return None;
+                    }
+                },
             }
         })
     }
 
-    async fn fetch_next_page(&mut self, client: &Client) -> Result<()> {
-        let secret = get_secret(client).await?;
-
+    async fn fetch_next_page(&mut self, client: &ClientWithMiddleware) -> Result<()> {
         let after = match &self.page_state {
             PageState::Inital => "".to_string(),
             PageState::Next(a) => format!("after={}&", a),
             _ => unreachable!(),
         };
 
-        let url = compute_url(&self.tab.url(&self.community_id, &after), &secret).await?;
-
-        let response = client
-            .get(url.as_str())
-            .header(header::REFERER, REFERER)
-            .header(header::AUTHORIZATION, &self.auth)
-            .send()
+        let response = self
+            .signer
+            .get(
+                client,
+                &self.tab.url(&self.community_id, &after),
+                &self.auth,
+            )
             .await?
             .error_for_status()?;
         let post_page = response.json::<ArtistPostsResponse>().await?;
@@ -153,7 +187,14 @@ impl ArtistPosts {
                 self.page_state = PageState::Done;
                 return Ok(());
             }
-            self.all_ids.push_back(p);
+            // Posts are newest first, so every following post is before the range as well
+            if self.range.is_before(&p.time) {
+                self.page_state = PageState::Done;
+                return Ok(());
+            }
+            if !self.range.is_after(&p.time) && self.members.matches(&p.author) {
+                self.all_ids.push_back(p);
+            }
         }
 
         Ok(())
@@ -206,44 +247,111 @@ mod test {
     use futures::stream::StreamExt;
 
     use super::*;
-    use crate::utils::{setup, LOGIN_INFO};
+    use crate::mock::{MockServer, AUTH};
+
+    fn posts(
+        server: &MockServer,
+        tab: Tab,
+        min_id: Option<&str>,
+        members: MemberFilter,
+        range: DateRange,
+    ) -> ArtistPosts {
+        ArtistPosts::init(
+            CommunityId::new(14),
+            tab,
+            AUTH.to_owned(),
+            server.signer(),
+            min_id.map(str::to_owned),
+            None,
+            members,
+            range,
+        )
+    }
+
+    async fn post_ids(server: &MockServer, mut posts: ArtistPosts) -> Vec<String> {
+        let client = server.client();
+        let posts_stream = posts.as_stream(&client).await;
+        futures::pin_mut!(posts_stream);
+        let mut ids = Vec::new();
+        while let Some(post) = posts_stream.next().await {
+            ids.push(post.unwrap().post_id);
+        }
+        ids
+    }
+
+    #[test]
+    fn date_range() {
+        let at = |t| OffsetDateTime::from_unix_timestamp(t).unwrap();
+        let range = DateRange {
+            since: Some(at(100)),
+            until: Some(at(200)),
+        };
+        assert!(range.is_before(&at(99)));
+        assert!(!range.is_before(&at(100)));
+        assert!(!range.is_after(&at(199)));
+        assert!(range.is_after(&at(200)));
+        assert!(!DateRange::default().is_bounded());
+        assert!(!DateRange::default().is_before(&at(0)));
+        assert!(!DateRange::default().is_after(&at(i32::MAX.into())));
+    }
 
     #[tokio::test]
     async fn artist_posts_paging() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let mut artist_posts = ArtistPosts::init(
-            CommunityId::new(14),
+        let server = MockServer::start().await;
+        let all = posts(
+            &server,
             Tab::ArtistPosts,
-            auth.clone(),
-            None,
             None,
+            MemberFilter::default(),
+            DateRange::default(),
         );
-        let posts_stream = artist_posts.as_stream(&client).await;
-        futures::pin_mut!(posts_stream);
-        let mut ids = HashSet::new();
-        for _ in 0..30 {
-            let post = posts_stream.next().await.unwrap().unwrap();
-            dbg!(&post.post_id);
-            assert!(!ids.contains(&post.post_id));
-            ids.insert(post.post_id);
-        }
+        let ids = post_ids(&server, all).await;
+        assert_eq!(ids.len(), 5);
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), ids.len());
     }
 
     #[tokio::test]
     async fn lives_paging() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let mut artist_posts =
-            ArtistPosts::init(CommunityId::new(14), Tab::Lives, auth.clone(), None, None);
-        let posts_stream = artist_posts.as_stream(&client).await;
-        futures::pin_mut!(posts_stream);
-        let mut ids = HashSet::new();
-        for _ in 0..30 {
-            let post = posts_stream.next().await.unwrap().unwrap();
-            dbg!(&post.post_id);
-            assert!(!ids.contains(&post.post_id));
-            ids.insert(post.post_id);
-        }
+        let server = MockServer::start().await;
+        let all = posts(
+            &server,
+            Tab::Lives,
+            None,
+            MemberFilter::default(),
+            DateRange::default(),
+        );
+        let ids = post_ids(&server, all).await;
+        assert_eq!(ids, ["0-119057265", "0-118000001", "0-117000001"]);
+    }
+
+    #[tokio::test]
+    async fn bounds() {
+        let server = MockServer::start().await;
+        let until_stop_id = posts(
+            &server,
+            Tab::ArtistPosts,
+            Some("1-105000001"),
+            MemberFilter::default(),
+            DateRange::default(),
+        );
+        assert_eq!(
+            post_ids(&server, until_stop_id).await,
+            ["1-106622307", "1-106028137"]
+        );
+
+        // 2022-08-01 to 2022-10-01 KST, without DAMI
+        let members = MemberFilter {
+            include: Vec::new(),
+            exclude: vec!["DAMI".to_owned()],
+        };
+        let range = DateRange {
+            since: Some(OffsetDateTime::from_unix_timestamp(1659279600).unwrap()),
+            until: Some(OffsetDateTime::from_unix_timestamp(1664550000).unwrap()),
+        };
+        let window = posts(&server, Tab::ArtistPosts, None, members, range);
+        assert_eq!(
+            post_ids(&server, window).await,
+            ["1-105000001", "1-104000001"]
+        );
     }
 }
@@ -0,0 +1,87 @@
+use anyhow::Result;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use time::serde::rfc3339;
+use time::OffsetDateTime;
+
+use super::APP_ID;
+use crate::auth::Signer;
+use crate::utils::deserialize_timestamp;
+
+#[derive(Deserialize, Debug)]
+struct ChatMessagesResponse {
+    paging: Paging,
+    data: Vec<ChatMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Paging {
+    next_params: Option<NextParams>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NextParams {
+    after: String,
+}
+
+/// Message of a live chat replay
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    #[serde(rename = "messageId")]
+    pub id: String,
+    pub content: String,
+    #[serde(rename = "createdAt")]
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    #[serde(serialize_with = "rfc3339::serialize")]
+    pub time: OffsetDateTime,
+    pub profile: ChatProfile,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatProfile {
+    pub profile_name: String,
+    pub profile_type: String,
+}
+
+/// Fetch every message artists wrote in the chat of a live, oldest first
+pub(crate) async fn artist_chat_messages(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    auth: &str,
+    chat_id: &str,
+) -> Result<Vec<ChatMessage>> {
+    let mut messages = Vec::new();
+    let mut after = String::new();
+    loop {
+        let page = signer
+            .get(
+                client,
+                &format!(
+                    "/chat/v1.0/chat-{}/artistMessages?{after}\
+                    limit=100&\
+                    pagingType=CURSOR&\
+                    appId={}&\
+                    language=en&\
+                    platform=WEB&\
+                    wpf=pc",
+                    chat_id, APP_ID
+                ),
+                auth,
+            )
+            .await?
+            .error_for_status()?
+            .json::<ChatMessagesResponse>()
+            .await?;
+        messages.extend(page.data);
+
+        match page.paging.next_params {
+            Some(next_params) => after = format!("after={}&", next_params.after),
+            None => break,
+        }
+    }
+
+    Ok(messages)
+}
@@ -0,0 +1,294 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use futures::Stream;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use time::serde::rfc3339;
+use time::OffsetDateTime;
+
+use super::community_id::CommunityId;
+use super::member::Member;
+use super::APP_ID;
+use crate::auth::Signer;
+use crate::utils::{deserialize_timestamp, slug};
+
+#[derive(Debug)]
+enum PageState {
+    Inital,
+    Next(String),
+    Done,
+}
+
+/// Where artist comments are listed
+#[derive(Debug)]
+pub enum CommentSource {
+    /// Comments under a single post
+    Post(String),
+    /// Comments anywhere in a community, newest first
+    Community(CommunityId),
+}
+
+impl CommentSource {
+    fn url(&self, after: &str) -> String {
+        let (path, field_set) = match self {
+            Self::Post(post_id) => (
+                format!("post-{}/artistComments", post_id),
+                "postArtistCommentsV1",
+            ),
+            Self::Community(community_id) => (
+                format!("community-{}/artistTabComments", community_id.id()),
+                "artistTabCommentsV1",
+            ),
+        };
+        let params = format!(
+            "fieldSet={}&\
+                limit=20&\
+                pagingType=CURSOR&\
+                appId={}&\
+                language=en&\
+                platform=WEB&\
+                wpf=pc",
+            field_set, APP_ID
+        );
+        format!("/comment/v1.0/{path}?{after}{params}")
+    }
+}
+
+/// Comments written by artists, including their replies to other comments
+#[derive(Debug)]
+pub struct ArtistComments {
+    comments: VecDeque<Comment>,
+    source: CommentSource,
+    auth: String,
+    signer: Signer,
+    // For pagination
+    page_state: PageState,
+}
+
+impl ArtistComments {
+    pub(crate) fn init(source: CommentSource, auth: String, signer: Signer) -> Self {
+        Self {
+            comments: VecDeque::new(),
+            source,
+            auth,
+            signer,
+            page_state: PageState::Inital,
+        }
+    }
+
+    pub async fn as_stream<'a>(
+        &'a mut self,
+        client: &'a ClientWithMiddleware,
+    ) -> impl Stream<Item = Result<Comment>> + 'a {
+        futures::stream::unfold(self, |state| async {
+            // Pop off and return the next comment if it exists
+            if let Some(comment) = state.comments.pop_front() {
+                return Some((Ok(comment), state));
+            }
+
+            match &state.page_state {
+                // Reached last page
+                PageState::Done => None,
+
+                // Fetch next page
+                _ => {
+                    if let Err(e) = state.fetch_next_page(client).await {
+                        state.page_state = PageState::Done;
+                        return Some((Err(e), state));
+                    }
+                    state
+                        .comments
+                        .pop_front()
+                        .map(|comment| (Ok(comment), state))
+                }
+            }
+        })
+    }
+
+    async fn fetch_next_page(&mut self, client: &ClientWithMiddleware) -> Result<()> {
+        let after = match &self.page_state {
+            PageState::Inital => "".to_string(),
+            PageState::Next(a) => format!("after={}&", a),
+            _ => unreachable!(),
+        };
+
+        let comment_page = self
+            .signer
+            .get(client, &self.source.url(&after), &self.auth)
+            .await?
+            .error_for_status()?
+            .json::<CommentsResponse>()
+            .await?;
+
+        // Update page state
+        self.page_state = match comment_page.paging.next_params {
+            Some(next_params) => PageState::Next(next_params.after),
+            None => PageState::Done,
+        };
+        self.comments.extend(comment_page.data);
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct CommentsResponse {
+    paging: Paging,
+    data: Vec<Comment>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Paging {
+    next_params: Option<NextParams>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NextParams {
+    after: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    #[serde(rename = "commentId")]
+    pub id: String,
+    pub body: String,
+    #[serde(rename = "createdAt")]
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    #[serde(serialize_with = "rfc3339::serialize")]
+    pub time: OffsetDateTime,
+    pub author: Member,
+    pub parent: Option<CommentParent>,
+    /// Post the comment was written under, only included in community feeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<CommentRoot>,
+}
+
+/// What a comment was written under
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", content = "data")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CommentParent {
+    Post {},
+    Comment {
+        #[serde(rename = "commentId")]
+        id: String,
+    },
+}
+
+/// Post at the top of a comment thread
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "type", content = "data")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CommentRoot {
+    Post(RootPost),
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RootPost {
+    #[serde(rename = "postId")]
+    pub id: String,
+    pub plain_body: String,
+    pub author: Member,
+}
+
+impl Comment {
+    /// Post the comment was written under, if included in the response
+    pub fn root_post(&self) -> Option<&RootPost> {
+        match &self.root {
+            Some(CommentRoot::Post(post)) => Some(post),
+            None => None,
+        }
+    }
+
+    pub fn slug(&self) -> Result<String> {
+        slug(&self.time, &self.id, &self.author, Some(&self.body))
+    }
+
+    /// ID of the comment this is a reply to
+    pub fn parent_comment_id(&self) -> Option<&str> {
+        match &self.parent {
+            Some(CommentParent::Comment { id }) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+/// Fetch a comment given with a comment ID
+pub(crate) async fn comment(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    auth: &str,
+    comment_id: &str,
+) -> Result<Comment> {
+    let comment = signer
+        .get(
+            client,
+            &format!(
+            "/comment/v1.0/comment-{}?fieldSet=commentV1&appId={}&language=en&platform=WEB&wpf=pc",
+            comment_id, APP_ID
+        ),
+            auth,
+        )
+        .await?
+        .error_for_status()?
+        .json::<Comment>()
+        .await?;
+
+    Ok(comment)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use futures::stream::StreamExt;
+
+    use super::*;
+    use crate::mock::{MockServer, AUTH};
+
+    #[tokio::test]
+    async fn artist_comments_paging() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let mut comments = ArtistComments::init(
+            CommentSource::Post("1-106028137".to_owned()),
+            AUTH.to_owned(),
+            server.signer(),
+        );
+        let comments_stream = comments.as_stream(&client).await;
+        futures::pin_mut!(comments_stream);
+        let mut ids = HashSet::new();
+        while let Some(comment) = comments_stream.next().await {
+            let comment = comment.unwrap();
+            assert_eq!(comment.author.profile_type, "ARTIST");
+            assert!(!ids.contains(&comment.id));
+            ids.insert(comment.id);
+        }
+        assert_eq!(ids.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn community_artist_comments() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let mut comments = ArtistComments::init(
+            CommentSource::Community(CommunityId::new(14)),
+            AUTH.to_owned(),
+            server.signer(),
+        );
+        let comments_stream = comments.as_stream(&client).await;
+        futures::pin_mut!(comments_stream);
+        let mut count = 0;
+        while let Some(comment) = comments_stream.next().await {
+            let comment = comment.unwrap();
+            assert_eq!(comment.author.profile_type, "ARTIST");
+            assert!(comment.root_post().is_some());
+            count += 1;
+        }
+        assert_eq!(count, 3);
+    }
+}
@@ -1,10 +1,12 @@
 use anyhow::Result;
-use reqwest::{header, Client};
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Deserializer, Serialize};
+use url::form_urlencoded;
 
-use super::{APP_ID, REFERER};
-use crate::auth::{compute_url, get_secret};
+use super::APP_ID;
+use crate::auth::Signer;
 
 #[derive(Eq, PartialEq, Hash, Clone, Copy, Debug)]
 pub struct CommunityId(u64);
@@ -46,21 +48,93 @@ struct CommunityIdResponse {
     community_id: u64,
 }
 
-pub(crate) async fn community_id(client: &Client, artist: &str, auth: &str) -> Result<CommunityId> {
-    let secret = get_secret(client).await?;
+/// Community the logged in account joined
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Community {
+    #[serde(rename = "communityId")]
+    pub id: CommunityId,
+    #[serde(rename = "communityName")]
+    pub name: String,
+    /// Artist code in the URLs of the community, e.g. `dreamcatcher`
+    pub url_path: String,
+}
+
+/// Find a community by its ID, a weverse.io URL, the artist code in its URLs or its name
+///
+/// Names are only found among the communities the account joined.
+pub(crate) async fn resolve_community(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    artist: &str,
+    auth: &str,
+) -> Result<CommunityId> {
+    if let Ok(id) = artist.parse() {
+        return Ok(CommunityId(id));
+    }
+    let url_artist = url_artist(artist);
+    let artist = url_artist.as_deref().unwrap_or(artist);
+    match community_id(client, signer, artist, auth).await {
+        Ok(id) => Ok(id),
+        Err(e) => joined_communities(client, signer, auth)
+            .await?
+            .into_iter()
+            .find(|c| c.name.eq_ignore_ascii_case(artist))
+            .map(|c| c.id)
+            .ok_or(e),
+    }
+}
 
-    let url = compute_url(
-        &format!(
-            "/community/v1.0/communityIdUrlPathByUrlPathArtistCode?appId={}&keyword={}&language=en&platform=WEB&wpf=pc",
-            APP_ID, artist),
-        &secret)
+/// Artist code of a weverse.io URL, e.g. `dreamcatcher` of `https://weverse.io/dreamcatcher/feed`
+fn url_artist(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    if host != "weverse.io" && !host.ends_with(".weverse.io") {
+        return None;
+    }
+    url.path_segments()?
+        .find(|s| !s.is_empty())
+        .map(str::to_owned)
+}
+
+/// Communities the logged in account joined
+pub(crate) async fn joined_communities(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    auth: &str,
+) -> Result<Vec<Community>> {
+    let communities = signer
+        .get(
+            client,
+            &format!(
+                "/users/v1.0/users/me/joinedCommunities?appId={}&language=en&platform=WEB&wpf=pc",
+                APP_ID
+            ),
+            auth,
+        )
+        .await?
+        .error_for_status()?
+        .json::<Vec<Community>>()
         .await?;
+    Ok(communities)
+}
 
-    let id = client
-        .get(url.as_str())
-        .header(header::REFERER, REFERER)
-        .header(header::AUTHORIZATION, auth)
-        .send()
+pub(crate) async fn community_id(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    artist: &str,
+    auth: &str,
+) -> Result<CommunityId> {
+    let id = signer
+        .get(
+            client,
+            &format!(
+                "/community/v1.0/communityIdUrlPathByUrlPathArtistCode?appId={}&keyword={}&language=en&platform=WEB&wpf=pc",
+                APP_ID,
+                form_urlencoded::byte_serialize(artist.as_bytes()).collect::<String>()
+            ),
+            auth,
+        )
         .await?
         .error_for_status()?
         .json::<CommunityIdResponse>()
@@ -73,29 +147,99 @@ pub(crate) async fn community_id(client: &Client, artist: &str, auth: &str) -> R
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::utils::{setup, LOGIN_INFO};
+    use crate::mock::{MockServer, AUTH};
 
     #[tokio::test]
     async fn valid() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let id = community_id(&client, "dreamcatcher", auth).await.unwrap();
+        let server = MockServer::start().await;
+        let id = community_id(&server.client(), &server.signer(), "dreamcatcher", AUTH)
+            .await
+            .unwrap();
         assert_eq!(id, CommunityId(14));
     }
 
     #[tokio::test]
     async fn uppercase() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let id = community_id(&client, "Dreamcatcher", auth).await.unwrap();
+        let server = MockServer::start().await;
+        let id = community_id(&server.client(), &server.signer(), "Dreamcatcher", AUTH)
+            .await
+            .unwrap();
         assert_eq!(id, CommunityId(14));
     }
 
+    #[tokio::test]
+    async fn keyword_with_space() {
+        let server = MockServer::start().await;
+        let id = community_id(&server.client(), &server.signer(), "LE SSERAFIM", AUTH)
+            .await
+            .unwrap();
+        assert_eq!(id, CommunityId(81));
+    }
+
     #[tokio::test]
     async fn invalid() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let res = community_id(&client, "invalidcommunity", auth).await;
+        let server = MockServer::start().await;
+        let res = community_id(&server.client(), &server.signer(), "invalidcommunity", AUTH).await;
         assert!(res.is_err());
     }
+
+    #[test]
+    fn artist_of_url() {
+        assert_eq!(
+            url_artist("https://weverse.io/dreamcatcher/feed").as_deref(),
+            Some("dreamcatcher")
+        );
+        assert_eq!(
+            url_artist("https://weverse.io/dreamcatcher").as_deref(),
+            Some("dreamcatcher")
+        );
+        assert_eq!(
+            url_artist("https://m.weverse.io/dreamcatcher/artist/1-2").as_deref(),
+            Some("dreamcatcher")
+        );
+        assert_eq!(url_artist("https://weverse.io/").as_deref(), None);
+        assert_eq!(
+            url_artist("https://example.com/dreamcatcher").as_deref(),
+            None
+        );
+        assert_eq!(url_artist("dreamcatcher").as_deref(), None);
+    }
+
+    #[tokio::test]
+    async fn resolve() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let signer = server.signer();
+        for artist in [
+            "14",
+            "dreamcatcher",
+            "https://weverse.io/dreamcatcher/feed",
+            "Dreamcatcher",
+        ] {
+            let id = resolve_community(&client, &signer, artist, AUTH)
+                .await
+                .unwrap();
+            assert_eq!(id, CommunityId(14), "{}", artist);
+        }
+        let id = resolve_community(&client, &signer, "Purple Kiss", AUTH)
+            .await
+            .unwrap();
+        assert_eq!(id, CommunityId(91));
+        assert!(
+            resolve_community(&client, &signer, "invalidcommunity", AUTH)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn joined() {
+        let server = MockServer::start().await;
+        let communities = joined_communities(&server.client(), &server.signer(), AUTH)
+            .await
+            .unwrap();
+        let names: Vec<_> = communities.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["DREAMCATCHER", "PURPLE KISS"]);
+        assert_eq!(communities[0].url_path, "dreamcatcher");
+    }
 }
@@ -0,0 +1,225 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::Stream;
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use sns_archive_common::{set_mtime, SavablePost};
+use time::serde::rfc3339;
+use time::OffsetDateTime;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use super::community_id::CommunityId;
+use super::member::Member;
+use super::APP_ID;
+use crate::auth::Signer;
+use crate::utils::{deserialize_timestamp, download_html, slug};
+
+#[derive(Debug)]
+enum PageState {
+    Inital,
+    Next(String),
+    Done,
+}
+
+/// Letters artists wrote to their fans, newest first
+#[derive(Debug)]
+pub struct Letters {
+    letters: VecDeque<Letter>,
+    community_id: CommunityId,
+    auth: String,
+    signer: Signer,
+    // For pagination
+    page_state: PageState,
+}
+
+impl Letters {
+    pub(crate) fn init(community_id: CommunityId, auth: String, signer: Signer) -> Self {
+        Self {
+            letters: VecDeque::new(),
+            community_id,
+            auth,
+            signer,
+            page_state: PageState::Inital,
+        }
+    }
+
+    pub async fn as_stream<'a>(
+        &'a mut self,
+        client: &'a ClientWithMiddleware,
+    ) -> impl Stream<Item = Result<Letter>> + 'a {
+        futures::stream::unfold(self, |state| async {
+            // Pop off and return the next letter if it exists
+            if let Some(letter) = state.letters.pop_front() {
+                return Some((Ok(letter), state));
+            }
+
+            match &state.page_state {
+                // Reached last page
+                PageState::Done => None,
+
+                // Fetch next page
+                _ => {
+                    if let Err(e) = state.fetch_next_page(client).await {
+                        state.page_state = PageState::Done;
+                        return Some((Err(e), state));
+                    }
+                    state.letters.pop_front().map(|letter| (Ok(letter), state))
+                }
+            }
+        })
+    }
+
+    async fn fetch_next_page(&mut self, client: &ClientWithMiddleware) -> Result<()> {
+        let after = match &self.page_state {
+            PageState::Inital => "".to_string(),
+            PageState::Next(a) => format!("after={}&", a),
+            _ => unreachable!(),
+        };
+
+        let letter_page = self
+            .signer
+            .get(
+                client,
+                &format!(
+                    "/letter/v1.0/community-{}/artistLetters?{after}\
+                    fieldSet=artistLettersV1&\
+                    limit=20&\
+                    pagingType=CURSOR&\
+                    appId={}&\
+                    language=en&\
+                    platform=WEB&\
+                    wpf=pc",
+                    self.community_id.id(),
+                    APP_ID
+                ),
+                &self.auth,
+            )
+            .await?
+            .error_for_status()?
+            .json::<LettersResponse>()
+            .await?;
+
+        // Update page state
+        self.page_state = match letter_page.paging.next_params {
+            Some(next_params) => PageState::Next(next_params.after),
+            None => PageState::Done,
+        };
+        let web = &self.signer.domains().web;
+        self.letters
+            .extend(letter_page.data.into_iter().map(|letter| Letter {
+                web: web.clone(),
+                ..letter
+            }));
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct LettersResponse {
+    paging: Paging,
+    data: Vec<Letter>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Paging {
+    next_params: Option<NextParams>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NextParams {
+    after: String,
+}
+
+/// Letter an artist wrote to their fans
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Letter {
+    #[serde(rename = "letterId")]
+    id: String,
+    #[serde(default)]
+    title: String,
+    /// HTML body
+    body: String,
+    #[serde(rename = "publishedAt")]
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    #[serde(serialize_with = "rfc3339::serialize")]
+    time: OffsetDateTime,
+    author: Member,
+    /// Website the letter was fetched from, to resolve relative image URLs against
+    #[serde(skip)]
+    web: String,
+}
+
+impl Letter {
+    /// Letter ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[async_trait]
+impl SavablePost for Letter {
+    async fn download(
+        &self,
+        client: &ClientWithMiddleware,
+        directory: impl AsRef<Path> + Send,
+    ) -> Result<()> {
+        let directory = directory.as_ref();
+        let slug = self.slug()?;
+
+        // Write all data as a json file
+        let info = serde_json::to_vec_pretty(self)?;
+        let mut file = fs::File::create(directory.join(format!("{}.json", slug))).await?;
+        file.write_all(info.as_slice()).await?;
+
+        let base = Url::parse(&self.web)?;
+        download_html(client, &base, directory, &slug, &self.body).await?;
+
+        // Set mtime on directory and all files in it
+        set_mtime(directory, &self.time)?;
+        let mut read_dir = fs::read_dir(directory).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            set_mtime(entry.path(), &self.time)?;
+        }
+        Ok(())
+    }
+
+    fn slug(&self) -> Result<String> {
+        let title = Some(self.title.as_str()).filter(|t| !t.is_empty());
+        slug(&self.time, &self.id, &self.author, title)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::mock::{MockServer, AUTH};
+
+    #[tokio::test]
+    async fn letters_paging() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let mut letters = Letters::init(CommunityId::new(14), AUTH.to_owned(), server.signer());
+        let letters_stream = letters.as_stream(&client).await;
+        futures::pin_mut!(letters_stream);
+        let mut ids = HashSet::new();
+        while let Some(letter) = letters_stream.next().await {
+            let letter = letter.unwrap();
+            assert_eq!(letter.author.profile_type, "ARTIST");
+            assert!(!ids.contains(&letter.id));
+            ids.insert(letter.id);
+        }
+        assert_eq!(ids.len(), 2);
+    }
+}
@@ -1,9 +1,9 @@
 use anyhow::Result;
-use reqwest::{header, Client};
+use reqwest_middleware::ClientWithMiddleware;
 use serde::Deserialize;
 
-use super::{APP_ID, REFERER};
-use crate::auth::{compute_url, get_secret};
+use super::APP_ID;
+use crate::auth::Signer;
 
 #[allow(dead_code)]
 #[derive(Deserialize, Clone, Debug)]
@@ -18,22 +18,16 @@ pub struct Me {
     status: String,
 }
 
-pub(crate) async fn me(client: &Client, auth: &str) -> Result<Me> {
-    let secret = get_secret(client).await?;
-    let url = compute_url(
-        &format!(
-            "/users/v1.0/users/me?appId={}&language=en&os=WEB&platform=WEB&wpf=pc",
-            APP_ID
-        ),
-        &secret,
-    )
-    .await?;
-
-    let me = client
-        .get(url.as_str())
-        .header(header::REFERER, REFERER)
-        .header(header::AUTHORIZATION, auth)
-        .send()
+pub(crate) async fn me(client: &ClientWithMiddleware, signer: &Signer, auth: &str) -> Result<Me> {
+    let me = signer
+        .get(
+            client,
+            &format!(
+                "/users/v1.0/users/me?appId={}&language=en&os=WEB&platform=WEB&wpf=pc",
+                APP_ID
+            ),
+            auth,
+        )
         .await?
         .error_for_status()?
         .json::<Me>()
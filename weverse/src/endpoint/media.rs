@@ -0,0 +1,415 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use sns_archive_common::{set_mtime, streamed_download, SavablePost};
+use time::serde::rfc3339;
+use time::OffsetDateTime;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use super::community_id::CommunityId;
+use super::member::Member;
+use super::vod::{download_vod, vod_videos, LiveVideo, MediaInfo, VideoPolicy, VideoType};
+use super::APP_ID;
+use crate::auth::Signer;
+use crate::error::WeverseError;
+use crate::utils::{deserialize_timestamp, slug};
+
+#[derive(Debug)]
+enum PageState {
+    Inital,
+    Next(String),
+    Done,
+}
+
+/// Category of the media tab, e.g. MVs or photo sets
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct MediaCategory {
+    pub id: u64,
+    pub title: String,
+}
+
+/// Which media posts to list
+#[derive(Debug)]
+pub enum MediaSource {
+    /// Every media post of a community
+    All(CommunityId),
+    Category(u64),
+}
+
+impl MediaSource {
+    fn url(&self, after: &str) -> String {
+        let path = match self {
+            Self::All(community_id) => format!("community-{}/more", community_id.id()),
+            Self::Category(category_id) => format!("category-{}/mediaPosts", category_id),
+        };
+        let params = format!(
+            "fieldSet=postsV1&\
+                limit=20&\
+                pagingType=CURSOR&\
+                appId={}&\
+                language=en&\
+                platform=WEB&\
+                wpf=pc",
+            APP_ID
+        );
+        format!("/media/v1.0/{path}?{after}{params}")
+    }
+}
+
+/// Official media posts of a community, newest first
+#[derive(Debug)]
+pub struct MediaPosts {
+    posts: VecDeque<MediaPost>,
+    source: MediaSource,
+    auth: String,
+    signer: Signer,
+    // For pagination
+    page_state: PageState,
+}
+
+impl MediaPosts {
+    pub(crate) fn init(source: MediaSource, auth: String, signer: Signer) -> Self {
+        Self {
+            posts: VecDeque::new(),
+            source,
+            auth,
+            signer,
+            page_state: PageState::Inital,
+        }
+    }
+
+    pub async fn as_stream<'a>(
+        &'a mut self,
+        client: &'a ClientWithMiddleware,
+    ) -> impl Stream<Item = Result<MediaPost>> + 'a {
+        futures::stream::unfold(self, |state| async {
+            // Pop off and return the next post if it exists
+            if let Some(post) = state.posts.pop_front() {
+                return Some((Ok(post), state));
+            }
+
+            match &state.page_state {
+                // Reached last page
+                PageState::Done => None,
+
+                // Fetch next page
+                _ => {
+                    if let Err(e) = state.fetch_next_page(client).await {
+                        state.page_state = PageState::Done;
+                        return Some((Err(e), state));
+                    }
+                    state.posts.pop_front().map(|post| (Ok(post), state))
+                }
+            }
+        })
+    }
+
+    async fn fetch_next_page(&mut self, client: &ClientWithMiddleware) -> Result<()> {
+        let after = match &self.page_state {
+            PageState::Inital => "".to_string(),
+            PageState::Next(a) => format!("after={}&", a),
+            _ => unreachable!(),
+        };
+
+        let post_page = self
+            .signer
+            .get(client, &self.source.url(&after), &self.auth)
+            .await?
+            .error_for_status()?
+            .json::<MediaPostsResponse>()
+            .await?;
+
+        // Update page state
+        self.page_state = match post_page.paging.next_params {
+            Some(next_params) => PageState::Next(next_params.after),
+            None => PageState::Done,
+        };
+        self.posts.extend(post_page.data.into_iter().map(|mut p| {
+            p.auth = self.auth.clone();
+            p.signer = self.signer.clone();
+            p
+        }));
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct MediaPostsResponse {
+    paging: Paging,
+    data: Vec<MediaPost>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Paging {
+    next_params: Option<NextParams>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NextParams {
+    after: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaPost {
+    #[serde(rename = "postId")]
+    id: String,
+    #[serde(rename = "publishedAt")]
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    #[serde(serialize_with = "rfc3339::serialize")]
+    time: OffsetDateTime,
+    author: Member,
+    #[serde(flatten)]
+    extension: MediaExtension,
+    #[serde(skip)]
+    auth: String,
+    #[serde(skip)]
+    signer: Signer,
+    #[serde(skip)]
+    video_policy: VideoPolicy,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(tag = "postType", content = "extension")]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum MediaExtension {
+    Image(ExtensionImage),
+    Video(ExtensionVideo),
+    Youtube(ExtensionYoutube),
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ExtensionImage {
+    image: Images,
+    media_info: MediaInfo,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Images {
+    photos: Vec<Photo>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+struct Photo {
+    url: String,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ExtensionVideo {
+    video: LiveVideo,
+    media_info: MediaInfo,
+}
+
+/// Videos hosted on YouTube, only their link is saved
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ExtensionYoutube {
+    youtube: Youtube,
+    media_info: MediaInfo,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Youtube {
+    video_path: String,
+}
+
+impl MediaExtension {
+    fn media_info(&self) -> &MediaInfo {
+        match self {
+            Self::Image(e) => &e.media_info,
+            Self::Video(e) => &e.media_info,
+            Self::Youtube(e) => &e.media_info,
+        }
+    }
+}
+
+/// List the media categories of a community
+pub(crate) async fn media_categories(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    auth: &str,
+    community_id: CommunityId,
+) -> Result<Vec<MediaCategory>> {
+    let categories = signer
+        .get(
+            client,
+            &format!(
+                "/media/v1.0/community-{}/categories?appId={}&language=en&platform=WEB&wpf=pc",
+                community_id.id(),
+                APP_ID
+            ),
+            auth,
+        )
+        .await?
+        .error_for_status()?
+        .json::<Vec<MediaCategory>>()
+        .await?;
+    Ok(categories)
+}
+
+#[async_trait]
+impl SavablePost for MediaPost {
+    async fn download(
+        &self,
+        client: &ClientWithMiddleware,
+        directory: impl AsRef<Path> + Send,
+    ) -> Result<()> {
+        let directory = directory.as_ref();
+        self.write_info(directory).await?;
+
+        let result = match &self.extension {
+            MediaExtension::Image(image) => self
+                .download_all_photos(client, &image.image.photos, directory)
+                .await
+                .into_iter()
+                .collect::<Result<()>>(),
+            MediaExtension::Video(video) => {
+                self.download_video(client, &video.video, directory).await
+            }
+            // Nothing to download besides the info file
+            MediaExtension::Youtube(_) => Ok(()),
+        };
+        if result.is_err() {
+            return Err(WeverseError::Download(self.id.clone()).into());
+        }
+
+        // Set mtime on directory and all files in it
+        set_mtime(directory, &self.time)?;
+        let mut read_dir = fs::read_dir(directory).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            set_mtime(entry.path(), &self.time)?;
+        }
+        Ok(())
+    }
+
+    fn slug(&self) -> Result<String> {
+        let title = &self.extension.media_info().title;
+        slug(&self.time, &self.id, &self.author, Some(title))
+    }
+}
+
+impl MediaPost {
+    /// Post ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Choose which streams of videos to download
+    pub fn set_video_policy(&mut self, video_policy: VideoPolicy) {
+        self.video_policy = video_policy;
+    }
+
+    /// Write all data as a json file
+    async fn write_info(&self, directory: impl AsRef<Path>) -> Result<()> {
+        let info = serde_json::to_vec_pretty(self)?;
+        let filename = directory.as_ref().join(format!("{}.json", self.slug()?));
+        let mut file = fs::File::create(filename).await?;
+        file.write_all(info.as_slice()).await?;
+        Ok(())
+    }
+
+    async fn download_all_photos(
+        &self,
+        client: &ClientWithMiddleware,
+        photos: &[Photo],
+        directory: impl AsRef<Path>,
+    ) -> Vec<Result<()>> {
+        let urls: Vec<_> = photos.iter().map(|p| p.url.clone()).collect();
+        futures::stream::iter(urls)
+            .enumerate()
+            .map(|(i, url)| self.download_photo(client, url, i, directory.as_ref()))
+            .buffered(usize::MAX)
+            .collect()
+            .await
+    }
+
+    async fn download_photo(
+        &self,
+        client: &ClientWithMiddleware,
+        photo_url: String,
+        idx: usize,
+        directory: impl AsRef<Path>,
+    ) -> Result<()> {
+        let url = Url::parse(&photo_url)?;
+        let ext = url
+            .path()
+            .rsplit_once('.')
+            .map(|(_, ext)| ext)
+            .unwrap_or("jpg");
+        let filename = format!("{}-img{:02}.{}", self.slug()?, idx + 1, ext);
+        let path = directory.as_ref().join(filename);
+        streamed_download(client, photo_url, path).await
+    }
+
+    async fn download_video(
+        &self,
+        client: &ClientWithMiddleware,
+        video: &LiveVideo,
+        directory: impl AsRef<Path>,
+    ) -> Result<()> {
+        let video_type = VideoType::Extension(video.clone());
+        let videos = vod_videos(client, &self.signer, &self.auth, &video_type).await?;
+        download_vod(
+            client,
+            &videos,
+            &self.video_policy,
+            directory.as_ref(),
+            &self.slug()?,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::mock::{MockServer, AUTH};
+
+    #[tokio::test]
+    async fn categories() {
+        let server = MockServer::start().await;
+        let categories = media_categories(
+            &server.client(),
+            &server.signer(),
+            AUTH,
+            CommunityId::new(14),
+        )
+        .await
+        .unwrap();
+        assert!(!categories.is_empty());
+    }
+
+    #[tokio::test]
+    async fn media_paging() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let mut media_posts = MediaPosts::init(
+            MediaSource::All(CommunityId::new(14)),
+            AUTH.to_owned(),
+            server.signer(),
+        );
+        let posts_stream = media_posts.as_stream(&client).await;
+        futures::pin_mut!(posts_stream);
+        let mut ids = HashSet::new();
+        while let Some(post) = posts_stream.next().await {
+            let post = post.unwrap();
+            assert!(!ids.contains(&post.id));
+            ids.insert(post.id);
+        }
+        assert_eq!(ids.len(), 3);
+    }
+}
@@ -19,3 +19,64 @@ pub struct Member {
 pub struct OfficialProfile {
     pub official_name: String,
 }
+
+/// Members to download, by member ID or official name
+#[derive(Clone, Default, Debug)]
+pub struct MemberFilter {
+    /// Only download these members, all members if empty
+    pub include: Vec<String>,
+    /// Never download these members
+    pub exclude: Vec<String>,
+}
+
+impl MemberFilter {
+    pub fn matches(&self, member: &Member) -> bool {
+        let is = |m: &String| {
+            *m == member.id
+                || member
+                    .official_profile
+                    .as_ref()
+                    .is_some_and(|p| p.official_name.eq_ignore_ascii_case(m))
+        };
+        (self.include.is_empty() || self.include.iter().any(is)) && !self.exclude.iter().any(is)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn member(id: &str, official_name: &str) -> Member {
+        Member {
+            id: id.to_owned(),
+            community_id: CommunityId::new(14),
+            profile_name: official_name.to_lowercase(),
+            profile_type: "ARTIST".to_owned(),
+            official_profile: Some(OfficialProfile {
+                official_name: official_name.to_owned(),
+            }),
+        }
+    }
+
+    #[test]
+    fn member_filter() {
+        let siyeon = member("a1", "SIYEON");
+        let dami = member("b2", "DAMI");
+
+        assert!(MemberFilter::default().matches(&siyeon));
+
+        let include = MemberFilter {
+            include: vec!["siyeon".to_owned()],
+            exclude: vec![],
+        };
+        assert!(include.matches(&siyeon));
+        assert!(!include.matches(&dami));
+
+        let exclude = MemberFilter {
+            include: vec![],
+            exclude: vec!["b2".to_owned()],
+        };
+        assert!(exclude.matches(&siyeon));
+        assert!(!exclude.matches(&dami));
+    }
+}
@@ -1,10 +1,15 @@
 pub mod artist_tab_posts;
+pub mod chat;
+pub mod comments;
 pub mod community_id;
+pub mod letter;
 pub mod me;
+pub mod media;
 pub mod member;
 pub mod moments;
+pub mod notice;
 pub mod post;
 pub mod vod;
 
- static APP_ID: &str = "be4d79eb8fc7bd008ee82c8ec4ff6fd4";
- static REFERER: &str = "https://weverse.io/";
+static APP_ID: &str = "be4d79eb8fc7bd008ee82c8ec4ff6fd4";
+pub(crate) static REFERER: &str = "https://weverse.io/";
@@ -1,45 +1,43 @@
 use anyhow::Result;
 use futures::stream::{self, StreamExt};
-use reqwest::{header, Client};
+use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Serialize};
 
 use super::community_id::CommunityId;
+use super::member::{Member, MemberFilter};
 use super::post::{post, ArtistPost};
-use super::{APP_ID, REFERER};
-use crate::auth::{compute_url, get_secret};
+use super::APP_ID;
+use crate::auth::Signer;
 
 pub struct Moments {}
 
 impl Moments {
-    pub async fn get_latest_moments(
-        client: &Client,
+    pub(crate) async fn get_latest_moments(
+        client: &ClientWithMiddleware,
+        signer: &Signer,
         auth: &str,
         community_id: CommunityId,
+        members: &MemberFilter,
     ) -> Result<Vec<ArtistPost>> {
-        let secret = get_secret(client).await?;
-        let url = compute_url(
-            &format!(
-                "/member/v1.1/community-{}/artistMembers?appId={}&fieldSet=artistMembersV1&language=en&platform=WEB&wpf=pc",
-                community_id.id(), APP_ID
+        let moment_ids: Vec<_> = signer
+            .get(
+                client,
+                &format!(
+                    "/member/v1.1/community-{}/artistMembers?appId={}&fieldSet=artistMembersV1&language=en&platform=WEB&wpf=pc",
+                    community_id.id(), APP_ID
                 ),
-                &secret,
-                )
-            .await?;
-
-        let moment_ids: Vec<_> = client
-            .get(url.as_str())
-            .header(header::REFERER, REFERER)
-            .header(header::AUTHORIZATION, auth)
-            .send()
+                auth,
+            )
             .await?
             .error_for_status()?
             .json::<Vec<LatestMomentWrapper>>()
             .await?
             .into_iter()
+            .filter(|m| members.matches(&m.member))
             .filter_map(|m| m.artist_latest_moment.map(|m| m.id))
             .collect();
         let posts: Vec<_> = stream::iter(moment_ids.iter())
-            .map(|id| post(client, auth, id))
+            .map(|id| post(client, signer, auth, id))
             .buffered(20)
             .collect::<Vec<_>>()
             .await
@@ -52,6 +50,8 @@ impl Moments {
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 struct LatestMomentWrapper {
+    #[serde(flatten)]
+    member: Member,
     artist_latest_moment: Option<ArtistLatestMoment>,
 }
 
@@ -64,16 +64,37 @@ struct ArtistLatestMoment {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::utils::{setup, LOGIN_INFO};
+    use crate::mock::{MockServer, AUTH};
 
     #[tokio::test]
     async fn latest_moments() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
+        let server = MockServer::start().await;
         let community_id = CommunityId::new(14);
-        let latest_moments = Moments::get_latest_moments(&client, auth, community_id)
-            .await
-            .unwrap();
-        assert_eq!(7, latest_moments.len());
+        let latest_moments = Moments::get_latest_moments(
+            &server.client(),
+            &server.signer(),
+            AUTH,
+            community_id,
+            &MemberFilter::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(4, latest_moments.len());
+
+        let members = MemberFilter {
+            include: vec!["SIYEON".to_owned(), "JI U".to_owned()],
+            exclude: Vec::new(),
+        };
+        let latest_moments = Moments::get_latest_moments(
+            &server.client(),
+            &server.signer(),
+            AUTH,
+            community_id,
+            &members,
+        )
+        .await
+        .unwrap();
+        let ids: Vec<_> = latest_moments.iter().map(|m| m.id()).collect();
+        assert_eq!(ids, ["1-14373893", "2-103571496"]);
     }
 }
@@ -0,0 +1,265 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::Stream;
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
+use sns_archive_common::{set_mtime, SavablePost};
+use time::serde::rfc3339;
+use time::OffsetDateTime;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+use super::community_id::CommunityId;
+use super::APP_ID;
+use crate::auth::Signer;
+use crate::utils::{deserialize_timestamp, download_html, named_slug};
+
+#[derive(Debug)]
+enum PageState {
+    Inital,
+    Next(String),
+    Done,
+}
+
+/// Notices of a community, newest first
+#[derive(Debug)]
+pub struct Notices {
+    notices: VecDeque<NoticeShort>,
+    community_id: CommunityId,
+    auth: String,
+    signer: Signer,
+    // For pagination
+    page_state: PageState,
+}
+
+impl Notices {
+    pub(crate) fn init(community_id: CommunityId, auth: String, signer: Signer) -> Self {
+        Self {
+            notices: VecDeque::new(),
+            community_id,
+            auth,
+            signer,
+            page_state: PageState::Inital,
+        }
+    }
+
+    pub async fn as_stream<'a>(
+        &'a mut self,
+        client: &'a ClientWithMiddleware,
+    ) -> impl Stream<Item = Result<NoticeShort>> + 'a {
+        futures::stream::unfold(self, |state| async {
+            // Pop off and return the next notice if it exists
+            if let Some(notice) = state.notices.pop_front() {
+                return Some((Ok(notice), state));
+            }
+
+            match &state.page_state {
+                // Reached last page
+                PageState::Done => None,
+
+                // Fetch next page
+                _ => {
+                    if let Err(e) = state.fetch_next_page(client).await {
+                        state.page_state = PageState::Done;
+                        return Some((Err(e), state));
+                    }
+                    state.notices.pop_front().map(|notice| (Ok(notice), state))
+                }
+            }
+        })
+    }
+
+    async fn fetch_next_page(&mut self, client: &ClientWithMiddleware) -> Result<()> {
+        let after = match &self.page_state {
+            PageState::Inital => "".to_string(),
+            PageState::Next(a) => format!("after={}&", a),
+            _ => unreachable!(),
+        };
+
+        let notice_page = self
+            .signer
+            .get(
+                client,
+                &format!(
+                    "/notice/v1.0/community-{}/notices?{after}\
+                    fieldSet=noticesV1&\
+                    limit=20&\
+                    pagingType=CURSOR&\
+                    appId={}&\
+                    language=en&\
+                    platform=WEB&\
+                    wpf=pc",
+                    self.community_id.id(),
+                    APP_ID
+                ),
+                &self.auth,
+            )
+            .await?
+            .error_for_status()?
+            .json::<NoticesResponse>()
+            .await?;
+
+        // Update page state
+        self.page_state = match notice_page.paging.next_params {
+            Some(next_params) => PageState::Next(next_params.after),
+            None => PageState::Done,
+        };
+        self.notices.extend(notice_page.data);
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct NoticesResponse {
+    paging: Paging,
+    data: Vec<NoticeShort>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Paging {
+    next_params: Option<NextParams>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NextParams {
+    after: String,
+}
+
+/// Notice as listed, without its body
+#[derive(Deserialize, Debug)]
+pub struct NoticeShort {
+    #[serde(rename = "noticeId")]
+    pub id: u64,
+    pub title: String,
+    #[serde(rename = "publishAt")]
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    pub time: OffsetDateTime,
+}
+
+impl NoticeShort {
+    pub fn slug(&self) -> Result<String> {
+        notice_slug(&self.time, self.id, &self.title)
+    }
+}
+
+/// Community notice written by the agency or Weverse
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Notice {
+    #[serde(rename = "noticeId")]
+    id: u64,
+    title: String,
+    /// HTML body
+    body: String,
+    #[serde(rename = "publishAt")]
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    #[serde(serialize_with = "rfc3339::serialize")]
+    time: OffsetDateTime,
+    /// Website the notice was fetched from, to resolve relative image URLs against
+    #[serde(skip)]
+    web: String,
+}
+
+impl Notice {
+    /// Notice ID
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+fn notice_slug(time: &OffsetDateTime, id: u64, title: &str) -> Result<String> {
+    named_slug(time, &id.to_string(), "notice", Some(title))
+}
+
+/// Fetch a notice given with a notice ID
+pub(crate) async fn notice(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    auth: &str,
+    notice_id: u64,
+) -> Result<Notice> {
+    let notice = signer
+        .get(
+            client,
+            &format!(
+                "/notice/v1.0/notice-{}?fieldSet=noticeV1&appId={}&language=en&platform=WEB&wpf=pc",
+                notice_id, APP_ID
+            ),
+            auth,
+        )
+        .await?
+        .error_for_status()?
+        .json::<Notice>()
+        .await?;
+
+    Ok(Notice {
+        web: signer.domains().web.clone(),
+        ..notice
+    })
+}
+
+#[async_trait]
+impl SavablePost for Notice {
+    async fn download(
+        &self,
+        client: &ClientWithMiddleware,
+        directory: impl AsRef<Path> + Send,
+    ) -> Result<()> {
+        let directory = directory.as_ref();
+        let slug = self.slug()?;
+
+        // Write all data as a json file
+        let info = serde_json::to_vec_pretty(self)?;
+        let mut file = fs::File::create(directory.join(format!("{}.json", slug))).await?;
+        file.write_all(info.as_slice()).await?;
+
+        let base = Url::parse(&self.web)?;
+        download_html(client, &base, directory, &slug, &self.body).await?;
+
+        // Set mtime on directory and all files in it
+        set_mtime(directory, &self.time)?;
+        let mut read_dir = fs::read_dir(directory).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            set_mtime(entry.path(), &self.time)?;
+        }
+        Ok(())
+    }
+
+    fn slug(&self) -> Result<String> {
+        notice_slug(&self.time, self.id, &self.title)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::mock::{MockServer, AUTH};
+
+    #[tokio::test]
+    async fn notices_paging() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let signer = server.signer();
+        let mut notices = Notices::init(CommunityId::new(14), AUTH.to_owned(), signer.clone());
+        let notices_stream = notices.as_stream(&client).await;
+        futures::pin_mut!(notices_stream);
+        let short = notices_stream.next().await.unwrap().unwrap();
+        let full = notice(&client, &signer, AUTH, short.id).await.unwrap();
+        assert_eq!(short.slug().unwrap(), full.slug().unwrap());
+
+        let dir = tempfile::tempdir().unwrap();
+        full.download(&client, dir.path()).await.unwrap();
+        let slug = full.slug().unwrap();
+        assert!(dir.path().join(format!("{}.html", slug)).exists());
+        assert!(dir.path().join(format!("{}-img01.jpg", slug)).exists());
+        // Relative to the website
+        assert!(dir.path().join(format!("{}-img02.jpg", slug)).exists());
+    }
+}
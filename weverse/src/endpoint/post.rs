@@ -6,7 +6,8 @@ use async_trait::async_trait;
 use futures::StreamExt;
 use lazy_static::lazy_static;
 use regex::Regex;
-use reqwest::{header, Client, Url};
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Serialize};
 use sns_archive_common::{set_mtime, streamed_download, SavablePost};
 use time::serde::rfc3339;
@@ -14,12 +15,16 @@ use time::OffsetDateTime;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 
+use super::chat::artist_chat_messages;
 use super::community_id::CommunityId;
 use super::member::Member;
-use super::vod::{vod_videos, CVideo, LiveVideo, MediaInfo, VideoType};
-use super::{APP_ID, REFERER};
-use crate::auth::{compute_url, get_secret};
+use super::vod::{
+    download_vod, vod_info, vod_videos, CVideo, LiveVideo, MediaInfo, VideoPolicy, VideoType,
+};
+use super::APP_ID;
+use crate::auth::Signer;
 use crate::error::WeverseError;
+use crate::subtitles::{srt_to_vtt, vtt_to_srt};
 use crate::utils::{deserialize_timestamp, slug};
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -42,6 +47,10 @@ pub struct ArtistPost {
     author_moment_posts: Option<AuthorMomentPosts>,
     #[serde(skip)]
     auth: String,
+    #[serde(skip)]
+    signer: Signer,
+    #[serde(skip)]
+    video_policy: VideoPolicy,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -196,28 +205,27 @@ struct MomentData {
 }
 
 /// Fetch a post given with a post ID
-pub(crate) async fn post(client: &Client, auth: &str, post_id: &str) -> Result<ArtistPost> {
-    let secret = get_secret(client).await?;
-
-    let url = compute_url(
-        &format!(
-            "/post/v1.0/post-{}?fieldSet=postV1&appId={}&language=en&platform=WEB&wpf=pc",
-            post_id, APP_ID
-        ),
-        &secret,
-    )
-    .await?;
-
-    let mut post = client
-        .get(url.as_str())
-        .header(header::REFERER, REFERER)
-        .header(header::AUTHORIZATION, auth)
-        .send()
+pub(crate) async fn post(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    auth: &str,
+    post_id: &str,
+) -> Result<ArtistPost> {
+    let mut post = signer
+        .get(
+            client,
+            &format!(
+                "/post/v1.0/post-{}?fieldSet=postV1&appId={}&language=en&platform=WEB&wpf=pc",
+                post_id, APP_ID
+            ),
+            auth,
+        )
         .await?
         .error_for_status()?
         .json::<ArtistPost>()
         .await?;
     post.auth = auth.to_owned();
+    post.signer = signer.clone();
 
     Ok(post)
 }
@@ -232,7 +240,11 @@ lazy_static! {
 
 #[async_trait]
 impl SavablePost for ArtistPost {
-    async fn download(&self, client: &Client, directory: impl AsRef<Path> + Send) -> Result<()> {
+    async fn download(
+        &self,
+        client: &ClientWithMiddleware,
+        directory: impl AsRef<Path> + Send,
+    ) -> Result<()> {
         match self.section_type {
             SectionType::Live => {
                 let dir = directory.as_ref();
@@ -274,6 +286,16 @@ impl SavablePost for ArtistPost {
 }
 
 impl ArtistPost {
+    /// Post ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Choose which streams of videos and lives to download
+    pub fn set_video_policy(&mut self, video_policy: VideoPolicy) {
+        self.video_policy = video_policy;
+    }
+
     /// Returns the next newest moment after the current one
     pub fn next_moment_id(&self) -> Option<String> {
         self.author_moment_posts.as_ref().and_then(|mps| {
@@ -285,6 +307,14 @@ impl ArtistPost {
         })
     }
 
+    /// Returns the moment right before the current one, the opposite of [`Self::next_moment_id`]
+    pub fn newer_moment_id(&self) -> Option<String> {
+        self.author_moment_posts.as_ref().and_then(|mps| {
+            let idx = mps.data.iter().position(|m| m.id == self.id)?;
+            mps.data.get(idx.checked_sub(1)?).map(|m| m.id.clone())
+        })
+    }
+
     /// Write all data as a json file
     async fn write_info(&self, directory: impl AsRef<Path>) -> Result<()> {
         let info = serde_json::to_vec_pretty(self)?;
@@ -296,41 +326,80 @@ impl ArtistPost {
 
     async fn download_live(
         &self,
-        client: &Client,
+        client: &ClientWithMiddleware,
         auth: &str,
         directory: impl AsRef<Path>,
     ) -> Result<()> {
-        let video_type = match self.extension {
-            Extension::Video(ref video) => VideoType::Extension(video.video.clone()),
+        let (live_video, media_info) = match self.extension {
+            Extension::Video(ref video) => (&video.video, &video.media_info),
             _ => unreachable!(),
         };
-        let secret = get_secret(client).await.unwrap();
-        let vod_info = vod_videos(client, auth, &video_type, &secret)
-            .await
-            .unwrap();
-        let video_url = &vod_info.iter().max().unwrap().source;
-        let url = Url::parse(video_url)?;
-        let ext = url
-            .path()
-            .rsplit_once('.')
-            .map(|(_, ext)| ext)
-            .unwrap_or("mp4");
-        let filename = format!("{}.{}", self.slug()?, ext);
-        let path = directory.as_ref().join(filename);
-        streamed_download(client, video_url, path).await
+        let video_type = VideoType::Extension(live_video.clone());
+        let vod_info = vod_info(client, &self.signer, auth, &video_type).await?;
+        let slug = self.slug()?;
+        let directory = directory.as_ref();
+
+        download_vod(
+            client,
+            &vod_info.videos,
+            &self.video_policy,
+            directory,
+            &slug,
+        )
+        .await?;
+
+        // Thumbnail
+        if let Some(thumbnail) = &media_info.thumbnail {
+            let url = Url::parse(&thumbnail.url)?;
+            let ext = url
+                .path()
+                .rsplit_once('.')
+                .map(|(_, ext)| ext)
+                .unwrap_or("jpg");
+            let filename = format!("{}-thumb.{}", slug, ext);
+            streamed_download(client, url, directory.join(filename)).await?;
+        }
+
+        // Captions in both WebVTT and SubRip
+        for caption in &vod_info.captions {
+            let text = client
+                .get(&caption.source)
+                .send()
+                .await?
+                .error_for_status()?
+                .text()
+                .await?;
+            let (vtt, srt) = if text.trim_start_matches('\u{feff}').starts_with("WEBVTT") {
+                let srt = vtt_to_srt(&text)?;
+                (text, srt)
+            } else {
+                (srt_to_vtt(&text)?, text)
+            };
+            let locale = caption.locale.replace('_', "-");
+            fs::write(directory.join(format!("{}.{}.vtt", slug, locale)), vtt).await?;
+            fs::write(directory.join(format!("{}.{}.srt", slug, locale)), srt).await?;
+        }
+
+        // Chat replay
+        if let Some(chat_id) = &live_video.chat_id {
+            let messages = artist_chat_messages(client, &self.signer, auth, chat_id).await?;
+            let chat = serde_json::to_vec_pretty(&messages)?;
+            fs::write(directory.join(format!("{}-chat.json", slug)), chat).await?;
+        }
+
+        Ok(())
     }
 
     async fn download_all_photos(
         &self,
-        client: &Client,
+        client: &ClientWithMiddleware,
         directory: impl AsRef<Path>,
     ) -> Vec<Result<()>> {
         // Download both regular and moments photos
         let photos = self.photos().map(|p| p.url).chain(
             self.extension
                 .moment()
-                .and_then(|m| m.photo_url().map(|u| u.to_owned()))
-                .into_iter(),
+                .and_then(|m| m.photo_url().map(|u| u.to_owned())),
         );
         futures::stream::iter(photos)
             .enumerate()
@@ -342,7 +411,7 @@ impl ArtistPost {
 
     async fn download_all_videos(
         &self,
-        client: &Client,
+        client: &ClientWithMiddleware,
         auth: &str,
         directory: impl AsRef<Path>,
     ) -> Vec<Result<()>> {
@@ -350,8 +419,7 @@ impl ArtistPost {
         let videos = self.videos().chain(
             self.extension
                 .moment()
-                .and_then(|m| m.video().map(|v| v.to_owned()))
-                .into_iter(),
+                .and_then(|m| m.video().map(|v| v.to_owned())),
         );
         futures::stream::iter(videos)
             .enumerate()
@@ -363,7 +431,7 @@ impl ArtistPost {
 
     async fn download_photo(
         &self,
-        client: &Client,
+        client: &ClientWithMiddleware,
         photo_url: impl AsRef<str>,
         idx: usize,
         directory: impl AsRef<Path>,
@@ -381,7 +449,7 @@ impl ArtistPost {
 
     async fn download_video(
         &self,
-        client: &Client,
+        client: &ClientWithMiddleware,
         auth: &str,
         video: Video,
         idx: usize,
@@ -391,20 +459,16 @@ impl ArtistPost {
             post_id: video.id,
             infra_id: video.upload_info.id,
         });
-        let secret = get_secret(client).await.unwrap();
-        let vod_info = vod_videos(client, auth, &video_type, &secret)
-            .await
-            .unwrap();
-        let video_url = &vod_info.iter().max().unwrap().source;
-        let url = Url::parse(video_url)?;
-        let ext = url
-            .path()
-            .rsplit_once('.')
-            .map(|(_, ext)| ext)
-            .unwrap_or("mp4");
-        let filename = format!("{}-vid{:02}.{}", self.slug()?, idx + 1, ext);
-        let path = directory.as_ref().join(filename);
-        streamed_download(client, video_url, path).await
+        let videos = vod_videos(client, &self.signer, auth, &video_type).await?;
+        let stem = format!("{}-vid{:02}", self.slug()?, idx + 1);
+        download_vod(
+            client,
+            &videos,
+            &self.video_policy,
+            directory.as_ref(),
+            &stem,
+        )
+        .await
     }
 
     fn photos(&self) -> impl Iterator<Item = Photo> + '_ {
@@ -431,13 +495,16 @@ impl ArtistPost {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::utils::{setup, LOGIN_INFO};
+    use crate::mock::{MockServer, AUTH};
+
+    async fn mock_post(post_id: &str) -> Result<ArtistPost> {
+        let server = MockServer::start().await;
+        post(&server.client(), &server.signer(), AUTH, post_id).await
+    }
 
     #[tokio::test]
     async fn post_with_video() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let post = post(&client, auth, "1-106028137").await.unwrap();
+        let post = mock_post("1-106028137").await.unwrap();
         assert_eq!(
             post.slug().unwrap(),
             "20220927-1-106028137-DAMI-이런걸 왜 찍었던 거지🤔"
@@ -447,9 +514,7 @@ mod test {
 
     #[tokio::test]
     async fn post_with_image() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let post = post(&client, auth, "1-106622307").await.unwrap();
+        let post = mock_post("1-106622307").await.unwrap();
         assert_eq!(
             post.slug().unwrap(),
             "20221010-1-106622307-HANDONG-내일이다!!오또캐~~~🫶🫶🫶🫶"
@@ -459,17 +524,13 @@ mod test {
 
     #[tokio::test]
     async fn invalid_post() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let post = post(&client, auth, "5-2849541rq3").await;
+        let post = mock_post("5-2849541rq3").await;
         assert!(post.is_err());
     }
 
     #[tokio::test]
     async fn moment_post_video() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let post = post(&client, auth, "4-111010672").await.unwrap();
+        let post = mock_post("4-111010672").await.unwrap();
         assert_eq!(
             post.slug().unwrap(),
             "20230111-4-111010672-GAHYEON-우리색 하늘"
@@ -481,9 +542,7 @@ mod test {
 
     #[tokio::test]
     async fn moment_post_photo() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let post = post(&client, auth, "2-247595").await.unwrap();
+        let post = mock_post("2-247595").await.unwrap();
         assert_eq!(post.slug().unwrap(), "20220606-2-247595-YOOHYEON-여러분 비편열었다근데 일반게시물 같은 이 기분은…뭐지..왜 다들 비밀로 안써!비밀");
         assert!(!post.author_moment_posts.unwrap().data.is_empty());
         assert!(matches!(post.extension, Extension::MomentW1(_)));
@@ -492,9 +551,7 @@ mod test {
 
     #[tokio::test]
     async fn moment_post_bgimage() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let post = post(&client, auth, "1-14373893").await.unwrap();
+        let post = mock_post("1-14373893").await.unwrap();
         assert_eq!(
             post.slug().unwrap(),
             "20220512-1-14373893-JI U-내 사랑❤️오늘도 수고해또❤️잘자❤️"
@@ -506,18 +563,61 @@ mod test {
 
     #[tokio::test]
     async fn next_moment() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let post = post(&client, auth, "2-103571496").await.unwrap();
+        let post = mock_post("2-103571496").await.unwrap();
         assert_eq!(post.next_moment_id(), Some(String::from("2-103510239")))
     }
 
+    #[tokio::test]
+    async fn newer_moment() {
+        let post = mock_post("2-103510239").await.unwrap();
+        assert_eq!(post.newer_moment_id(), Some(String::from("2-103571496")))
+    }
+
     #[tokio::test]
     async fn live() {
-        let client = Client::new();
-        let auth = LOGIN_INFO.get_or_init(setup()).await;
-        let post = post(&client, auth, "0-119057265").await.unwrap();
+        let post = mock_post("0-119057265").await.unwrap();
         assert_eq!(post.slug().unwrap(), "20230510-0-119057265-YOOHYEON-안농");
         assert!(matches!(post.extension, Extension::Video(_)));
     }
+
+    #[tokio::test]
+    async fn download_post_with_video() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let post = post(&client, &server.signer(), AUTH, "1-106028137")
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        post.download(&client, dir.path()).await.unwrap();
+
+        let slug = post.slug().unwrap();
+        assert!(dir.path().join(format!("{}.json", slug)).exists());
+        let video = std::fs::read_to_string(dir.path().join(format!("{}-vid01.mp4", slug)));
+        assert_eq!(video.unwrap(), "mock 1080p video\n");
+    }
+
+    #[tokio::test]
+    async fn download_live() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let mut live = post(&client, &server.signer(), AUTH, "0-119057265")
+            .await
+            .unwrap();
+        live.set_video_policy(VideoPolicy {
+            quality: "480".parse().unwrap(),
+            proxy: false,
+            codecs: Vec::new(),
+        });
+        let dir = tempfile::tempdir().unwrap();
+        live.download(&client, dir.path()).await.unwrap();
+
+        let slug = live.slug().unwrap();
+        let read =
+            |suffix: &str| std::fs::read_to_string(dir.path().join(format!("{}{}", slug, suffix)));
+        assert_eq!(read(".mp4").unwrap(), "mock 480p live\n");
+        assert!(read("-thumb.jpg").is_ok());
+        assert!(read(".en-US.vtt").unwrap().starts_with("WEBVTT"));
+        assert!(read(".en-US.srt").unwrap().starts_with("1\n00:00:01,000"));
+        assert!(read("-chat.json").unwrap().contains("잘자요"));
+    }
 }
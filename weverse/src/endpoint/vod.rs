@@ -1,10 +1,16 @@
-use anyhow::Result;
-use reqwest::{header, Client};
-use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::path::Path;
+use std::str::FromStr;
 
-use super::REFERER;
-use crate::auth::compute_url;
+use anyhow::{anyhow, Result};
+use reqwest::{Method, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Deserializer, Serialize};
+use sns_archive_common::streamed_download;
+
+use crate::auth::Signer;
 use crate::endpoint::APP_ID;
+use crate::manifest::{download_manifest, ManifestKind};
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -15,6 +21,22 @@ struct InKeyResponse {
 #[derive(Deserialize, Debug)]
 struct VodResponse {
     videos: Videos,
+    #[serde(default)]
+    captions: Captions,
+}
+
+#[derive(Deserialize, Default, Debug)]
+struct Captions {
+    list: Vec<Caption>,
+}
+
+/// Caption track of a VOD
+#[derive(Deserialize, Clone, Debug)]
+pub struct Caption {
+    /// Language and country, e.g. `en_US`
+    pub locale: String,
+    /// Source URL
+    pub source: String,
 }
 
 #[derive(Deserialize, Debug)]
@@ -29,26 +51,27 @@ pub struct PlayInfoResponse {
 }
 
 /// VOD video information
-#[derive(Deserialize, PartialEq, Eq, Clone, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 pub struct Video {
     /// Source URL
     pub source: String,
     /// File size in bytes
     pub size: u64,
+    /// Combined video and audio bitrate in kbps
+    #[serde(default, deserialize_with = "deserialize_bitrate")]
+    pub bitrate: Option<u64>,
     /// Video encoding
     #[serde(rename = "encodingOption")]
     pub encoding: Encoding,
+    /// Video codec, e.g. `h264` or `hevc`, if the stream reports it
+    #[serde(default)]
+    pub codec: Option<String>,
 }
 
-impl Ord for Video {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.encoding.cmp(&other.encoding)
-    }
-}
-
-impl PartialOrd for Video {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+impl Video {
+    /// Sort key ordering videos by resolution, then bitrate, then file size
+    pub fn quality_key(&self) -> (u64, Option<u64>, u64) {
+        (self.encoding.pixels(), self.bitrate, self.size)
     }
 }
 
@@ -61,17 +84,176 @@ pub struct Encoding {
     pub height: u64,
 }
 
-impl PartialOrd for Encoding {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+impl Encoding {
+    /// Shorter side in pixels, 1080 for both landscape and portrait 1080p videos
+    pub fn resolution(&self) -> u64 {
+        self.width.min(self.height)
+    }
+
+    /// Number of pixels of a frame
+    pub fn pixels(&self) -> u64 {
+        self.width * self.height
+    }
+}
+
+fn deserialize_bitrate<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct Bitrate {
+        video: f64,
+        #[serde(default)]
+        audio: f64,
+    }
+
+    let bitrate = Bitrate::deserialize(deserializer)?;
+    Ok(Some((bitrate.video + bitrate.audio).round() as u64))
+}
+
+/// Which stream to download when a VOD has several encodings
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum VideoQuality {
+    /// Highest resolution
+    #[default]
+    Max,
+    /// Highest resolution up to this height, e.g. `1080`
+    Height(u64),
+    /// Lowest resolution of at least this height, e.g. `720+`
+    AtLeast(u64),
+}
+
+impl FromStr for VideoQuality {
+    type Err = anyhow::Error;
+
+    /// Parse `max`, a height like `1080` or `1080p`, or a minimum height like `720+` or `720p+`
+    fn from_str(s: &str) -> Result<Self> {
+        let height = |h: &str| {
+            h.trim_end_matches('p')
+                .parse()
+                .map_err(|_| anyhow!("invalid video quality {}", s))
+        };
+        match s.trim() {
+            "max" => Ok(Self::Max),
+            s => match s.strip_suffix('+') {
+                Some(h) => Ok(Self::AtLeast(height(h)?)),
+                None => Ok(Self::Height(height(s)?)),
+            },
+        }
+    }
+}
+
+/// Which streams of a VOD to download
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct VideoPolicy {
+    pub quality: VideoQuality,
+    /// Also keep the lowest resolution stream as a proxy for previews
+    pub proxy: bool,
+    /// Preferred codecs, most preferred first, between streams of the same resolution
+    pub codecs: Vec<String>,
+}
+
+impl VideoPolicy {
+    /// Stream to download out of `videos`, falls back to the closest stream if none matches the
+    /// quality exactly
+    ///
+    /// Streams of the selected resolution are picked by codec preference, then bitrate, then file
+    /// size.
+    pub fn select<'a>(&self, videos: &'a [Video]) -> Option<&'a Video> {
+        let res = |v: &&Video| v.encoding.resolution();
+        let pixels = |v: &Video| v.encoding.pixels();
+        let target = match self.quality {
+            VideoQuality::Max => videos.iter().map(pixels).max(),
+            VideoQuality::Height(h) => videos
+                .iter()
+                .filter(|v| res(v) <= h)
+                .map(pixels)
+                .max()
+                .or_else(|| videos.iter().map(pixels).min()),
+            VideoQuality::AtLeast(h) => videos
+                .iter()
+                .filter(|v| res(v) >= h)
+                .map(pixels)
+                .min()
+                .or_else(|| videos.iter().map(pixels).max()),
+        }?;
+        videos
+            .iter()
+            .filter(|v| pixels(v) == target)
+            .max_by_key(|v| (Reverse(self.codec_rank(v)), v.bitrate, v.size))
+    }
+
+    /// Low resolution proxy to download in addition to the selected stream, if enabled and of a
+    /// lower resolution than it
+    pub fn select_proxy<'a>(&self, videos: &'a [Video]) -> Option<&'a Video> {
+        if !self.proxy {
+            return None;
+        }
+        let proxy = videos.iter().min_by_key(|v| v.quality_key())?;
+        let selected = self.select(videos)?;
+        (proxy.encoding.pixels() < selected.encoding.pixels()).then_some(proxy)
+    }
+
+    /// Position of the codec of `video` in the preferred codecs, after every preferred codec if
+    /// it isn't one of them
+    fn codec_rank(&self, video: &Video) -> usize {
+        video
+            .codec
+            .as_deref()
+            .and_then(|codec| {
+                self.codecs
+                    .iter()
+                    .position(|c| c.eq_ignore_ascii_case(codec))
+            })
+            .unwrap_or(self.codecs.len())
+    }
+}
+
+/// Download the stream of a VOD selected by `policy` as `{stem}.mp4`, and its proxy as
+/// `{stem}-proxy.mp4`
+pub(crate) async fn download_vod(
+    client: &ClientWithMiddleware,
+    videos: &[Video],
+    policy: &VideoPolicy,
+    directory: &Path,
+    stem: &str,
+) -> Result<()> {
+    let video = policy
+        .select(videos)
+        .ok_or_else(|| anyhow!("no streams for video {}", stem))?;
+    download_stream(client, video, policy, directory, stem).await?;
+    if let Some(proxy) = policy.select_proxy(videos) {
+        // The lowest stream of a manifest
+        let policy = VideoPolicy {
+            quality: VideoQuality::Height(0),
+            proxy: false,
+            codecs: policy.codecs.clone(),
+        };
+        let stem = format!("{}-proxy", stem);
+        download_stream(client, proxy, &policy, directory, &stem).await?;
     }
+    Ok(())
 }
 
-impl Ord for Encoding {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        let size = |e: &Self| e.width * e.height;
-        size(self).cmp(&size(other))
+/// Download a stream, picking the stream of a manifest with `policy`
+async fn download_stream(
+    client: &ClientWithMiddleware,
+    video: &Video,
+    policy: &VideoPolicy,
+    directory: &Path,
+    stem: &str,
+) -> Result<()> {
+    let url = Url::parse(&video.source)?;
+    if let Some(kind) = ManifestKind::of(&url) {
+        return download_manifest(client, &url, kind, policy, directory, stem).await;
     }
+    let ext = url
+        .path()
+        .rsplit_once('.')
+        .map(|(_, ext)| ext)
+        .unwrap_or("mp4");
+    let filename = format!("{}.{}", stem, ext);
+    streamed_download(client, &video.source, directory.join(filename)).await
 }
 
 pub(crate) enum VideoType {
@@ -86,12 +268,22 @@ pub(crate) struct LiveVideo {
     infra_id: String,
     #[serde(rename = "videoId")]
     video_id: u64,
+    /// Chat of a live broadcast
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_id: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct MediaInfo {
     pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<Thumbnail>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub(crate) struct Thumbnail {
+    pub url: String,
 }
 
 impl VideoType {
@@ -108,24 +300,38 @@ pub struct CVideo {
     pub infra_id: String,
 }
 
+/// Streams and caption tracks of a VOD
+#[derive(Debug)]
+pub struct VodInfo {
+    /// Videos sorted by resolution
+    pub videos: Vec<Video>,
+    pub captions: Vec<Caption>,
+}
+
 pub(crate) async fn vod_videos(
-    client: &Client,
+    client: &ClientWithMiddleware,
+    signer: &Signer,
     auth: &str,
     video_ids: &VideoType,
-    secret: &[u8],
 ) -> Result<Vec<Video>> {
-    let mut videos = match video_ids {
+    Ok(vod_info(client, signer, auth, video_ids).await?.videos)
+}
+
+pub(crate) async fn vod_info(
+    client: &ClientWithMiddleware,
+    signer: &Signer,
+    auth: &str,
+    video_ids: &VideoType,
+) -> Result<VodInfo> {
+    let vod = match video_ids {
         VideoType::Extension(e) => {
             // Get inKey
             let url = format!(
                 "/video/v1.0/vod/{}/inKey?preview=false&appId={}&wpf=pc",
                 e.video_id, APP_ID
             );
-            let url = compute_url(&url, secret).await?;
-            let in_key = client.post(url.as_str())
-                .header(header::REFERER, REFERER)
-                .header(header::AUTHORIZATION, auth)
-                .send()
+            let in_key = signer
+                .send(client, Method::POST, &url, auth)
                 .await?
                 .error_for_status()?
                 .json::<InKeyResponse>()
@@ -134,7 +340,8 @@ pub(crate) async fn vod_videos(
 
             // Get vod info
             let url = format!(
-                "https://global.apis.naver.com/rmcnmv/rmcnmv/vod/play/v2.0/{}",
+                "{}/vod/play/v2.0/{}",
+                signer.domains().vod,
                 video_ids.infra_id()
             );
             client
@@ -145,8 +352,6 @@ pub(crate) async fn vod_videos(
                 .error_for_status()?
                 .json::<VodResponse>()
                 .await?
-                .videos
-                .list
         }
         VideoType::NoExtension(id) => {
             // Get vod info directly
@@ -154,22 +359,111 @@ pub(crate) async fn vod_videos(
                 "/cvideo/v1.0/cvideo-{}/playInfo?videoId={}&appId={}&language=en&platform=WEB&wpf=pc",
                 id.post_id, id.post_id, APP_ID
             );
-            let url = compute_url(&url, secret).await?;
-            client
-                .get(url.as_str())
-                .header(header::REFERER, REFERER)
-                .header(header::AUTHORIZATION, auth)
-                .send()
+            signer
+                .get(client, &url, auth)
                 .await?
                 .error_for_status()?
                 .json::<PlayInfoResponse>()
                 .await?
                 .play_info
-                .videos
-                .list
         }
     };
-    videos.sort();
+    let mut videos = vod.videos.list;
+    videos.sort_by_key(Video::quality_key);
+
+    Ok(VodInfo {
+        videos,
+        captions: vod.captions.list,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn video(width: u64, height: u64, bitrate: u64) -> Video {
+        Video {
+            source: format!("{}x{}-{}", width, height, bitrate),
+            size: bitrate * 100,
+            bitrate: Some(bitrate),
+            encoding: Encoding { width, height },
+            codec: None,
+        }
+    }
 
-    Ok(videos)
+    #[test]
+    fn select_quality() {
+        let videos = [
+            video(480, 270, 400),
+            video(1280, 720, 1200),
+            video(1280, 720, 2000),
+            video(1920, 1080, 4000),
+        ];
+        let select = |quality: &str| {
+            let policy = VideoPolicy {
+                quality: quality.parse().unwrap(),
+                proxy: false,
+                codecs: Vec::new(),
+            };
+            policy.select(&videos).unwrap().source.as_str()
+        };
+        assert_eq!(select("max"), "1920x1080-4000");
+        assert_eq!(select("1080p"), "1920x1080-4000");
+        assert_eq!(select("720"), "1280x720-2000");
+        assert_eq!(select("144"), "480x270-400");
+        assert_eq!(select("720p+"), "1280x720-2000");
+        assert_eq!(select("2160+"), "1920x1080-4000");
+        assert!("best".parse::<VideoQuality>().is_err());
+    }
+
+    #[test]
+    fn portrait_resolution() {
+        let videos = [video(720, 1280, 1200), video(1080, 1920, 4000)];
+        let policy = VideoPolicy {
+            quality: VideoQuality::Height(720),
+            proxy: true,
+            codecs: Vec::new(),
+        };
+        assert_eq!(policy.select(&videos).unwrap().source, "720x1280-1200");
+        assert!(policy.select_proxy(&videos).is_none());
+    }
+
+    #[test]
+    fn prefer_codec() {
+        let codec = |mut video: Video, codec: &str| {
+            video.codec = Some(codec.to_owned());
+            video
+        };
+        let videos = [
+            codec(video(1280, 720, 2000), "h264"),
+            codec(video(1920, 1080, 5000), "h264"),
+            codec(video(1920, 1080, 3000), "hevc"),
+        ];
+        let mut policy = VideoPolicy::default();
+        assert_eq!(policy.select(&videos).unwrap().source, "1920x1080-5000");
+        // Codec preference doesn't lower the resolution
+        policy.codecs = vec!["HEVC".to_owned()];
+        assert_eq!(policy.select(&videos).unwrap().source, "1920x1080-3000");
+        policy.quality = VideoQuality::Height(720);
+        assert_eq!(policy.select(&videos).unwrap().source, "1280x720-2000");
+    }
+
+    #[test]
+    fn no_proxy_of_same_resolution() {
+        let codec = |mut video: Video, codec: &str| {
+            video.codec = Some(codec.to_owned());
+            video
+        };
+        let videos = [
+            codec(video(1920, 1080, 5000), "h264"),
+            codec(video(1920, 1080, 3000), "hevc"),
+        ];
+        let policy = VideoPolicy {
+            proxy: true,
+            codecs: vec!["hevc".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(policy.select(&videos).unwrap().source, "1920x1080-3000");
+        assert!(policy.select_proxy(&videos).is_none());
+    }
 }
@@ -1,6 +1,16 @@
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
 #[derive(Debug)]
 pub enum WeverseError {
-    Login,
+    NotLoggedIn(String),
+    /// The refresh token of an account expired, it has to log in again
+    TokenExpired {
+        email: String,
+        /// `None` if the token doesn't say when it expires
+        expiry: Option<OffsetDateTime>,
+    },
+    Cookies,
     Auth,
     SavedAuthFile,
     Download(String),
@@ -11,7 +21,20 @@ impl std::error::Error for WeverseError {}
 impl std::fmt::Display for WeverseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Login => write!(f, "failed to log in to weverse"),
+            Self::NotLoggedIn(email) => write!(
+                f,
+                "no saved weverse login for {}, log in with `sns-archive weverse login`",
+                email
+            ),
+            Self::TokenExpired { email, expiry } => {
+                write!(f, "weverse login of {} expired", email)?;
+                if let Some(expiry) = expiry {
+                    let expiry = expiry.format(&Rfc3339).map_err(|_| std::fmt::Error)?;
+                    write!(f, " at {}", expiry)?;
+                }
+                write!(f, ", log in again with `sns-archive weverse login`")
+            }
+            Self::Cookies => write!(f, "no weverse tokens found in cookies"),
             Self::Auth => write!(f, "failed to authenticate with weverse"),
             Self::SavedAuthFile => write!(f, "failed to get weverse saved auth file"),
             Self::Download(s) => write!(f, "failed to get download weverse post {}", s),
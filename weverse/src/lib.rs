@@ -1,8 +1,14 @@
 mod auth;
 mod client;
+mod domains;
 pub mod endpoint;
 mod error;
+mod manifest;
+#[cfg(test)]
+mod mock;
+mod subtitles;
 mod utils;
 
-pub use auth::LoginInfo;
+pub use auth::{login_status, logout, LoginInfo, LoginStatus, Tokens};
 pub use client::AuthenticatedWeverseClient;
+pub use error::WeverseError;
@@ -0,0 +1,1080 @@
+//! Download videos that are only available as HLS or DASH manifests
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use anyhow::{anyhow, bail, Result};
+use futures::{stream, StreamExt};
+use reqwest::header::RANGE;
+use reqwest::{StatusCode, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use roxmltree::Node;
+use sns_archive_common::streamed_download;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::endpoint::vod::{Encoding, Video, VideoPolicy};
+
+/// Number of segments downloaded at once
+const SEGMENT_CONNECTIONS: usize = 8;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ManifestKind {
+    Hls,
+    Dash,
+}
+
+impl ManifestKind {
+    /// Kind of manifest `url` points to, `None` for regular video files
+    pub(crate) fn of(url: &Url) -> Option<Self> {
+        let path = url.path().to_lowercase();
+        if path.ends_with(".m3u8") {
+            Some(Self::Hls)
+        } else if path.ends_with(".mpd") {
+            Some(Self::Dash)
+        } else {
+            None
+        }
+    }
+}
+
+/// Download the video of a manifest to `{stem}.mp4`, or `{stem}.ts` for MPEG-TS without ffmpeg
+///
+/// The stream is picked with `policy` if the manifest has several. Segments are downloaded in
+/// parallel and kept in a hidden directory next to the video until they are joined, so an
+/// interrupted download continues with the missing segments. Separate audio is muxed in with
+/// ffmpeg.
+pub(crate) async fn download_manifest(
+    client: &ClientWithMiddleware,
+    url: &Url,
+    kind: ManifestKind,
+    policy: &VideoPolicy,
+    directory: &Path,
+    stem: &str,
+) -> Result<()> {
+    let tracks = match kind {
+        ManifestKind::Hls => hls_tracks(client, url, policy).await?,
+        ManifestKind::Dash => dash_tracks(client, url, policy).await?,
+    };
+    download_tracks(client, url, &tracks, directory, stem).await
+}
+
+/// Segments of a stream, joined in order into a single file
+#[derive(PartialEq, Eq, Debug)]
+struct Track {
+    /// Initialization section of fMP4 segments
+    init: Option<Segment>,
+    segments: Vec<Segment>,
+    container: Container,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Container {
+    Mp4,
+    MpegTs,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Segment {
+    url: Url,
+    /// Part of the resource at `url` holding the segment, the whole resource if `None`
+    range: Option<ByteRange>,
+}
+
+impl Segment {
+    fn new(url: Url) -> Self {
+        Self { url, range: None }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct ByteRange {
+    start: u64,
+    length: u64,
+}
+
+impl ByteRange {
+    /// Last byte of the range
+    fn end(&self) -> u64 {
+        self.start + self.length.max(1) - 1
+    }
+}
+
+async fn download_tracks(
+    client: &ClientWithMiddleware,
+    url: &Url,
+    tracks: &[Track],
+    directory: &Path,
+    stem: &str,
+) -> Result<()> {
+    let ffmpeg = has_ffmpeg().await;
+    if tracks.len() > 1 && !ffmpeg {
+        bail!("ffmpeg is required to join the separate audio of {}", url);
+    }
+
+    let segments_dir = directory.join(format!(".{}.segments", stem));
+    let mut joined = Vec::new();
+    for (i, track) in tracks.iter().enumerate() {
+        let dir = segments_dir.join(i.to_string());
+        joined.push(download_track(client, track, &dir).await?);
+    }
+
+    // fMP4 segments joined after their init segment are a valid fragmented MP4, while MPEG-TS
+    // segments are remuxed to MP4 if ffmpeg is available
+    let mp4 = directory.join(format!("{}.mp4", stem));
+    match (tracks, joined.as_slice()) {
+        ([track], [joined]) => match track.container {
+            Container::Mp4 => fs::rename(joined, &mp4).await?,
+            Container::MpegTs if ffmpeg => remux(std::slice::from_ref(joined), &mp4).await?,
+            Container::MpegTs => fs::rename(joined, directory.join(format!("{}.ts", stem))).await?,
+        },
+        _ => remux(&joined, &mp4).await?,
+    }
+    fs::remove_dir_all(&segments_dir).await?;
+
+    Ok(())
+}
+
+/// Download the segments of `track` to `dir` and join them, skipping the segments finished by
+/// an earlier attempt
+async fn download_track(
+    client: &ClientWithMiddleware,
+    track: &Track,
+    dir: &Path,
+) -> Result<PathBuf> {
+    fs::create_dir_all(dir).await?;
+    let parts: Vec<(Segment, PathBuf)> = track
+        .init
+        .iter()
+        .chain(track.segments.iter())
+        .enumerate()
+        .map(|(i, segment)| (segment.clone(), dir.join(format!("{:05}", i))))
+        .collect();
+    let paths: Vec<PathBuf> = parts.iter().map(|(_, path)| path.clone()).collect();
+    stream::iter(parts)
+        .map(|(segment, path)| download_segment(client, segment, path))
+        .buffer_unordered(SEGMENT_CONNECTIONS)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<()>>()?;
+
+    let joined = dir.join("joined");
+    join_files(&paths, &joined).await?;
+    Ok(joined)
+}
+
+async fn download_segment(
+    client: &ClientWithMiddleware,
+    segment: Segment,
+    path: PathBuf,
+) -> Result<()> {
+    if fs::try_exists(&path).await? {
+        return Ok(());
+    }
+    match segment.range {
+        Some(range) => download_range(client, &segment.url, range, &path).await,
+        None => streamed_download(client, segment.url, path).await,
+    }
+}
+
+/// Download part of a resource to `path`, which only exists once the whole part was received
+async fn download_range(
+    client: &ClientWithMiddleware,
+    url: &Url,
+    range: ByteRange,
+    path: &Path,
+) -> Result<()> {
+    let resp = client
+        .get(url.clone())
+        .header(RANGE, format!("bytes={}-{}", range.start, range.end()))
+        .send()
+        .await?
+        .error_for_status()?;
+    let partial = resp.status() == StatusCode::PARTIAL_CONTENT;
+    let body = resp.bytes().await?;
+    let bytes = if partial {
+        &body[..]
+    } else {
+        // Server ignored the range and sent the whole resource
+        body.get(range.start as usize..(range.start + range.length) as usize)
+            .ok_or_else(|| anyhow!("{} is shorter than its segments", url))?
+    };
+    if bytes.len() as u64 != range.length {
+        bail!(
+            "expected {} bytes of {} but received {} bytes",
+            range.length,
+            url,
+            bytes.len()
+        );
+    }
+
+    let part = path.with_extension("part");
+    fs::write(&part, bytes).await?;
+    fs::rename(&part, path).await?;
+    Ok(())
+}
+
+async fn join_files(parts: &[PathBuf], path: &Path) -> Result<()> {
+    let mut file = fs::File::create(path).await?;
+    for part in parts {
+        let mut part = fs::File::open(part).await?;
+        tokio::io::copy(&mut part, &mut file).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+async fn has_ffmpeg() -> bool {
+    Command::new("ffmpeg")
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await
+        .is_ok_and(|s| s.success())
+}
+
+/// Copy the streams of `inputs` into an MP4 file without re-encoding
+///
+/// With several inputs, the video of the first is combined with the audio of the others.
+async fn remux(inputs: &[PathBuf], output: &Path) -> Result<()> {
+    let temp = output.with_extension("remux.mp4");
+    let mut command = Command::new("ffmpeg");
+    command.arg("-y");
+    for input in inputs {
+        command.arg("-i").arg(input);
+    }
+    if inputs.len() > 1 {
+        command.arg("-map").arg("0:v");
+        for i in 1..inputs.len() {
+            command.arg("-map").arg(format!("{}:a", i));
+        }
+    }
+    let status = command
+        .arg("-c")
+        .arg("copy")
+        .arg("-movflags")
+        .arg("+faststart")
+        .arg(&temp)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await?;
+    if !status.success() {
+        let _ = fs::remove_file(&temp).await;
+        bail!("ffmpeg failed to remux {}", output.display());
+    }
+    fs::rename(&temp, output).await?;
+    Ok(())
+}
+
+/// Codec of a `CODECS` or `codecs` attribute like `avc1.640028,mp4a.40.2` in the names used by
+/// [`VideoPolicy::codecs`]
+fn video_codec(codecs: &str) -> Option<String> {
+    codecs.split(',').find_map(|codec| {
+        let name = match codec.trim().split('.').next()? {
+            "avc1" | "avc3" => "h264",
+            "hvc1" | "hev1" => "hevc",
+            "av01" => "av1",
+            "vp09" | "vp9" => "vp9",
+            _ => return None,
+        };
+        Some(name.to_owned())
+    })
+}
+
+/// Video stream of a manifest as a [`Video`], so it can be picked with a [`VideoPolicy`]
+fn stream_video(
+    source: String,
+    bandwidth: u64,
+    width: u64,
+    height: u64,
+    codecs: Option<&str>,
+) -> Video {
+    Video {
+        source,
+        size: 0,
+        bitrate: Some(bandwidth / 1000),
+        encoding: Encoding { width, height },
+        codec: codecs.and_then(video_codec),
+    }
+}
+
+/// Index of the stream of `videos` picked by `policy`
+fn select(policy: &VideoPolicy, videos: &[Video]) -> Option<usize> {
+    let selected = policy.select(videos)?;
+    videos.iter().position(|v| std::ptr::eq(v, selected))
+}
+
+async fn hls_tracks(
+    client: &ClientWithMiddleware,
+    url: &Url,
+    policy: &VideoPolicy,
+) -> Result<Vec<Track>> {
+    let master = match fetch_playlist(client, url).await? {
+        Playlist::Media(track) => return Ok(vec![track]),
+        Playlist::Master(master) => master,
+    };
+    let variant = master
+        .select(policy)
+        .ok_or_else(|| anyhow!("no variants in playlist {}", url))?;
+    let mut tracks = vec![fetch_media_playlist(client, &variant.uri).await?];
+    if let Some(audio) = master.audio(variant) {
+        tracks.push(fetch_media_playlist(client, audio).await?);
+    }
+    Ok(tracks)
+}
+
+async fn fetch_playlist(client: &ClientWithMiddleware, url: &Url) -> Result<Playlist> {
+    let text = client
+        .get(url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    parse_playlist(url, &text)
+}
+
+async fn fetch_media_playlist(client: &ClientWithMiddleware, url: &Url) -> Result<Track> {
+    match fetch_playlist(client, url).await? {
+        Playlist::Media(track) => Ok(track),
+        Playlist::Master(_) => bail!("nested master playlist {}", url),
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+enum Playlist {
+    Master(MasterPlaylist),
+    Media(Track),
+}
+
+#[derive(PartialEq, Eq, Debug)]
+struct MasterPlaylist {
+    variants: Vec<Variant>,
+    /// Audio renditions of `EXT-X-MEDIA` tags
+    audio: Vec<Rendition>,
+}
+
+impl MasterPlaylist {
+    /// Variant picked by `policy`
+    fn select(&self, policy: &VideoPolicy) -> Option<&Variant> {
+        let videos: Vec<Video> = self
+            .variants
+            .iter()
+            .map(|v| {
+                let (width, height) = v.resolution.unwrap_or_default();
+                stream_video(
+                    v.uri.to_string(),
+                    v.bandwidth,
+                    width,
+                    height,
+                    v.codecs.as_deref(),
+                )
+            })
+            .collect();
+        select(policy, &videos).map(|i| &self.variants[i])
+    }
+
+    /// Playlist of the separate audio of `variant`, the default rendition of its group or else
+    /// the first one
+    ///
+    /// `None` if the audio is part of the variant's segments.
+    fn audio(&self, variant: &Variant) -> Option<&Url> {
+        let group = variant.audio.as_deref()?;
+        let mut renditions = self.audio.iter().filter(|r| r.group == group);
+        let rendition = renditions
+            .clone()
+            .find(|r| r.default)
+            .or_else(|| renditions.next())?;
+        rendition.uri.as_ref()
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+struct Variant {
+    bandwidth: u64,
+    /// Width and height
+    resolution: Option<(u64, u64)>,
+    codecs: Option<String>,
+    /// Group of the audio renditions
+    audio: Option<String>,
+    uri: Url,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+struct Rendition {
+    group: String,
+    default: bool,
+    /// Playlist of the rendition, `None` if it is part of the variant's segments
+    uri: Option<Url>,
+}
+
+/// Parse an HLS playlist, URIs are resolved relative to the playlist's `url`
+fn parse_playlist(url: &Url, text: &str) -> Result<Playlist> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    if lines.next() != Some("#EXTM3U") {
+        bail!("{} is not an HLS playlist", url);
+    }
+
+    let mut variants = Vec::new();
+    let mut audio = Vec::new();
+    let mut init = None;
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut stream_inf = None;
+    let mut byte_range = None;
+    for line in lines {
+        if let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            stream_inf = Some(attributes(attrs));
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let mut attrs = attributes(attrs);
+            if attrs.get("TYPE").map(String::as_str) == Some("AUDIO") {
+                audio.push(Rendition {
+                    group: attrs.remove("GROUP-ID").unwrap_or_default(),
+                    default: attrs.get("DEFAULT").map(String::as_str) == Some("YES"),
+                    uri: attrs.get("URI").map(|uri| url.join(uri)).transpose()?,
+                });
+            }
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-MAP:") {
+            let attrs = attributes(attrs);
+            let uri = attrs
+                .get("URI")
+                .ok_or_else(|| anyhow!("missing init segment URI in {}", url))?;
+            let range = match attrs.get("BYTERANGE") {
+                Some(range) => Some(parse_byte_range(url, range, None)?),
+                None => None,
+            };
+            init = Some(Segment {
+                url: url.join(uri)?,
+                range,
+            });
+        } else if let Some(range) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            // Without an offset the range follows the previous segment's
+            let previous = segments
+                .last()
+                .and_then(|s| s.range)
+                .map(|r| r.start + r.length);
+            byte_range = Some(parse_byte_range(url, range, previous)?);
+        } else if let Some(attrs) = line.strip_prefix("#EXT-X-KEY:") {
+            if attributes(attrs).get("METHOD").map(String::as_str) != Some("NONE") {
+                bail!("encrypted HLS playlist {} is not supported", url);
+            }
+        } else if !line.starts_with('#') {
+            let uri = url.join(line)?;
+            match stream_inf.take() {
+                Some(attrs) => variants.push(Variant {
+                    bandwidth: attrs
+                        .get("BANDWIDTH")
+                        .and_then(|b| b.parse().ok())
+                        .unwrap_or_default(),
+                    resolution: attrs.get("RESOLUTION").and_then(|r| {
+                        let (width, height) = r.split_once('x')?;
+                        Some((width.parse().ok()?, height.parse().ok()?))
+                    }),
+                    codecs: attrs.get("CODECS").cloned(),
+                    audio: attrs.get("AUDIO").cloned(),
+                    uri,
+                }),
+                None => segments.push(Segment {
+                    url: uri,
+                    range: byte_range.take(),
+                }),
+            }
+        }
+    }
+
+    if variants.is_empty() {
+        let container = match init {
+            Some(_) => Container::Mp4,
+            None => Container::MpegTs,
+        };
+        Ok(Playlist::Media(Track {
+            init,
+            segments,
+            container,
+        }))
+    } else {
+        Ok(Playlist::Master(MasterPlaylist { variants, audio }))
+    }
+}
+
+/// Parse a byte range like `1024@2048`, the offset defaults to `previous`
+fn parse_byte_range(url: &Url, range: &str, previous: Option<u64>) -> Result<ByteRange> {
+    let invalid = || anyhow!("invalid byte range {} in {}", range, url);
+    let (length, start) = match range.split_once('@') {
+        Some((length, start)) => (length, Some(start.parse().map_err(|_| invalid())?)),
+        None => (range, previous),
+    };
+    Ok(ByteRange {
+        start: start.ok_or_else(invalid)?,
+        length: length.parse().map_err(|_| invalid())?,
+    })
+}
+
+/// Parse an attribute list like `BANDWIDTH=1280000,CODECS="avc1.4d401f,mp4a.40.2"`
+fn attributes(list: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+    let mut rest = list;
+    while let Some((name, value)) = rest.split_once('=') {
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let (value, next) = quoted.split_once('"').unwrap_or((quoted, ""));
+                (value, next.trim_start_matches(','))
+            }
+            None => value.split_once(',').unwrap_or((value, "")),
+        };
+        attrs.insert(name.trim().to_owned(), value.to_owned());
+        rest = next;
+    }
+    attrs
+}
+
+async fn dash_tracks(
+    client: &ClientWithMiddleware,
+    url: &Url,
+    policy: &VideoPolicy,
+) -> Result<Vec<Track>> {
+    let text = client
+        .get(url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    parse_mpd(url, &text, policy)
+}
+
+/// Tracks of the video representation of a DASH manifest picked by `policy`, followed by the
+/// highest bandwidth audio representation if the audio is separate
+fn parse_mpd(url: &Url, text: &str, policy: &VideoPolicy) -> Result<Vec<Track>> {
+    let doc = roxmltree::Document::parse(text)?;
+    let mpd = doc.root_element();
+    if mpd.attribute("type") == Some("dynamic") {
+        bail!("live DASH manifest {} is not supported", url);
+    }
+    let period = child(mpd, "Period").ok_or_else(|| anyhow!("no period in {}", url))?;
+    let duration = period
+        .attribute("duration")
+        .or_else(|| mpd.attribute("mediaPresentationDuration"))
+        .and_then(parse_duration);
+    let base = base_url(&base_url(url, mpd)?, period)?;
+
+    let mut videos = Vec::new();
+    let mut video_reps = Vec::new();
+    let mut audio: Option<(Node, Node)> = None;
+    for set in period
+        .children()
+        .filter(|n| n.has_tag_name("AdaptationSet"))
+    {
+        for rep in set.children().filter(|n| n.has_tag_name("Representation")) {
+            let bandwidth = attr_u64(rep, "bandwidth").unwrap_or_default();
+            let content = set
+                .attribute("contentType")
+                .or_else(|| inherited(rep, set, "mimeType"))
+                .unwrap_or_default();
+            if content.starts_with("audio") {
+                if audio.is_none_or(|(a, _)| attr_u64(a, "bandwidth") < Some(bandwidth)) {
+                    audio = Some((rep, set));
+                }
+            } else if content.starts_with("video") || inherited(rep, set, "width").is_some() {
+                let size = |name| {
+                    inherited(rep, set, name)
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or_default()
+                };
+                let video = stream_video(
+                    rep.attribute("id").unwrap_or_default().to_owned(),
+                    bandwidth,
+                    size("width"),
+                    size("height"),
+                    inherited(rep, set, "codecs"),
+                );
+                videos.push(video);
+                video_reps.push((rep, set));
+            }
+        }
+    }
+
+    let (rep, set) = select(policy, &videos)
+        .map(|i| video_reps[i])
+        .ok_or_else(|| anyhow!("no video in {}", url))?;
+    let mut tracks = vec![dash_track(&base, duration, period, set, rep)?];
+    if let Some((rep, set)) = audio {
+        tracks.push(dash_track(&base, duration, period, set, rep)?);
+    }
+    Ok(tracks)
+}
+
+/// Segments of a DASH representation
+///
+/// `duration` is the length of the period in seconds.
+fn dash_track(
+    base: &Url,
+    duration: Option<f64>,
+    period: Node,
+    set: Node,
+    rep: Node,
+) -> Result<Track> {
+    let base = base_url(&base_url(base, set)?, rep)?;
+    let id = rep.attribute("id").unwrap_or_default();
+    let bandwidth = attr_u64(rep, "bandwidth").unwrap_or_default();
+    let segment_info = |name: &str| {
+        child(rep, name)
+            .or_else(|| child(set, name))
+            .or_else(|| child(period, name))
+    };
+
+    let (init, segments) = if let Some(template) = segment_info("SegmentTemplate") {
+        let expand = |template: &str, number, time| {
+            base.join(&expand_template(template, id, bandwidth, number, time))
+        };
+        let init = match template.attribute("initialization") {
+            Some(init) => Some(Segment::new(expand(init, 0, 0)?)),
+            None => None,
+        };
+        let media = template
+            .attribute("media")
+            .ok_or_else(|| anyhow!("missing media template of {}", id))?;
+        let start_number = attr_u64(template, "startNumber").unwrap_or(1);
+        let timescale = attr_u64(template, "timescale").unwrap_or(1);
+        let times = match child(template, "SegmentTimeline") {
+            Some(timeline) => timeline_times(timeline, timescale, duration)?,
+            None => {
+                let segment_duration = attr_u64(template, "duration")
+                    .filter(|d| *d > 0)
+                    .ok_or_else(|| anyhow!("missing segment duration of {}", id))?;
+                let duration =
+                    duration.ok_or_else(|| anyhow!("missing duration of representation {}", id))?;
+                let count = (duration * timescale as f64 / segment_duration as f64).ceil() as u64;
+                (0..count).map(|i| i * segment_duration).collect()
+            }
+        };
+        let segments = times
+            .into_iter()
+            .zip(start_number..)
+            .map(|(time, number)| Ok(Segment::new(expand(media, number, time)?)))
+            .collect::<Result<Vec<_>>>()?;
+        (init, segments)
+    } else if let Some(list) = segment_info("SegmentList") {
+        let segment = |node: Node, url_attr, range_attr| -> Result<Segment> {
+            Ok(Segment {
+                url: match node.attribute(url_attr) {
+                    Some(url) => base.join(url)?,
+                    None => base.clone(),
+                },
+                range: node.attribute(range_attr).and_then(parse_dash_range),
+            })
+        };
+        let init = match child(list, "Initialization") {
+            Some(init) => Some(segment(init, "sourceURL", "range")?),
+            None => None,
+        };
+        let segments = list
+            .children()
+            .filter(|n| n.has_tag_name("SegmentURL"))
+            .map(|n| segment(n, "media", "mediaRange"))
+            .collect::<Result<Vec<_>>>()?;
+        (init, segments)
+    } else {
+        // A single file, maybe with a SegmentBase index that isn't needed to download it whole
+        (None, vec![Segment::new(base)])
+    };
+
+    Ok(Track {
+        init,
+        segments,
+        container: Container::Mp4,
+    })
+}
+
+/// Start times of the segments of a `SegmentTimeline`
+fn timeline_times(timeline: Node, timescale: u64, duration: Option<f64>) -> Result<Vec<u64>> {
+    let mut times = Vec::new();
+    let mut time = 0;
+    for s in timeline.children().filter(|n| n.has_tag_name("S")) {
+        if let Some(t) = attr_u64(s, "t") {
+            time = t;
+        }
+        let d = attr_u64(s, "d")
+            .filter(|d| *d > 0)
+            .ok_or_else(|| anyhow!("missing segment duration in timeline"))?;
+        let repeat: i64 = s.attribute("r").and_then(|r| r.parse().ok()).unwrap_or(0);
+        let count = if repeat >= 0 {
+            repeat as u64 + 1
+        } else {
+            // Repeated until the end of the period
+            let duration = duration.ok_or_else(|| anyhow!("missing duration of timeline"))?;
+            let end = (duration * timescale as f64) as u64;
+            end.saturating_sub(time).div_ceil(d)
+        };
+        for _ in 0..count {
+            times.push(time);
+            time += d;
+        }
+    }
+    Ok(times)
+}
+
+/// Fill in the identifiers of a `SegmentTemplate` URL like `$RepresentationID$/$Number%05d$.m4s`
+fn expand_template(template: &str, id: &str, bandwidth: u64, number: u64, time: u64) -> String {
+    let mut expanded = String::new();
+    // Text and identifiers alternate between `$`s
+    for (i, part) in template.split('$').enumerate() {
+        if i % 2 == 0 {
+            expanded.push_str(part);
+            continue;
+        }
+        let (name, format) = part.split_once('%').unwrap_or((part, ""));
+        let width: usize = format
+            .trim_start_matches('0')
+            .trim_end_matches('d')
+            .parse()
+            .unwrap_or(0);
+        match name {
+            "" => expanded.push('$'),
+            "RepresentationID" => expanded.push_str(id),
+            "Bandwidth" => expanded.push_str(&format!("{:0width$}", bandwidth)),
+            "Number" => expanded.push_str(&format!("{:0width$}", number)),
+            "Time" => expanded.push_str(&format!("{:0width$}", time)),
+            _ => expanded.push_str(&format!("${}$", part)),
+        }
+    }
+    expanded
+}
+
+/// Parse a duration like `PT1H2M3.5S` to seconds
+fn parse_duration(duration: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in duration.strip_prefix('P')?.chars() {
+        let unit = match c {
+            'T' => {
+                in_time = true;
+                continue;
+            }
+            '0'..='9' | '.' => {
+                number.push(c);
+                continue;
+            }
+            'D' if !in_time => 86400.0,
+            'H' if in_time => 3600.0,
+            'M' if in_time => 60.0,
+            'S' if in_time => 1.0,
+            _ => return None,
+        };
+        seconds += number.parse::<f64>().ok()? * unit;
+        number.clear();
+    }
+    Some(seconds)
+}
+
+/// Parse a DASH byte range like `0-1023`
+fn parse_dash_range(range: &str) -> Option<ByteRange> {
+    let (start, end) = range.split_once('-')?;
+    let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+    Some(ByteRange {
+        start,
+        length: end.checked_sub(start)? + 1,
+    })
+}
+
+/// `base` joined with the `BaseURL` of `node`, if it has one
+fn base_url(base: &Url, node: Node) -> Result<Url> {
+    match child(node, "BaseURL").and_then(|n| n.text()) {
+        Some(url) => Ok(base.join(url.trim())?),
+        None => Ok(base.clone()),
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+/// Attribute of a representation, or of its adaptation set
+fn inherited<'a>(rep: Node<'a, '_>, set: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    rep.attribute(name).or_else(|| set.attribute(name))
+}
+
+fn attr_u64(node: Node, name: &str) -> Option<u64> {
+    node.attribute(name)?.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::endpoint::vod::VideoQuality;
+    use crate::mock::MockServer;
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn segments(track: &Track) -> Vec<&str> {
+        track.segments.iter().map(|s| s.url.as_str()).collect()
+    }
+
+    fn quality(quality: &str) -> VideoPolicy {
+        VideoPolicy {
+            quality: quality.parse().unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn master_playlist() {
+        let playlist = url("https://cdn.example/live/master.m3u8?token=1");
+        let text = "#EXTM3U\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"Korean\",URI=\"audio/ko.m3u8\"\n\
+            #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=YES,URI=\"audio/en.m3u8\"\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000,CODECS=\"avc1.4d401f,mp4a.40.2\",RESOLUTION=640x360\n\
+            360p/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,AUDIO=\"aac\"\n\
+            https://other.example/1080p/index.m3u8\n";
+        let Playlist::Master(master) = parse_playlist(&playlist, text).unwrap() else {
+            panic!("not a master playlist");
+        };
+        assert_eq!(
+            master.variants[0],
+            Variant {
+                bandwidth: 800000,
+                resolution: Some((640, 360)),
+                codecs: Some("avc1.4d401f,mp4a.40.2".to_owned()),
+                audio: None,
+                uri: url("https://cdn.example/live/360p/index.m3u8"),
+            }
+        );
+
+        // Variants are picked by the video quality, with the separate audio of their group
+        let max = master.select(&quality("max")).unwrap();
+        assert_eq!(max.uri.as_str(), "https://other.example/1080p/index.m3u8");
+        assert_eq!(
+            master.audio(max).unwrap().as_str(),
+            "https://cdn.example/live/audio/en.m3u8"
+        );
+        let low = master.select(&quality("480")).unwrap();
+        assert_eq!(low.uri.as_str(), "https://cdn.example/live/360p/index.m3u8");
+        assert_eq!(master.audio(low), None);
+    }
+
+    #[test]
+    fn media_playlist() {
+        let playlist = url("https://cdn.example/live/1080p/index.m3u8");
+        let text = "#EXTM3U\n\
+            #EXT-X-TARGETDURATION:4\n\
+            #EXT-X-MAP:URI=\"init.mp4\"\n\
+            #EXTINF:4.0,\n\
+            seg0.m4s\n\
+            #EXTINF:4.0,\n\
+            seg1.m4s\n\
+            #EXT-X-ENDLIST\n";
+        assert_eq!(
+            parse_playlist(&playlist, text).unwrap(),
+            Playlist::Media(Track {
+                init: Some(Segment::new(url("https://cdn.example/live/1080p/init.mp4"))),
+                segments: vec![
+                    Segment::new(url("https://cdn.example/live/1080p/seg0.m4s")),
+                    Segment::new(url("https://cdn.example/live/1080p/seg1.m4s")),
+                ],
+                container: Container::Mp4,
+            })
+        );
+
+        let encrypted = "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\"\nseg0.ts\n";
+        assert!(parse_playlist(&playlist, encrypted).is_err());
+    }
+
+    #[test]
+    fn byte_ranges() {
+        let playlist = url("https://cdn.example/live/index.m3u8");
+        let text = "#EXTM3U\n\
+            #EXT-X-MAP:URI=\"video.mp4\",BYTERANGE=\"100@0\"\n\
+            #EXT-X-BYTERANGE:500@100\n\
+            video.mp4\n\
+            #EXT-X-BYTERANGE:400\n\
+            video.mp4\n";
+        let Playlist::Media(track) = parse_playlist(&playlist, text).unwrap() else {
+            panic!("not a media playlist");
+        };
+        let range = |start, length| Some(ByteRange { start, length });
+        assert_eq!(track.init.unwrap().range, range(0, 100));
+        assert_eq!(track.segments[0].range, range(100, 500));
+        assert_eq!(track.segments[1].range, range(600, 400));
+    }
+
+    #[test]
+    fn mpd() {
+        let manifest = url("https://cdn.example/vod/manifest.mpd");
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT10S">
+              <Period>
+                <AdaptationSet contentType="video" mimeType="video/mp4">
+                  <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4" media="$RepresentationID$/$Time$.m4s">
+                    <SegmentTimeline>
+                      <S t="0" d="4000" r="1"/>
+                      <S d="2000"/>
+                    </SegmentTimeline>
+                  </SegmentTemplate>
+                  <Representation id="720p" bandwidth="2000000" width="1280" height="720" codecs="avc1.64001f"/>
+                  <Representation id="1080p" bandwidth="5000000" width="1920" height="1080" codecs="avc1.640028"/>
+                  <Representation id="1080p-hevc" bandwidth="3000000" width="1920" height="1080" codecs="hvc1.1.6.L120.90"/>
+                </AdaptationSet>
+                <AdaptationSet contentType="audio" mimeType="audio/mp4">
+                  <Representation id="audio-64" bandwidth="64000">
+                    <BaseURL>audio/64.mp4</BaseURL>
+                  </Representation>
+                  <Representation id="audio-128" bandwidth="128000">
+                    <BaseURL>audio/128.mp4</BaseURL>
+                    <SegmentList>
+                      <Initialization range="0-99"/>
+                      <SegmentURL mediaRange="100-599"/>
+                      <SegmentURL mediaRange="600-999"/>
+                    </SegmentList>
+                  </Representation>
+                </AdaptationSet>
+              </Period>
+            </MPD>"#;
+
+        let tracks = parse_mpd(&manifest, text, &quality("720")).unwrap();
+        assert_eq!(
+            tracks[0].init.as_ref().unwrap().url.as_str(),
+            "https://cdn.example/vod/720p/init.mp4"
+        );
+        assert_eq!(
+            segments(&tracks[0]),
+            [
+                "https://cdn.example/vod/720p/0.m4s",
+                "https://cdn.example/vod/720p/4000.m4s",
+                "https://cdn.example/vod/720p/8000.m4s",
+            ]
+        );
+
+        let audio = &tracks[1];
+        assert_eq!(
+            audio.init,
+            Some(Segment {
+                url: url("https://cdn.example/vod/audio/128.mp4"),
+                range: Some(ByteRange {
+                    start: 0,
+                    length: 100
+                }),
+            })
+        );
+        assert_eq!(
+            audio.segments[1].range,
+            Some(ByteRange {
+                start: 600,
+                length: 400
+            })
+        );
+
+        let mut policy = quality("max");
+        let tracks = parse_mpd(&manifest, text, &policy).unwrap();
+        assert!(segments(&tracks[0])[0].contains("/1080p/"));
+        policy.codecs = vec!["hevc".to_owned()];
+        let tracks = parse_mpd(&manifest, text, &policy).unwrap();
+        assert!(segments(&tracks[0])[0].contains("/1080p-hevc/"));
+    }
+
+    #[test]
+    fn template() {
+        assert_eq!(
+            expand_template("$RepresentationID$/seg-$Number%05d$.m4s", "v1", 0, 42, 0),
+            "v1/seg-00042.m4s"
+        );
+        assert_eq!(expand_template("a$$b-$Time$", "v1", 0, 0, 9000), "a$b-9000");
+        assert_eq!(parse_duration("PT1H2M3.5S"), Some(3723.5));
+        assert_eq!(parse_duration("P1DT1S"), Some(86401.0));
+    }
+
+    #[test]
+    fn manifest_kind() {
+        let kind = |url| ManifestKind::of(&Url::parse(url).unwrap());
+        assert_eq!(
+            kind("https://cdn.example/a.m3u8?x=1"),
+            Some(ManifestKind::Hls)
+        );
+        assert_eq!(kind("https://cdn.example/a.mpd"), Some(ManifestKind::Dash));
+        assert_eq!(kind("https://cdn.example/a.mp4"), None);
+    }
+
+    #[tokio::test]
+    async fn resume_hls() {
+        let server = MockServer::start().await;
+        let client = server.client();
+        let master = server.url("/files/hls/master.m3u8");
+        let policy = VideoPolicy {
+            quality: VideoQuality::Height(360),
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let download = || {
+            download_manifest(
+                &client,
+                &master,
+                ManifestKind::Hls,
+                &policy,
+                dir.path(),
+                "video",
+            )
+        };
+
+        // Interrupted by a failing segment, the finished segments are kept
+        server.fail_once("/files/hls/360p/seg2.m4s");
+        assert!(download().await.is_err());
+        let segments = dir.path().join(".video.segments/0");
+        assert!(segments.join("00002").exists());
+        assert!(!segments.join("00003").exists());
+        assert!(!dir.path().join("video.mp4").exists());
+
+        // Only the missing segment is downloaded again
+        download().await.unwrap();
+        let video = std::fs::read_to_string(dir.path().join("video.mp4")).unwrap();
+        assert_eq!(video, "init\nseg0\nseg1\nseg2\n");
+        assert_eq!(server.requests("/files/hls/360p/seg1.m4s"), 1);
+        assert_eq!(server.requests("/files/hls/360p/seg2.m4s"), 2);
+        assert_eq!(server.requests("/files/hls/720p.mp4"), 0);
+        assert!(!dir.path().join(".video.segments").exists());
+    }
+
+    #[tokio::test]
+    async fn hls_byte_ranges() {
+        let server = MockServer::start().await;
+        let master = server.url("/files/hls/master.m3u8");
+        let dir = tempfile::tempdir().unwrap();
+        download_manifest(
+            &server.client(),
+            &master,
+            ManifestKind::Hls,
+            &VideoPolicy::default(),
+            dir.path(),
+            "video",
+        )
+        .await
+        .unwrap();
+        let video = std::fs::read_to_string(dir.path().join("video.mp4")).unwrap();
+        assert_eq!(video, "init\nseg0\nseg1\n");
+    }
+
+    #[tokio::test]
+    async fn dash() {
+        let server = MockServer::start().await;
+        let manifest = server.url("/files/dash/manifest.mpd");
+        let dir = tempfile::tempdir().unwrap();
+        download_manifest(
+            &server.client(),
+            &manifest,
+            ManifestKind::Dash,
+            &quality("480"),
+            dir.path(),
+            "video",
+        )
+        .await
+        .unwrap();
+        let video = std::fs::read_to_string(dir.path().join("video.mp4")).unwrap();
+        assert_eq!(video, "init 480p\nseg 1\nseg 2\nseg 3\n");
+    }
+}
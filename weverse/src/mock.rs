@@ -0,0 +1,200 @@
+//! Local server standing in for Weverse, serving the recorded responses in `fixtures/`
+//!
+//! A request is answered with the fixture at its path, e.g. `GET /api/post/v1.0/post-1-1` with
+//! `fixtures/api/post/v1.0/post-1-1.json`. The `after` and `keyword` parameters select another
+//! fixture of the same path, e.g. `artistTabPosts.after-2.json` for the second page. API requests
+//! must be signed with [`SECRET`] and carry [`AUTH`], like on Weverse. Tokens are only validated
+//! if they are [`AUTH`] and only refreshed with [`REFRESH`]. `Range` requests get part of the
+//! fixture.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use base64::engine::general_purpose;
+use base64::Engine as _;
+use hmac::{Hmac, Mac};
+use reqwest::{StatusCode, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use sha1::Sha1;
+use sns_archive_common::test_server::{Request, Response, TestServer};
+use sns_archive_common::RequestPolicy;
+
+use crate::auth::Signer;
+use crate::domains::Domains;
+
+/// Authorization of API requests
+pub(crate) const AUTH: &str = "Bearer mock-access-token";
+
+/// Refresh token that gets a new [`AUTH`]
+pub(crate) const REFRESH: &str = "mock-refresh-token";
+
+/// Secret API requests are signed with, found in `fixtures/web/static/main.js`
+pub(crate) const SECRET: &str = "0123456789abcdef0123456789abcdef01234567";
+
+/// Host of URLs in fixtures, replaced with the address of the server when served
+const FIXTURE_HOST: &str = "http://weverse.mock";
+
+pub(crate) struct MockServer {
+    server: TestServer,
+    /// Paths answered with an error the next time they are requested
+    failing: Arc<Mutex<HashSet<String>>>,
+}
+
+impl MockServer {
+    /// Serve fixtures on a free port until the test's runtime shuts down
+    pub(crate) async fn start() -> Self {
+        let failing = Arc::new(Mutex::new(HashSet::new()));
+        let server_failing = failing.clone();
+        let server = TestServer::start(move |request| {
+            match server_failing.lock().unwrap().remove(request.path()) {
+                true => error(StatusCode::INTERNAL_SERVER_ERROR),
+                false => respond(request),
+            }
+        })
+        .await;
+        Self { server, failing }
+    }
+
+    /// Address of the server without the trailing slash
+    fn base(&self) -> &str {
+        self.server.base().as_str().trim_end_matches('/')
+    }
+
+    /// URL of `path` on the server
+    pub(crate) fn url(&self, path: &str) -> Url {
+        self.server.url(path)
+    }
+
+    /// Answer the next request for `path` with `500 Internal Server Error`
+    pub(crate) fn fail_once(&self, path: &str) {
+        self.failing.lock().unwrap().insert(path.to_owned());
+    }
+
+    pub(crate) fn domains(&self) -> Domains {
+        Domains {
+            api: format!("{}/api", self.base()),
+            account: format!("{}/account", self.base()),
+            web: format!("{}/web/", self.base()),
+            vod: format!("{}/rmcnmv", self.base()),
+        }
+    }
+
+    /// Signer without a cache file
+    pub(crate) fn signer(&self) -> Signer {
+        Signer::new(self.domains(), None)
+    }
+
+    /// Number of requests received for `path`
+    pub(crate) fn requests(&self, path: &str) -> usize {
+        self.server.requests(path)
+    }
+
+    /// Client without rate limiting or retries
+    pub(crate) fn client(&self) -> ClientWithMiddleware {
+        sns_archive_common::http_client(RequestPolicy {
+            requests_per_second: 0.0,
+            max_retries: 0,
+            ..Default::default()
+        })
+    }
+}
+
+fn respond(request: &Request) -> Response {
+    let path = match fixture(request) {
+        Ok(path) => path,
+        Err(status) => return error(status),
+    };
+    let Ok(body) = std::fs::read(&path) else {
+        return Response::new(StatusCode::NOT_FOUND, error_body("not found"))
+            .header("Content-Type", "application/json");
+    };
+    // URLs in text fixtures point to this server
+    let body = match String::from_utf8(body) {
+        Ok(text) => text
+            .replace(FIXTURE_HOST, request.base.as_str().trim_end_matches('/'))
+            .into_bytes(),
+        Err(e) => e.into_bytes(),
+    };
+    Response::ranged(request, body).header("Content-Type", content_type(&path))
+}
+
+fn error(status: StatusCode) -> Response {
+    Response::new(status, error_body(&status.to_string()))
+        .header("Content-Type", "application/json")
+}
+
+/// Fixture answering `request`, or the status of a rejected request
+fn fixture(request: &Request) -> Result<PathBuf, StatusCode> {
+    let url = request.url();
+    let authorization = request.header("authorization");
+    if let Some(path) = request.target.strip_prefix("/api") {
+        if !is_signed(path) || authorization != Some(AUTH) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    } else if url.path().ends_with("/token/validate") && authorization != Some(AUTH) {
+        return Err(StatusCode::UNAUTHORIZED);
+    } else if url.path().ends_with("/token/refresh") {
+        let refresh = serde_json::from_slice::<serde_json::Value>(&request.body)
+            .ok()
+            .and_then(|body| body["refreshToken"].as_str().map(str::to_owned));
+        if refresh.as_deref() != Some(REFRESH) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    let mut path = url.path().to_owned();
+    if path.ends_with('/') {
+        path.push_str("index.html");
+    }
+    let has_extension = path.rsplit('/').next().unwrap_or_default().contains('.');
+    for (name, value) in url.query_pairs() {
+        if name == "after" || name == "keyword" {
+            path.push_str(&format!(".{}-{}", name, value.to_lowercase()));
+        }
+    }
+    if !has_extension {
+        path.push_str(".json");
+    }
+
+    Ok(PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures")
+        .join(path.trim_start_matches('/')))
+}
+
+/// Check the signature [`compute_url`](crate::auth::compute_url) added to an API request
+fn is_signed(target: &str) -> bool {
+    let Some((base_url, signature)) = target.rsplit_once("&wmsgpad=") else {
+        return false;
+    };
+    let Some((pad, _)) = signature.split_once("&wmd=") else {
+        return false;
+    };
+    let Ok(url) = Url::parse(&format!("http://localhost{}", target)) else {
+        return false;
+    };
+    let Some((_, digest)) = url.query_pairs().find(|(name, _)| name == "wmd") else {
+        return false;
+    };
+
+    let data: Vec<u8> = base_url.bytes().take(255).chain(pad.bytes()).collect();
+    let mut mac = Hmac::<Sha1>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(&data);
+    general_purpose::STANDARD.encode(mac.finalize().into_bytes()) == digest
+}
+
+fn content_type(path: &std::path::Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => "application/json",
+        Some("html") => "text/html",
+        Some("js") => "application/javascript",
+        Some("vtt") => "text/vtt",
+        Some("jpg") => "image/jpeg",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+fn error_body(message: &str) -> Vec<u8> {
+    format!("{{\"errorMessage\":\"{}\"}}", message).into_bytes()
+}
@@ -0,0 +1,131 @@
+//! Conversion between WebVTT and SubRip captions
+
+use anyhow::{anyhow, Result};
+
+/// A single caption cue
+#[derive(Debug, PartialEq, Eq)]
+struct Cue {
+    /// Start and end as `HH:MM:SS.mmm`
+    start: String,
+    end: String,
+    text: String,
+}
+
+/// Convert WebVTT captions to SubRip
+pub(crate) fn vtt_to_srt(vtt: &str) -> Result<String> {
+    let cues = parse(vtt)?;
+    let srt = cues
+        .iter()
+        .enumerate()
+        .map(|(i, c)| {
+            format!(
+                "{}\n{} --> {}\n{}\n",
+                i + 1,
+                c.start.replace('.', ","),
+                c.end.replace('.', ","),
+                c.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(srt)
+}
+
+/// Convert SubRip captions to WebVTT
+pub(crate) fn srt_to_vtt(srt: &str) -> Result<String> {
+    let cues = parse(srt)?;
+    let mut vtt = String::from("WEBVTT\n");
+    for c in cues {
+        vtt.push_str(&format!("\n{} --> {}\n{}\n", c.start, c.end, c.text));
+    }
+    Ok(vtt)
+}
+
+/// Parse the cues of WebVTT or SubRip captions, other WebVTT blocks are skipped
+fn parse(captions: &str) -> Result<Vec<Cue>> {
+    let captions = captions
+        .trim_start_matches('\u{feff}')
+        .replace("\r\n", "\n");
+    let mut cues = Vec::new();
+    for block in captions.split("\n\n") {
+        let mut lines = block.lines().skip_while(|l| l.trim().is_empty());
+        // Cue identifiers and SubRip numbers are optional, find the timing line
+        let Some(timing) = lines.by_ref().take(2).find(|l| l.contains("-->")) else {
+            continue;
+        };
+        let (start, end) = timing
+            .split_once("-->")
+            .ok_or_else(|| anyhow!("invalid cue timing {}", timing))?;
+        // WebVTT cue settings follow the end time
+        let end = end.split_whitespace().next().unwrap_or_default();
+        let text = lines.collect::<Vec<_>>().join("\n");
+        cues.push(Cue {
+            start: timestamp(start.trim())?,
+            end: timestamp(end)?,
+            text,
+        });
+    }
+    Ok(cues)
+}
+
+/// Normalize a timestamp to `HH:MM:SS.mmm`, hours are optional in WebVTT
+fn timestamp(ts: &str) -> Result<String> {
+    let ts = ts.replace(',', ".");
+    let (time, millis) = ts
+        .split_once('.')
+        .ok_or_else(|| anyhow!("invalid timestamp {}", ts))?;
+    let parts: Vec<u32> = time
+        .split(':')
+        .map(|p| p.parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| anyhow!("invalid timestamp {}", ts))?;
+    let (hours, minutes, seconds) = match parts[..] {
+        [h, m, s] => (h, m, s),
+        [m, s] => (0, m, s),
+        _ => return Err(anyhow!("invalid timestamp {}", ts)),
+    };
+    let millis: u32 = millis
+        .parse()
+        .map_err(|_| anyhow!("invalid timestamp {}", ts))?;
+    Ok(format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        hours, minutes, seconds, millis
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VTT: &str = "WEBVTT\n\
+        \n\
+        NOTE generated captions\n\
+        \n\
+        intro\n\
+        00:01.000 --> 00:03.500 align:center\n\
+        Hello\n\
+        everyone\n\
+        \n\
+        01:02:03.040 --> 01:02:05.000\n\
+        Bye\n";
+
+    const SRT: &str = "1\n\
+        00:00:01,000 --> 00:00:03,500\n\
+        Hello\n\
+        everyone\n\
+        \n\
+        2\n\
+        01:02:03,040 --> 01:02:05,000\n\
+        Bye\n";
+
+    #[test]
+    fn vtt_srt_roundtrip() {
+        assert_eq!(vtt_to_srt(VTT).unwrap(), SRT);
+        assert_eq!(vtt_to_srt(&srt_to_vtt(SRT).unwrap()).unwrap(), SRT);
+    }
+
+    #[test]
+    fn invalid_timestamp() {
+        assert!(vtt_to_srt("WEBVTT\n\n00:01 --> 00:02\nHello\n").is_err());
+    }
+}
@@ -1,6 +1,14 @@
+use std::path::Path;
+use std::sync::LazyLock;
+
 use anyhow::Result;
+use reqwest::Url;
+use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Deserializer};
+use sns_archive_common::streamed_download;
 use time::{format_description, OffsetDateTime, UtcOffset};
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::endpoint::member::Member;
@@ -23,15 +31,25 @@ pub(crate) fn slug(
     author: &Member,
     body: Option<&str>,
 ) -> Result<String> {
-    let time_str = {
-        let format = format_description::parse("[year][month][day]")?;
-        time.format(&format)?
-    };
     let username = if let Some(p) = &author.official_profile {
         &p.official_name
     } else {
         &author.profile_name
     };
+    named_slug(time, post_id, username, body)
+}
+
+/// Slug of a post that has no [`Member`] as author, e.g. notices written by the agency
+pub(crate) fn named_slug(
+    time: &OffsetDateTime,
+    post_id: &str,
+    username: &str,
+    body: Option<&str>,
+) -> Result<String> {
+    let time_str = {
+        let format = format_description::parse("[year][month][day]")?;
+        time.format(&format)?
+    };
     let slug = if let Some(b) = body {
         let body: String = UnicodeSegmentation::graphemes(b, true).take(50).collect();
         format!("{}-{}-{}-{}", time_str, post_id, username, body)
@@ -46,23 +64,36 @@ pub(crate) fn slug(
     Ok(sanitized_slug)
 }
 
-#[cfg(test)]
-use async_once_cell::OnceCell;
-
-#[cfg(test)]
-pub static LOGIN_INFO: OnceCell<String> = OnceCell::new();
-
-#[cfg(test)]
-pub async fn setup() -> String {
-    use dotenv::dotenv;
-    use reqwest::Client;
-
-    use crate::auth::{login, LoginInfo};
+/// Save an HTML body and the images embedded in it as `{slug}.html` and `{slug}-img01.jpg` etc.
+///
+/// Relative image URLs are resolved against `base`, the page the body is shown on.
+pub(crate) async fn download_html(
+    client: &ClientWithMiddleware,
+    base: &Url,
+    directory: &Path,
+    slug: &str,
+    body: &str,
+) -> Result<()> {
+    static IMG_SEL: LazyLock<scraper::Selector> =
+        LazyLock::new(|| scraper::Selector::parse("img").unwrap());
 
-    let _ = dotenv();
-    let email = std::env::var("WEVERSE_EMAIL").unwrap();
-    let login_info = LoginInfo::new(&email);
+    let mut file = fs::File::create(directory.join(format!("{}.html", slug))).await?;
+    file.write_all(body.as_bytes()).await?;
 
-    let client = Client::new();
-    login(&client, &login_info).await.unwrap()
+    let image_urls: Vec<_> = scraper::Html::parse_fragment(body)
+        .select(&IMG_SEL)
+        .filter_map(|img| img.attr("src"))
+        .map(|x| x.to_owned())
+        .collect();
+    for (i, image_url) in image_urls.iter().enumerate() {
+        let url = base.join(image_url)?;
+        let ext = url
+            .path()
+            .rsplit_once('.')
+            .map(|(_, ext)| ext)
+            .unwrap_or("jpg");
+        let filename = format!("{}-img{:02}.{}", slug, i + 1, ext);
+        streamed_download(client, url, directory.join(filename)).await?;
+    }
+    Ok(())
 }